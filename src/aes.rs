@@ -1,6 +1,16 @@
 /// Taken from: https://github.com/5n00py/soft-aes/blob/main/src/aes/aes_core.rs
 use const_for::const_for;
+use getrandom::getrandom;
 use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use crate::util::ct_eq;
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
+
+#[cfg(feature = "ct-aes")]
+use crate::util::{ct_gf256_inverse, ct_gf256_mul};
 
 pub const AES_BLOCK_SIZE: usize = 16;
 
@@ -8,6 +18,67 @@ pub const AES_128_KEY_SIZE: usize = 16;
 pub const AES_192_KEY_SIZE: usize = 24;
 pub const AES_256_KEY_SIZE: usize = 32;
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AesError {
+    /// The key wasn't 16, 24 or 32 bytes long.
+    InvalidKeyLength(usize),
+    /// The data wasn't a whole number of blocks, or was too short for the mode.
+    InvalidBlockLength(usize),
+    /// The output buffer was shorter than the input.
+    OutputTooSmall { expected: usize, actual: usize },
+    /// The PKCS#7 padding was malformed.
+    InvalidPadding,
+    /// The requested tag length isn't allowed by the mode.
+    InvalidTagLength(usize),
+    /// The nonce length isn't allowed by the mode.
+    InvalidNonceLength(usize),
+    /// The message is too long for the mode's length field.
+    MessageTooLong(usize),
+    /// The ciphertext is too short to hold the tag.
+    CiphertextTooShort(usize),
+    /// The two XTS keys have different lengths.
+    MismatchedKeyLengths(usize, usize),
+    /// The authentication tag didn't match.
+    AuthenticationFailed,
+}
+
+impl fmt::Display for AesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AesError::InvalidKeyLength(len) => write!(
+                f,
+                "Invalid key length. Expected 16, 24, or 32 bytes, got {} bytes",
+                len
+            ),
+            AesError::InvalidBlockLength(len) => {
+                write!(f, "Invalid data length, got {} bytes", len)
+            }
+            AesError::OutputTooSmall { expected, actual } => write!(
+                f,
+                "Output buffer too small. Expected at least {} bytes, got {} bytes",
+                expected, actual
+            ),
+            AesError::InvalidPadding => write!(f, "Invalid padding"),
+            AesError::InvalidTagLength(len) => write!(f, "Invalid tag length, got {} bytes", len),
+            AesError::InvalidNonceLength(len) => {
+                write!(f, "Invalid nonce length, got {} bytes", len)
+            }
+            AesError::MessageTooLong(len) => {
+                write!(f, "Message too long for the nonce, got {} bytes", len)
+            }
+            AesError::CiphertextTooShort(len) => {
+                write!(f, "Ciphertext too short to hold the tag, got {} bytes", len)
+            }
+            AesError::MismatchedKeyLengths(a, b) => {
+                write!(f, "Mismatched key lengths, got {} and {} bytes", a, b)
+            }
+            AesError::AuthenticationFailed => write!(f, "Authentication failed"),
+        }
+    }
+}
+
+impl Error for AesError {}
+
 const COL_SIZE: usize = 4;
 const ROW_SIZE: usize = 4;
 pub type AesBlock = [[u8; COL_SIZE]; ROW_SIZE];
@@ -61,6 +132,7 @@ const fn sub_byte(a: u8) -> u8 {
     affine_transform(a)
 }
 
+#[cfg_attr(feature = "ct-aes", allow(dead_code))]
 const S_BOX: [u8; 256] = {
     let mut result: [u8; 256] = [0; 256];
 
@@ -85,6 +157,7 @@ const fn inverse_affine_transform(c: u8) -> u8 {
     x
 }
 
+#[cfg_attr(feature = "ct-aes", allow(dead_code))]
 const INV_S_BOX: [u8; 256] = {
     let mut result = [0; 256];
     const_for!(i in 0..256 => {
@@ -93,6 +166,24 @@ const INV_S_BOX: [u8; 256] = {
     result
 };
 
+// With the `t-tables` feature, encryption rounds fuse SubBytes, ShiftRows and MixColumns into four
+// lookups per column. `T_TABLES[0][x]` is the column (2·S(x), S(x), S(x), 3·S(x)) as a big-endian
+// word, and each following table is the previous one rotated by a byte. The lookups are indexed
+// by the state, so this leaks through cache timing like the plain S-box, and isn't used with
+// `ct-aes`.
+#[cfg(all(feature = "t-tables", not(feature = "ct-aes")))]
+const T_TABLES: [[u32; 256]; 4] = {
+    let mut tables = [[0; 256]; 4];
+    const_for!(x in 0..256 => {
+        let s = S_BOX[x];
+        let word = u32::from_be_bytes([multiply_gf(s, 2), s, s, multiply_gf(s, 3)]);
+        const_for!(i in 0..4 => {
+            tables[i][x] = word.rotate_right(8 * i as u32);
+        });
+    });
+    tables
+};
+
 const fn rc(i: u8) -> u8 {
     if i == 0x00 {
         return 0x8D;
@@ -121,6 +212,7 @@ const RCON: [u8; 255] = {
     res
 };
 
+#[cfg_attr(feature = "ct-aes", allow(dead_code))]
 const LOG_TABLE: [u8; 256] = [
     0x00, 0x00, 0x19, 0x01, 0x32, 0x02, 0x1a, 0xc6, 0x4b, 0xc7, 0x1b, 0x68, 0x33, 0xee, 0xdf, 0x03,
     0x64, 0x04, 0xe0, 0x0e, 0x34, 0x8d, 0x81, 0xef, 0x4c, 0x71, 0x08, 0xc8, 0xf8, 0x69, 0x1c, 0xc1,
@@ -140,6 +232,7 @@ const LOG_TABLE: [u8; 256] = [
     0x67, 0x4a, 0xed, 0xde, 0xc5, 0x31, 0xfe, 0x18, 0x0d, 0x63, 0x8c, 0x80, 0xc0, 0xf7, 0x70, 0x07,
 ];
 
+#[cfg_attr(feature = "ct-aes", allow(dead_code))]
 const ALOG_TABLE: [u8; 256] = [
     0x01, 0x03, 0x05, 0x0f, 0x11, 0x33, 0x55, 0xff, 0x1a, 0x2e, 0x72, 0x96, 0xa1, 0xf8, 0x13, 0x35,
     0x5f, 0xe1, 0x38, 0x48, 0xd8, 0x73, 0x95, 0xa4, 0xf7, 0x02, 0x06, 0x0a, 0x1e, 0x22, 0x66, 0xaa,
@@ -159,6 +252,40 @@ const ALOG_TABLE: [u8; 256] = [
     0x39, 0x4b, 0xdd, 0x7c, 0x84, 0x97, 0xa2, 0xfd, 0x1c, 0x24, 0x6c, 0xb4, 0xc7, 0x52, 0xf6, 0x01,
 ];
 
+// With the `ct-aes` feature, the S-box is computed with arithmetic instead of looked up: the
+// inverse in GF(2^8) by constant-time square-and-multiply, followed by the affine transform. With
+// no secret-indexed memory accesses, cache timing doesn't leak the key. Multiplication also avoids
+// the log tables. This is several times slower.
+#[cfg(feature = "ct-aes")]
+fn s_box(x: u8) -> u8 {
+    let x = ct_gf256_inverse(x, 0x1b);
+    x ^ x.rotate_left(1) ^ x.rotate_left(2) ^ x.rotate_left(3) ^ x.rotate_left(4) ^ 0x63
+}
+
+#[cfg(not(feature = "ct-aes"))]
+fn s_box(x: u8) -> u8 {
+    S_BOX[x as usize]
+}
+
+#[cfg(feature = "ct-aes")]
+fn inv_s_box(x: u8) -> u8 {
+    ct_gf256_inverse(
+        x.rotate_left(1) ^ x.rotate_left(3) ^ x.rotate_left(6) ^ 0x05,
+        0x1b,
+    )
+}
+
+#[cfg(not(feature = "ct-aes"))]
+fn inv_s_box(x: u8) -> u8 {
+    INV_S_BOX[x as usize]
+}
+
+#[cfg(feature = "ct-aes")]
+fn mul(a: u8, b: u8) -> u8 {
+    ct_gf256_mul(a, b, 0x1b)
+}
+
+#[cfg(not(feature = "ct-aes"))]
 fn mul(a: u8, b: u8) -> u8 {
     if a != 0 && b != 0 {
         let log_a = LOG_TABLE[a as usize] as usize;
@@ -170,8 +297,9 @@ fn mul(a: u8, b: u8) -> u8 {
     }
 }
 
-fn expand_key(key: &[u8], nk: usize, nr: usize) -> [u8; 240] {
-    let mut expanded_key = [0u8; 240]; // Fixed buffer for expanded key
+// Writes the schedule into `expanded_key` rather than returning it, so no copy of the round keys
+// is left behind in this stack frame.
+fn expand_key_into(key: &[u8], nk: usize, nr: usize, expanded_key: &mut [u8; 240]) {
     let mut temp = [0u8; 4]; // Temporary storage for key schedule
 
     // Copy the initial key as the first round key
@@ -194,16 +322,16 @@ fn expand_key(key: &[u8], nk: usize, nr: usize) -> [u8; 240] {
             temp[3] = k;
 
             // SubWord operation: Substitute each byte in `temp` using the S-Box
-            for j in 0..4 {
-                temp[j] = S_BOX[temp[j] as usize];
+            for t in temp.iter_mut() {
+                *t = s_box(*t);
             }
 
             // XOR the first byte of `temp` with the round constant (RCON)
             temp[0] ^= RCON[i / nk];
         } else if nk > 6 && i % nk == 4 {
             // For AES-256, apply SubWord operation every fourth word
-            for j in 0..4 {
-                temp[j] = S_BOX[temp[j] as usize];
+            for t in temp.iter_mut() {
+                *t = s_box(*t);
             }
         }
 
@@ -213,7 +341,49 @@ fn expand_key(key: &[u8], nk: usize, nr: usize) -> [u8; 240] {
         }
         i += 1;
     }
-    expanded_key
+
+    #[cfg(feature = "zeroize")]
+    temp.zeroize();
+}
+
+/// An expanded AES key schedule.
+///
+/// Round `r`'s key, for `r` in `0..=rounds`, is `data[16 * r..16 * (r + 1)]`, in the same byte
+/// order as a block: bytes `4 * c..4 * c + 4` are column `c` of the round key, the word `w[4r + c]`
+/// in FIPS 197. Round 0's key is XORed in before the first round. The first `nk` words are the key
+/// itself. Schedules for shorter keys leave the end of `data` zeroed.
+#[derive(Clone)]
+pub struct RoundKeys {
+    pub data: [u8; 240],
+    pub rounds: usize,
+}
+
+impl RoundKeys {
+    /// The key for round `round`, which must be at most `rounds`.
+    pub fn round_key(&self, round: usize) -> [u8; AES_BLOCK_SIZE] {
+        assert!(round <= self.rounds, "round {} out of range", round);
+        self.data[round * 16..(round + 1) * 16].try_into().unwrap()
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for RoundKeys {
+    fn drop(&mut self) {
+        self.data.zeroize();
+    }
+}
+
+/// Expands a 128, 192 or 256-bit key into the round keys used by each round of the cipher.
+pub fn expand_key(key: &[u8]) -> Result<RoundKeys, AesError> {
+    validate_key_len(key.len())?;
+    let (nk, nr) = calculate_parameters(key.len());
+
+    let mut round_keys = RoundKeys {
+        data: [0; 240],
+        rounds: nr,
+    };
+    expand_key_into(key, nk, nr, &mut round_keys.data);
+    Ok(round_keys)
 }
 
 fn add_round_key(round: usize, state: &mut AesBlock, expanded_key: &[u8; 240]) {
@@ -225,17 +395,17 @@ fn add_round_key(round: usize, state: &mut AesBlock, expanded_key: &[u8; 240]) {
 }
 
 fn sub_bytes(state: &mut AesBlock) {
-    for i in 0..4 {
-        for j in 0..4 {
-            state[i][j] = S_BOX[state[i][j] as usize];
+    for row in state.iter_mut() {
+        for b in row.iter_mut() {
+            *b = s_box(*b);
         }
     }
 }
 
 fn inv_sub_bytes(state: &mut AesBlock) {
-    for i in 0..4 {
-        for j in 0..4 {
-            state[i][j] = INV_S_BOX[state[i][j] as usize];
+    for row in state.iter_mut() {
+        for b in row.iter_mut() {
+            *b = inv_s_box(*b);
         }
     }
 }
@@ -328,125 +498,2451 @@ fn calculate_parameters(key_length_bytes: usize) -> (usize, usize) {
     (words_in_key, encryption_rounds)
 }
 
-fn validate_key_len(key_len: usize) -> Result<(), Box<dyn Error>> {
+fn validate_key_len(key_len: usize) -> Result<(), AesError> {
     match key_len {
         AES_128_KEY_SIZE | AES_192_KEY_SIZE | AES_256_KEY_SIZE => Ok(()),
-        _ => Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            format!(
-                "AES CORE ERROR: Invalid key length. Expected 16, 24, or 32 bytes, got {} bytes",
-                key_len,
-            ),
-        ))),
+        _ => Err(AesError::InvalidKeyLength(key_len)),
     }
 }
 
-pub fn aes_enc_block(
-    block: &[u8; AES_BLOCK_SIZE],
-    key: &[u8],
-) -> Result<[u8; AES_BLOCK_SIZE], Box<dyn Error>> {
-    let key_len = key.len();
+/// An AES key with its key schedule expanded once up front, so encrypting or decrypting many
+/// blocks under the same key doesn't redo the expansion for every block.
+#[derive(Clone)]
+pub struct Aes {
+    expanded_key: [u8; 240],
+    // Round keys for the equivalent inverse cipher, if `new_for_decrypt` precomputed them
+    decryption_keys: Option<[u8; 240]>,
+    nk: usize,
+    nr: usize,
+}
+
+impl Aes {
+    pub fn new(key: &[u8]) -> Result<Self, AesError> {
+        validate_key_len(key.len())?;
+        Ok(Self::expand(key))
+    }
+
+    // The key length must already be valid
+    fn expand(key: &[u8]) -> Self {
+        let (nk, nr) = calculate_parameters(key.len());
+        let mut aes = Self {
+            expanded_key: [0; 240],
+            decryption_keys: None,
+            nk,
+            nr,
+        };
+        expand_key_into(key, nk, nr, &mut aes.expanded_key);
+        aes
+    }
 
-    validate_key_len(key_len)?;
+    /// Like `new`, but also precomputes the round keys for FIPS 197's equivalent inverse cipher,
+    /// for keys that will decrypt many blocks.
+    ///
+    /// Because InvMixColumns is linear, it can be moved before AddRoundKey in each decryption round
+    /// if the round key goes through InvMixColumns too. Doing that to the middle round keys once up
+    /// front gives decryption the same SubBytes, ShiftRows, MixColumns, AddRoundKey structure as
+    /// encryption, and with the `aes-ni` feature it saves transforming the keys on every block.
+    pub fn new_for_decrypt(key: &[u8]) -> Result<Self, AesError> {
+        let mut aes = Self::new(key)?;
+
+        let mut decryption_keys = aes.expanded_key;
+        for round in 1..aes.nr {
+            let round_key: &mut [u8; AES_BLOCK_SIZE] = (&mut decryption_keys
+                [round * 16..(round + 1) * 16])
+                .try_into()
+                .unwrap();
+            let mut state = copy_block_to_state(round_key);
+            inv_mix_columns(&mut state);
+            *round_key = copy_state_to_block(&state);
+        }
+
+        aes.decryption_keys = Some(decryption_keys);
+        Ok(aes)
+    }
 
-    let (nk, nr) = calculate_parameters(key_len);
+    /// The number of 32-bit words in the key, 4, 6 or 8.
+    pub fn nk(&self) -> usize {
+        self.nk
+    }
 
-    let mut state = copy_block_to_state(block);
+    /// The number of rounds, 10, 12 or 14.
+    pub fn nr(&self) -> usize {
+        self.nr
+    }
 
-    let expanded_key = expand_key(key, nk, nr);
+    pub fn encrypt_block(&self, block: &[u8; AES_BLOCK_SIZE]) -> [u8; AES_BLOCK_SIZE] {
+        let mut block = *block;
+        self.encrypt_block_in_place(&mut block);
+        block
+    }
 
-    // Add the first round key to the state before starting the rounds
-    add_round_key(0, &mut state, &expanded_key);
+    pub fn decrypt_block(&self, ciphertext: &[u8; AES_BLOCK_SIZE]) -> [u8; AES_BLOCK_SIZE] {
+        let mut block = *ciphertext;
+        self.decrypt_block_in_place(&mut block);
+        block
+    }
 
-    // Main rounds
-    for round in 1..nr {
+    /// Encrypts `block`, writing the ciphertext back over it.
+    pub fn encrypt_block_in_place(&self, block: &mut [u8; AES_BLOCK_SIZE]) {
+        #[cfg(all(feature = "aes-ni", target_arch = "x86_64"))]
+        if is_x86_feature_detected!("aes") {
+            // Safety: the CPU supports the AES instructions
+            unsafe { aes_ni::encrypt_block(&self.expanded_key, self.nr, block) };
+            return;
+        }
+
+        #[cfg(all(feature = "t-tables", not(feature = "ct-aes")))]
+        t_table_encrypt(&self.expanded_key, self.nr, block);
+        #[cfg(not(all(feature = "t-tables", not(feature = "ct-aes"))))]
+        self.encrypt_block_software(block);
+    }
+
+    /// Decrypts `block`, writing the plaintext back over it.
+    pub fn decrypt_block_in_place(&self, block: &mut [u8; AES_BLOCK_SIZE]) {
+        #[cfg(all(feature = "aes-ni", target_arch = "x86_64"))]
+        if is_x86_feature_detected!("aes") {
+            // Safety: the CPU supports the AES instructions
+            unsafe {
+                aes_ni::decrypt_block(
+                    &self.expanded_key,
+                    self.decryption_keys.as_ref(),
+                    self.nr,
+                    block,
+                )
+            };
+            return;
+        }
+
+        self.decrypt_block_software(block);
+    }
+
+    #[cfg_attr(all(feature = "t-tables", not(feature = "ct-aes")), allow(dead_code))]
+    fn encrypt_block_software(&self, block: &mut [u8; AES_BLOCK_SIZE]) {
+        let nr = self.nr;
+        let expanded_key = &self.expanded_key;
+
+        let mut state = copy_block_to_state(block);
+
+        // Add the first round key to the state before starting the rounds
+        add_round_key(0, &mut state, expanded_key);
+
+        // Main rounds
+        for round in 1..nr {
+            sub_bytes(&mut state);
+            shift_rows(&mut state);
+            mix_columns(&mut state);
+            add_round_key(round, &mut state, expanded_key);
+        }
+
+        // Final round (without mix_columns)
         sub_bytes(&mut state);
         shift_rows(&mut state);
-        mix_columns(&mut state);
-        add_round_key(round, &mut state, &expanded_key);
+        add_round_key(nr, &mut state, expanded_key);
+
+        *block = copy_state_to_block(&state);
+
+        #[cfg(feature = "zeroize")]
+        state.zeroize();
+    }
+
+    fn decrypt_block_software(&self, block: &mut [u8; AES_BLOCK_SIZE]) {
+        if let Some(decryption_keys) = &self.decryption_keys {
+            self.decrypt_block_equivalent(decryption_keys, block);
+            return;
+        }
+
+        let nr = self.nr;
+        let expanded_key = &self.expanded_key;
+
+        let mut state = copy_block_to_state(block);
+
+        // Add the last round key to the state before starting the rounds
+        add_round_key(nr, &mut state, expanded_key);
+
+        // Main rounds
+        for round in (1..nr).rev() {
+            inv_shift_rows(&mut state);
+            inv_sub_bytes(&mut state);
+            add_round_key(round, &mut state, expanded_key);
+            inv_mix_columns(&mut state);
+        }
+
+        // Final round (without inv_mix_columns)
+        inv_shift_rows(&mut state);
+        inv_sub_bytes(&mut state);
+        add_round_key(0, &mut state, expanded_key);
+
+        *block = copy_state_to_block(&state);
+
+        #[cfg(feature = "zeroize")]
+        state.zeroize();
+    }
+
+    fn decrypt_block_equivalent(
+        &self,
+        decryption_keys: &[u8; 240],
+        block: &mut [u8; AES_BLOCK_SIZE],
+    ) {
+        let nr = self.nr;
+
+        let mut state = copy_block_to_state(block);
+
+        add_round_key(nr, &mut state, decryption_keys);
+
+        // The same order of steps as encryption, with the inverse of each
+        for round in (1..nr).rev() {
+            inv_sub_bytes(&mut state);
+            inv_shift_rows(&mut state);
+            inv_mix_columns(&mut state);
+            add_round_key(round, &mut state, decryption_keys);
+        }
+
+        inv_sub_bytes(&mut state);
+        inv_shift_rows(&mut state);
+        add_round_key(0, &mut state, decryption_keys);
+
+        *block = copy_state_to_block(&state);
+
+        #[cfg(feature = "zeroize")]
+        state.zeroize();
+    }
+}
+
+#[cfg(all(feature = "t-tables", not(feature = "ct-aes")))]
+fn t_table_encrypt(expanded_key: &[u8; 240], nr: usize, block: &mut [u8; AES_BLOCK_SIZE]) {
+    // the state and round keys as four big-endian column words
+    let word = |bytes: &[u8]| u32::from_be_bytes(bytes[..4].try_into().unwrap());
+    let round_key = |round: usize, c: usize| word(&expanded_key[round * 16 + c * 4..]);
+
+    let mut state = [0u32; 4];
+    for (c, s) in state.iter_mut().enumerate() {
+        *s = word(&block[c * 4..]) ^ round_key(0, c);
+    }
+
+    // row r of column c comes from column c + r, which is ShiftRows
+    for round in 1..nr {
+        let mut next = [0u32; 4];
+        for (c, n) in next.iter_mut().enumerate() {
+            *n = T_TABLES[0][(state[c] >> 24) as usize]
+                ^ T_TABLES[1][(state[(c + 1) % 4] >> 16) as u8 as usize]
+                ^ T_TABLES[2][(state[(c + 2) % 4] >> 8) as u8 as usize]
+                ^ T_TABLES[3][state[(c + 3) % 4] as u8 as usize]
+                ^ round_key(round, c);
+        }
+        state = next;
+    }
+
+    // the final round has no MixColumns
+    for c in 0..4 {
+        let column = u32::from_be_bytes([
+            S_BOX[(state[c] >> 24) as usize],
+            S_BOX[(state[(c + 1) % 4] >> 16) as u8 as usize],
+            S_BOX[(state[(c + 2) % 4] >> 8) as u8 as usize],
+            S_BOX[state[(c + 3) % 4] as u8 as usize],
+        ]) ^ round_key(nr, c);
+        block[c * 4..c * 4 + 4].copy_from_slice(&column.to_be_bytes());
+    }
+}
+
+// With the `aes-ni` feature, blocks are encrypted with the x86-64 AES instructions when the CPU
+// has them. Each instruction does a whole round, using the same round keys as the software path.
+#[cfg(all(feature = "aes-ni", target_arch = "x86_64"))]
+mod aes_ni {
+    use super::AES_BLOCK_SIZE;
+    use std::arch::x86_64::*;
+
+    #[target_feature(enable = "aes")]
+    unsafe fn round_key(expanded_key: &[u8; 240], round: usize) -> __m128i {
+        _mm_loadu_si128(expanded_key[round * 16..].as_ptr() as *const __m128i)
+    }
+
+    #[target_feature(enable = "aes")]
+    pub unsafe fn encrypt_block(
+        expanded_key: &[u8; 240],
+        nr: usize,
+        block: &mut [u8; AES_BLOCK_SIZE],
+    ) {
+        let mut state = _mm_loadu_si128(block.as_ptr() as *const __m128i);
+
+        state = _mm_xor_si128(state, round_key(expanded_key, 0));
+        for round in 1..nr {
+            state = _mm_aesenc_si128(state, round_key(expanded_key, round));
+        }
+        state = _mm_aesenclast_si128(state, round_key(expanded_key, nr));
+
+        _mm_storeu_si128(block.as_mut_ptr() as *mut __m128i, state);
+    }
+
+    // `aesdec` implements the equivalent inverse cipher, which applies InvMixColumns before adding
+    // the round key, so the middle round keys need InvMixColumns applied to them too, unless
+    // `decryption_keys` already has.
+    #[target_feature(enable = "aes")]
+    pub unsafe fn decrypt_block(
+        expanded_key: &[u8; 240],
+        decryption_keys: Option<&[u8; 240]>,
+        nr: usize,
+        block: &mut [u8; AES_BLOCK_SIZE],
+    ) {
+        let mut state = _mm_loadu_si128(block.as_ptr() as *const __m128i);
+
+        state = _mm_xor_si128(state, round_key(expanded_key, nr));
+        for round in (1..nr).rev() {
+            let key = match decryption_keys {
+                Some(decryption_keys) => round_key(decryption_keys, round),
+                None => _mm_aesimc_si128(round_key(expanded_key, round)),
+            };
+            state = _mm_aesdec_si128(state, key);
+        }
+        state = _mm_aesdeclast_si128(state, round_key(expanded_key, 0));
+
+        _mm_storeu_si128(block.as_mut_ptr() as *mut __m128i, state);
+    }
+}
+
+/// With the `zeroize` feature, the key schedule is overwritten with zeros when an `Aes` is
+/// dropped, so round keys don't linger in freed memory.
+#[cfg(feature = "zeroize")]
+impl Drop for Aes {
+    fn drop(&mut self) {
+        self.expanded_key.zeroize();
+        if let Some(decryption_keys) = &mut self.decryption_keys {
+            decryption_keys.zeroize();
+        }
     }
+}
+
+// Typed wrappers around `Aes` for when the key size is known at compile time. The key length is
+// checked by the type system, so construction can't fail.
+macro_rules! aes_with_key_size {
+    ($(#[$doc:meta])* $name:ident, $key_size:expr) => {
+        $(#[$doc])*
+        #[derive(Clone)]
+        pub struct $name(Aes);
+
+        impl $name {
+            pub fn new(key: &[u8; $key_size]) -> Self {
+                Self(Aes::expand(key))
+            }
+
+            pub fn encrypt_block(&self, block: &[u8; AES_BLOCK_SIZE]) -> [u8; AES_BLOCK_SIZE] {
+                self.0.encrypt_block(block)
+            }
+
+            pub fn decrypt_block(&self, block: &[u8; AES_BLOCK_SIZE]) -> [u8; AES_BLOCK_SIZE] {
+                self.0.decrypt_block(block)
+            }
+
+            pub fn encrypt_block_in_place(&self, block: &mut [u8; AES_BLOCK_SIZE]) {
+                self.0.encrypt_block_in_place(block)
+            }
+
+            pub fn decrypt_block_in_place(&self, block: &mut [u8; AES_BLOCK_SIZE]) {
+                self.0.decrypt_block_in_place(block)
+            }
+        }
+
+        impl From<$name> for Aes {
+            fn from(aes: $name) -> Aes {
+                aes.0
+            }
+        }
+    };
+}
+
+aes_with_key_size!(
+    /// AES with a 128-bit key.
+    Aes128,
+    AES_128_KEY_SIZE
+);
+aes_with_key_size!(
+    /// AES with a 192-bit key.
+    Aes192,
+    AES_192_KEY_SIZE
+);
+aes_with_key_size!(
+    /// AES with a 256-bit key.
+    Aes256,
+    AES_256_KEY_SIZE
+);
+
+// Panics if the OS random number generator fails, which only happens on misconfigured systems.
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0; N];
+    getrandom(&mut bytes).expect("OS random number generator failed");
+    bytes
+}
+
+/// Generates a random 128-bit key from the OS random number generator.
+///
+/// # Panics
+///
+/// Panics if the OS random number generator fails.
+pub fn generate_key_128() -> [u8; AES_128_KEY_SIZE] {
+    random_bytes()
+}
+
+/// Generates a random 256-bit key from the OS random number generator.
+///
+/// # Panics
+///
+/// Panics if the OS random number generator fails.
+pub fn generate_key_256() -> [u8; AES_256_KEY_SIZE] {
+    random_bytes()
+}
 
-    // Final round (without mix_columns)
-    sub_bytes(&mut state);
-    shift_rows(&mut state);
-    add_round_key(nr, &mut state, &expanded_key);
+/// Generates a random IV for CBC, CFB or OFB, or an initial counter block for CTR. CBC needs an
+/// unpredictable IV for every message, which a counter doesn't give.
+///
+/// # Panics
+///
+/// Panics if the OS random number generator fails.
+pub fn generate_iv() -> [u8; AES_BLOCK_SIZE] {
+    random_bytes()
+}
 
-    Ok(copy_state_to_block(&state))
+pub fn aes_enc_block(
+    block: &[u8; AES_BLOCK_SIZE],
+    key: &[u8],
+) -> Result<[u8; AES_BLOCK_SIZE], AesError> {
+    Ok(Aes::new(key)?.encrypt_block(block))
 }
 
 pub fn aes_dec_block(
     ciphertext: &[u8; AES_BLOCK_SIZE],
     key: &[u8],
-) -> Result<[u8; AES_BLOCK_SIZE], Box<dyn Error>> {
-    let key_len = key.len();
+) -> Result<[u8; AES_BLOCK_SIZE], AesError> {
+    Ok(Aes::new(key)?.decrypt_block(ciphertext))
+}
+
+/// Like `aes_enc_block`, but writes the ciphertext back over `block` instead of returning a new
+/// array.
+pub fn aes_enc_block_in_place(
+    block: &mut [u8; AES_BLOCK_SIZE],
+    key: &[u8],
+) -> Result<(), AesError> {
+    Aes::new(key)?.encrypt_block_in_place(block);
+    Ok(())
+}
+
+/// Like `aes_dec_block`, but writes the plaintext back over `block` instead of returning a new
+/// array.
+pub fn aes_dec_block_in_place(
+    block: &mut [u8; AES_BLOCK_SIZE],
+    key: &[u8],
+) -> Result<(), AesError> {
+    Aes::new(key)?.decrypt_block_in_place(block);
+    Ok(())
+}
 
-    validate_key_len(key_len)?;
+fn validate_output_len(input_len: usize, output_len: usize) -> Result<(), AesError> {
+    if output_len < input_len {
+        return Err(AesError::OutputTooSmall {
+            expected: input_len,
+            actual: output_len,
+        });
+    }
+    Ok(())
+}
 
-    let (nk, nr) = calculate_parameters(key_len);
+fn validate_block_aligned(len: usize) -> Result<(), AesError> {
+    if !len.is_multiple_of(AES_BLOCK_SIZE) {
+        return Err(AesError::InvalidBlockLength(len));
+    }
+    Ok(())
+}
 
-    let mut state = copy_block_to_state(ciphertext);
+/// Encrypts every block of `data` in place in electronic codebook (ECB) fashion, expanding the
+/// key only once. `data` must be a multiple of the block size.
+///
+/// ECB on its own leaks which plaintext blocks are equal, so this is meant as a building block
+/// for other modes rather than for encrypting messages directly.
+pub fn aes_enc_blocks(data: &mut [u8], key: &[u8]) -> Result<(), AesError> {
+    let aes = Aes::new(key)?;
+    validate_block_aligned(data.len())?;
+
+    for chunk in data.chunks_exact_mut(AES_BLOCK_SIZE) {
+        let block: &mut [u8; AES_BLOCK_SIZE] = chunk.try_into().unwrap();
+        aes.encrypt_block_in_place(block);
+    }
 
-    let expanded_key = expand_key(key, nk, nr);
+    Ok(())
+}
 
-    // Add the last round key to the state before starting the rounds
-    add_round_key(nr, &mut state, &expanded_key);
+/// Decrypts every block of `data` in place, the inverse of `aes_enc_blocks`.
+pub fn aes_dec_blocks(data: &mut [u8], key: &[u8]) -> Result<(), AesError> {
+    let aes = Aes::new(key)?;
+    validate_block_aligned(data.len())?;
 
-    // Main rounds
-    for round in (1..nr).rev() {
-        inv_shift_rows(&mut state);
-        inv_sub_bytes(&mut state);
-        add_round_key(round, &mut state, &expanded_key);
-        inv_mix_columns(&mut state);
+    for chunk in data.chunks_exact_mut(AES_BLOCK_SIZE) {
+        let block: &mut [u8; AES_BLOCK_SIZE] = chunk.try_into().unwrap();
+        aes.decrypt_block_in_place(block);
     }
 
-    // Final round (without inv_mix_columns)
-    inv_shift_rows(&mut state);
-    inv_sub_bytes(&mut state);
-    add_round_key(0, &mut state, &expanded_key);
+    Ok(())
+}
 
-    Ok(copy_state_to_block(&state))
+/// Encrypts or decrypts `data` in counter (CTR) mode. The keystream is made by encrypting
+/// successive counter blocks, starting at `nonce`, and XORed with the data, so the same function
+/// both encrypts and decrypts. The whole 128-bit block is incremented as a big-endian integer,
+/// wrapping around on overflow, as in NIST SP 800-38A. The final keystream block is truncated to
+/// the length of the data, so no padding is needed.
+///
+/// A nonce must never be reused with the same key.
+pub fn aes_ctr(data: &[u8], key: &[u8], nonce: &[u8; AES_BLOCK_SIZE]) -> Result<Vec<u8>, AesError> {
+    let mut output = vec![0; data.len()];
+    aes_ctr_xor_into(data, &mut output, key, nonce)?;
+    Ok(output)
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use quickcheck_macros::quickcheck;
+/// Like `aes_ctr`, but writes the result into `output` instead of allocating. `output` must be at
+/// least as long as `input`; any bytes past `input.len()` are left untouched.
+pub fn aes_ctr_xor_into(
+    input: &[u8],
+    output: &mut [u8],
+    key: &[u8],
+    nonce: &[u8; AES_BLOCK_SIZE],
+) -> Result<(), AesError> {
+    let aes = Aes::new(key)?;
+    validate_output_len(input.len(), output.len())?;
 
-    #[test]
-    fn ex() {
-        // Test vectors for AES-128
-        let plaintext: [u8; AES_BLOCK_SIZE] = [
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00,
-        ];
-        let key: [u8; AES_128_KEY_SIZE] = [
-            0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd,
-            0xee, 0xff,
-        ];
-        let expected_ciphertext: [u8; AES_BLOCK_SIZE] = [
-            0xfd, 0xe4, 0xfb, 0xae, 0x4a, 0x09, 0xe0, 0x20, 0xef, 0xf7, 0x22, 0x96, 0x9f, 0x83,
-            0x83, 0x2b,
-        ];
+    ctr_xor(&aes, input, output, nonce);
+    Ok(())
+}
 
-        // Perform AES-128 encryption
-        let ciphertext = aes_enc_block(&plaintext, &key).expect("Encryption failed");
-        assert_eq!(ciphertext, expected_ciphertext);
+fn ctr_xor(aes: &Aes, input: &[u8], output: &mut [u8], nonce: &[u8; AES_BLOCK_SIZE]) {
+    let mut counter = u128::from_be_bytes(*nonce);
 
-        // Perform AES-128 decryption
-        let decrypted = aes_dec_block(&ciphertext, &key).expect("Decryption failed");
-        assert_eq!(decrypted, plaintext);
+    for (chunk, out) in input
+        .chunks(AES_BLOCK_SIZE)
+        .zip(output.chunks_mut(AES_BLOCK_SIZE))
+    {
+        let keystream = aes.encrypt_block(&counter.to_be_bytes());
+        for ((o, d), k) in out.iter_mut().zip(chunk).zip(keystream) {
+            *o = d ^ k;
+        }
+        counter = counter.wrapping_add(1);
     }
+}
 
-    #[quickcheck]
-    fn enc_and_dec(plaintext: Vec<u8>, key: Vec<u8>) -> bool {
-        // we need enough bytes to generate the plaintext and key
-        if plaintext.len() < 16 || key.len() < 16 {
-            return true;
+// 64 KiB per rayon task, so each one does enough work to outweigh the scheduling
+#[cfg(feature = "parallel")]
+const PARALLEL_CTR_CHUNK_BLOCKS: usize = 4096;
+
+/// Like `aes_ctr`, but splits `data` into chunks that are encrypted in parallel with rayon. Each
+/// keystream block only depends on its counter, so every chunk starts from the nonce plus the
+/// number of blocks before it, and the output is identical to `aes_ctr`.
+#[cfg(feature = "parallel")]
+pub fn aes_ctr_par(
+    data: &[u8],
+    key: &[u8],
+    nonce: &[u8; AES_BLOCK_SIZE],
+) -> Result<Vec<u8>, AesError> {
+    use rayon::prelude::*;
+
+    let aes = Aes::new(key)?;
+    let mut output = vec![0; data.len()];
+    let chunk_size = PARALLEL_CTR_CHUNK_BLOCKS * AES_BLOCK_SIZE;
+
+    data.par_chunks(chunk_size)
+        .zip(output.par_chunks_mut(chunk_size))
+        .enumerate()
+        .for_each(|(i, (input, output))| {
+            let counter =
+                u128::from_be_bytes(*nonce).wrapping_add((i * PARALLEL_CTR_CHUNK_BLOCKS) as u128);
+            ctr_xor(&aes, input, output, &counter.to_be_bytes());
+        });
+
+    Ok(output)
+}
+
+/// Encrypts `data` in cipher block chaining (CBC) mode. Each plaintext block is XORed with the
+/// previous ciphertext block, starting with the IV, before being encrypted. `data` must be a
+/// multiple of the block size; padding is left to the caller.
+pub fn aes_cbc_encrypt(
+    data: &[u8],
+    key: &[u8],
+    iv: &[u8; AES_BLOCK_SIZE],
+) -> Result<Vec<u8>, AesError> {
+    let mut output = vec![0; data.len()];
+    aes_cbc_encrypt_into(data, &mut output, key, iv)?;
+    Ok(output)
+}
+
+/// Like `aes_cbc_encrypt`, but writes the result into `output` instead of allocating. `output`
+/// must be at least as long as `input`.
+pub fn aes_cbc_encrypt_into(
+    input: &[u8],
+    output: &mut [u8],
+    key: &[u8],
+    iv: &[u8; AES_BLOCK_SIZE],
+) -> Result<(), AesError> {
+    let aes = Aes::new(key)?;
+    validate_block_aligned(input.len())?;
+    validate_output_len(input.len(), output.len())?;
+
+    let mut previous = *iv;
+
+    for (chunk, out) in input
+        .chunks_exact(AES_BLOCK_SIZE)
+        .zip(output.chunks_exact_mut(AES_BLOCK_SIZE))
+    {
+        for (p, b) in previous.iter_mut().zip(chunk) {
+            *p ^= b;
         }
-        let plaintext: &[u8; 16] = &plaintext[..16].try_into().unwrap();
-        let key: &[u8; 16] = &key[..16].try_into().unwrap();
-        let ciphertext = aes_enc_block(plaintext, key).expect("Encryption failed");
-        let decrypted = aes_dec_block(&ciphertext, key).expect("Decryption failed");
-        decrypted == *plaintext
+        aes.encrypt_block_in_place(&mut previous);
+        out.copy_from_slice(&previous);
+    }
+
+    Ok(())
+}
+
+/// Decrypts `data` in cipher block chaining (CBC) mode. `data` must be a multiple of the block
+/// size.
+pub fn aes_cbc_decrypt(
+    data: &[u8],
+    key: &[u8],
+    iv: &[u8; AES_BLOCK_SIZE],
+) -> Result<Vec<u8>, AesError> {
+    let mut output = vec![0; data.len()];
+    aes_cbc_decrypt_into(data, &mut output, key, iv)?;
+    Ok(output)
+}
+
+/// Like `aes_cbc_decrypt`, but writes the result into `output` instead of allocating. `output`
+/// must be at least as long as `input`.
+pub fn aes_cbc_decrypt_into(
+    input: &[u8],
+    output: &mut [u8],
+    key: &[u8],
+    iv: &[u8; AES_BLOCK_SIZE],
+) -> Result<(), AesError> {
+    let aes = Aes::new(key)?;
+    validate_block_aligned(input.len())?;
+    validate_output_len(input.len(), output.len())?;
+
+    let mut previous = *iv;
+
+    for (chunk, out) in input
+        .chunks_exact(AES_BLOCK_SIZE)
+        .zip(output.chunks_exact_mut(AES_BLOCK_SIZE))
+    {
+        let block: [u8; AES_BLOCK_SIZE] = chunk.try_into().unwrap();
+        let decrypted = aes.decrypt_block(&block);
+        for ((o, d), p) in out.iter_mut().zip(decrypted).zip(previous) {
+            *o = d ^ p;
+        }
+        previous = block;
+    }
+
+    Ok(())
+}
+
+/// Encrypts `data` in CBC mode with ciphertext stealing, so the ciphertext is exactly as long as
+/// the plaintext. This is the CS3 variant from NIST SP 800-38A's addendum, also used by Kerberos
+/// (RFC 3962): the last plaintext block is zero padded and encrypted as normal, then the last two
+/// ciphertext blocks are swapped and the final one is truncated to the length of the last
+/// plaintext block. The padding bytes can be dropped because decryption can recover them.
+/// `data` must be at least one block long.
+pub fn aes_cbc_cts_encrypt(
+    data: &[u8],
+    key: &[u8],
+    iv: &[u8; AES_BLOCK_SIZE],
+) -> Result<Vec<u8>, AesError> {
+    if data.len() < AES_BLOCK_SIZE {
+        return Err(AesError::InvalidBlockLength(data.len()));
+    }
+
+    let blocks = data.len().div_ceil(AES_BLOCK_SIZE);
+    let mut padded = data.to_vec();
+    padded.resize(blocks * AES_BLOCK_SIZE, 0);
+    let mut output = aes_cbc_encrypt(&padded, key, iv)?;
+
+    if blocks > 1 {
+        let (_, last_two) = output.split_at_mut((blocks - 2) * AES_BLOCK_SIZE);
+        let (second_last, last) = last_two.split_at_mut(AES_BLOCK_SIZE);
+        second_last.swap_with_slice(last);
+        output.truncate(data.len());
+    }
+
+    Ok(output)
+}
+
+/// Decrypts `data` encrypted with `aes_cbc_cts_encrypt`.
+pub fn aes_cbc_cts_decrypt(
+    data: &[u8],
+    key: &[u8],
+    iv: &[u8; AES_BLOCK_SIZE],
+) -> Result<Vec<u8>, AesError> {
+    if data.len() < AES_BLOCK_SIZE {
+        return Err(AesError::InvalidBlockLength(data.len()));
+    }
+
+    let blocks = data.len().div_ceil(AES_BLOCK_SIZE);
+    if blocks == 1 {
+        return aes_cbc_decrypt(data, key, iv);
+    }
+
+    // The block before the final partial one is the last CBC block, whose decryption is the
+    // padded last plaintext block XORed with the previous CBC block. The end of that previous
+    // block is what was stolen, so it can be pieced back together.
+    let aes = Aes::new(key)?;
+    let head = (blocks - 2) * AES_BLOCK_SIZE;
+    let tail = &data[head + AES_BLOCK_SIZE..];
+
+    let mut last = aes.decrypt_block(&data[head..head + AES_BLOCK_SIZE].try_into().unwrap());
+    let mut second_last = last;
+    second_last[..tail.len()].copy_from_slice(tail);
+    for (l, c) in last.iter_mut().zip(second_last) {
+        *l ^= c;
+    }
+
+    // Put the CBC blocks back in order and decrypt as normal
+    let mut reordered = data[..head].to_vec();
+    reordered.extend_from_slice(&second_last);
+    reordered.extend_from_slice(&data[head..head + AES_BLOCK_SIZE]);
+    let mut output = aes_cbc_decrypt(&reordered, key, iv)?;
+
+    output.truncate(head + AES_BLOCK_SIZE);
+    output.extend_from_slice(&last[..tail.len()]);
+    Ok(output)
+}
+
+/// Pads `data` to a multiple of `block_size` as described in PKCS#7 (RFC 5652). Each padding byte
+/// holds the number of bytes added, from 1 to `block_size`, so data that is already aligned gets
+/// a whole extra block of padding. This way the padding can always be removed unambiguously.
+pub fn pkcs7_pad(data: &[u8], block_size: usize) -> Vec<u8> {
+    assert!(
+        (1..=255).contains(&block_size),
+        "invalid block size {}",
+        block_size
+    );
+
+    let pad = block_size - data.len() % block_size;
+    let mut padded = Vec::with_capacity(data.len() + pad);
+    padded.extend_from_slice(data);
+    padded.resize(data.len() + pad, pad as u8);
+    padded
+}
+
+/// Removes PKCS#7 padding added by `pkcs7_pad`.
+///
+/// The padding is checked in constant time: the last `block_size` bytes are always read, and a
+/// single error is returned whether the length byte or any of the padding bytes is wrong.
+/// Reporting different errors, or returning early, would turn a decryptor into a padding oracle,
+/// which is enough to decrypt CBC ciphertexts one byte at a time.
+pub fn pkcs7_unpad(data: &[u8], block_size: usize) -> Result<Vec<u8>, AesError> {
+    if !(1..=255).contains(&block_size) || data.is_empty() || !data.len().is_multiple_of(block_size)
+    {
+        return Err(AesError::InvalidBlockLength(data.len()));
+    }
+
+    let pad = data[data.len() - 1];
+    let last_block = &data[data.len() - block_size..];
+
+    // each check produces 0xff on failure without branching: subtracting a larger value from a
+    // smaller one borrows into the high byte
+    let mut bad = ((u16::from(pad)).wrapping_sub(1) >> 8) as u8;
+    bad |= ((block_size as u16).wrapping_sub(u16::from(pad)) >> 8) as u8;
+    for (i, b) in last_block.iter().rev().enumerate() {
+        let in_padding = ((i as u16).wrapping_sub(u16::from(pad)) >> 8) as u8;
+        bad |= in_padding & (b ^ pad);
+    }
+
+    if bad != 0 {
+        return Err(AesError::InvalidPadding);
+    }
+
+    Ok(data[..data.len() - usize::from(pad)].to_vec())
+}
+
+/// Encrypts `data` in 128-bit cipher feedback (CFB) mode. Each keystream block is the encryption
+/// of the previous ciphertext block, starting with the IV. A partial final block is XORed with a
+/// truncated keystream, so no padding is needed.
+pub fn aes_cfb_encrypt(
+    data: &[u8],
+    key: &[u8],
+    iv: &[u8; AES_BLOCK_SIZE],
+) -> Result<Vec<u8>, AesError> {
+    let aes = Aes::new(key)?;
+
+    let mut feedback = *iv;
+    let mut output = Vec::with_capacity(data.len());
+
+    for chunk in data.chunks(AES_BLOCK_SIZE) {
+        let keystream = aes.encrypt_block(&feedback);
+        for (i, b) in chunk.iter().enumerate() {
+            feedback[i] = b ^ keystream[i];
+        }
+        output.extend_from_slice(&feedback[..chunk.len()]);
+    }
+
+    Ok(output)
+}
+
+/// Decrypts `data` in 128-bit cipher feedback (CFB) mode. Like encryption, this only uses the
+/// forward cipher, encrypting the previous ciphertext block to get the keystream.
+pub fn aes_cfb_decrypt(
+    data: &[u8],
+    key: &[u8],
+    iv: &[u8; AES_BLOCK_SIZE],
+) -> Result<Vec<u8>, AesError> {
+    let aes = Aes::new(key)?;
+
+    let mut feedback = *iv;
+    let mut output = Vec::with_capacity(data.len());
+
+    for chunk in data.chunks(AES_BLOCK_SIZE) {
+        let keystream = aes.encrypt_block(&feedback);
+        output.extend(chunk.iter().zip(keystream).map(|(c, k)| c ^ k));
+        feedback[..chunk.len()].copy_from_slice(chunk);
+    }
+
+    Ok(output)
+}
+
+/// Encrypts or decrypts `data` in output feedback (OFB) mode. The keystream is made by repeatedly
+/// encrypting the IV, independently of the data, so the same function both encrypts and decrypts.
+pub fn aes_ofb(data: &[u8], key: &[u8], iv: &[u8; AES_BLOCK_SIZE]) -> Result<Vec<u8>, AesError> {
+    let aes = Aes::new(key)?;
+
+    let mut keystream = *iv;
+    let mut output = Vec::with_capacity(data.len());
+
+    for chunk in data.chunks(AES_BLOCK_SIZE) {
+        aes.encrypt_block_in_place(&mut keystream);
+        output.extend(chunk.iter().zip(keystream).map(|(d, k)| d ^ k));
+    }
+
+    Ok(output)
+}
+
+// GCM authenticates with GHASH, a polynomial hash over GF(2^128). Blocks are read as big-endian
+// integers, but GCM numbers the bits of the field element from the most significant bit, so the
+// reduction polynomial x^128 + x^7 + x^2 + x + 1 shows up as 0xe1 in the top byte.
+const GCM_R: u128 = 0xe1 << 120;
+
+// Both operands are secret (the hash key and the running hash), so instead of branching on their
+// bits each bit is turned into an all-zeros or all-ones mask, like `ct_gf256_mul`.
+fn gf128_mul(x: u128, y: u128) -> u128 {
+    let mut z = 0;
+    let mut v = y;
+
+    for i in (0..128).rev() {
+        z ^= v & 0u128.wrapping_sub((x >> i) & 1);
+        v = (v >> 1) ^ (GCM_R & 0u128.wrapping_sub(v & 1));
+    }
+    z
+}
+
+fn ghash_update(h: u128, y: u128, data: &[u8]) -> u128 {
+    let mut y = y;
+    for chunk in data.chunks(AES_BLOCK_SIZE) {
+        // the last chunk is zero padded to a full block
+        let mut block = [0u8; AES_BLOCK_SIZE];
+        block[..chunk.len()].copy_from_slice(chunk);
+        y = gf128_mul(y ^ u128::from_be_bytes(block), h);
+    }
+    y
+}
+
+fn ghash(h: u128, aad: &[u8], ciphertext: &[u8]) -> u128 {
+    let y = ghash_update(h, 0, aad);
+    let y = ghash_update(h, y, ciphertext);
+
+    let lengths = ((aad.len() as u128 * 8) << 64) | (ciphertext.len() as u128 * 8);
+    gf128_mul(y ^ lengths, h)
+}
+
+// Derives the hash key H, the encryption of the all zero block, and the pre-counter block J0.
+fn gcm_init(aes: &Aes, nonce: &[u8]) -> (u128, [u8; AES_BLOCK_SIZE]) {
+    let h = u128::from_be_bytes(aes.encrypt_block(&[0; AES_BLOCK_SIZE]));
+
+    // 96 bit nonces are used directly with a counter of 1, anything else is hashed first
+    let j0 = if nonce.len() == 12 {
+        let mut j0 = [0u8; AES_BLOCK_SIZE];
+        j0[..12].copy_from_slice(nonce);
+        j0[15] = 1;
+        j0
+    } else {
+        ghash(h, &[], nonce).to_be_bytes()
+    };
+
+    (h, j0)
+}
+
+// Computes the authentication tag of a GCM message, T = E(K, J0) ^ GHASH(H, A, C).
+fn gcm_tag(
+    aes: &Aes,
+    h: u128,
+    j0: &[u8; AES_BLOCK_SIZE],
+    aad: &[u8],
+    ciphertext: &[u8],
+) -> [u8; AES_BLOCK_SIZE] {
+    let s = ghash(h, aad, ciphertext);
+    let mask = u128::from_be_bytes(aes.encrypt_block(j0));
+
+    (s ^ mask).to_be_bytes()
+}
+
+// GCM's counter mode starts after J0 and only increments the low 32 bits of the counter block,
+// wrapping around without carrying into the rest.
+fn gcm_ctr(aes: &Aes, j0: &[u8; AES_BLOCK_SIZE], input: &[u8], output: &mut [u8]) {
+    let mut counter = *j0;
+
+    for (chunk, out) in input
+        .chunks(AES_BLOCK_SIZE)
+        .zip(output.chunks_mut(AES_BLOCK_SIZE))
+    {
+        let low = u32::from_be_bytes(counter[12..].try_into().unwrap()).wrapping_add(1);
+        counter[12..].copy_from_slice(&low.to_be_bytes());
+
+        let keystream = aes.encrypt_block(&counter);
+        for ((o, d), k) in out.iter_mut().zip(chunk).zip(keystream) {
+            *o = d ^ k;
+        }
+    }
+}
+
+/// Encrypts and authenticates `plaintext` in Galois/counter mode (GCM), as described in NIST SP
+/// 800-38D. Returns the ciphertext followed by a 16 byte tag, which also covers `aad`.
+///
+/// Any nonce length is allowed, but 12 bytes is the standard and the fastest. A nonce must never
+/// be reused with the same key: that reveals the XOR of the plaintexts and lets an attacker
+/// recover the hash key and forge tags.
+pub fn aes_gcm_seal(
+    key: &[u8],
+    nonce: &[u8],
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Result<Vec<u8>, AesError> {
+    let aes = Aes::new(key)?;
+    let (h, j0) = gcm_init(&aes, nonce);
+
+    let mut output = vec![0; plaintext.len() + AES_BLOCK_SIZE];
+    gcm_ctr(&aes, &j0, plaintext, &mut output);
+
+    let tag = gcm_tag(&aes, h, &j0, aad, &output[..plaintext.len()]);
+    output[plaintext.len()..].copy_from_slice(&tag);
+
+    Ok(output)
+}
+
+/// Decrypts and verifies a message sealed with `aes_gcm_seal`, returning the plaintext. The tag
+/// is checked before anything is decrypted.
+pub fn aes_gcm_open(
+    key: &[u8],
+    nonce: &[u8],
+    aad: &[u8],
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, AesError> {
+    let aes = Aes::new(key)?;
+    if ciphertext.len() < AES_BLOCK_SIZE {
+        return Err(AesError::CiphertextTooShort(ciphertext.len()));
+    }
+    let (ciphertext, tag) = ciphertext.split_at(ciphertext.len() - AES_BLOCK_SIZE);
+
+    let (h, j0) = gcm_init(&aes, nonce);
+    if !ct_eq(&gcm_tag(&aes, h, &j0, aad, ciphertext), tag) {
+        return Err(AesError::AuthenticationFailed);
+    }
+
+    let mut plaintext = vec![0; ciphertext.len()];
+    gcm_ctr(&aes, &j0, ciphertext, &mut plaintext);
+    Ok(plaintext)
+}
+
+/// Checks the tag of an AES-GCM message without decrypting it. This only needs GHASH and one
+/// block encryption, so it can be used to reject forged messages before doing the more expensive
+/// decryption. The tag is compared in constant time. An invalid key length fails verification.
+pub fn aes_gcm_verify(
+    key: &[u8],
+    nonce: &[u8],
+    aad: &[u8],
+    ciphertext: &[u8],
+    tag: &[u8; AES_BLOCK_SIZE],
+) -> bool {
+    match Aes::new(key) {
+        Ok(aes) => {
+            let (h, j0) = gcm_init(&aes, nonce);
+            ct_eq(&gcm_tag(&aes, h, &j0, aad, ciphertext), tag)
+        }
+        Err(_) => false,
+    }
+}
+
+// CCM authenticates with CBC-MAC, encrypting each block XORed with the previous result. The
+// data is zero padded to a whole number of blocks.
+fn cbc_mac(aes: &Aes, mut mac: [u8; AES_BLOCK_SIZE], data: &[u8]) -> [u8; AES_BLOCK_SIZE] {
+    for chunk in data.chunks(AES_BLOCK_SIZE) {
+        for (m, b) in mac.iter_mut().zip(chunk) {
+            *m ^= b;
+        }
+        aes.encrypt_block_in_place(&mut mac);
+    }
+    mac
+}
+
+// Checks the CCM parameters and builds the first counter block, A_0. The nonce takes up 15 - L
+// bytes of the block, leaving L bytes for the counter, so shorter nonces allow longer messages.
+fn ccm_counter(
+    nonce: &[u8],
+    message_len: usize,
+    tag_len: usize,
+) -> Result<[u8; AES_BLOCK_SIZE], AesError> {
+    if !matches!(tag_len, 4 | 6 | 8 | 10 | 12 | 14 | 16) {
+        return Err(AesError::InvalidTagLength(tag_len));
+    }
+    if !(7..=13).contains(&nonce.len()) {
+        return Err(AesError::InvalidNonceLength(nonce.len()));
+    }
+
+    let l = 15 - nonce.len();
+    if l < 8 && (message_len as u64) >> (8 * l) != 0 {
+        return Err(AesError::MessageTooLong(message_len));
+    }
+
+    let mut counter = [0; AES_BLOCK_SIZE];
+    counter[0] = (l - 1) as u8;
+    counter[1..1 + nonce.len()].copy_from_slice(nonce);
+    Ok(counter)
+}
+
+// Computes the unencrypted CCM tag, the CBC-MAC of B_0, the length-prefixed aad and the
+// plaintext.
+fn ccm_mac(
+    aes: &Aes,
+    counter: &[u8; AES_BLOCK_SIZE],
+    aad: &[u8],
+    plaintext: &[u8],
+    tag_len: usize,
+) -> [u8; AES_BLOCK_SIZE] {
+    // B_0 holds the flags, the nonce and the message length in the counter's place
+    let mut b0 = *counter;
+    b0[0] |= (((tag_len - 2) / 2) as u8) << 3;
+    if !aad.is_empty() {
+        b0[0] |= 0x40;
+    }
+    let length = (plaintext.len() as u64).to_be_bytes();
+    let l = usize::from(counter[0]) + 1;
+    b0[AES_BLOCK_SIZE - l..].copy_from_slice(&length[8 - l.min(8)..]);
+
+    let mut mac = aes.encrypt_block(&b0);
+
+    if !aad.is_empty() {
+        let mut encoded = match aad.len() {
+            len if len < 0xff00 => (len as u16).to_be_bytes().to_vec(),
+            len if len as u64 <= u64::from(u32::MAX) => {
+                [&[0xff, 0xfe], &(len as u32).to_be_bytes()[..]].concat()
+            }
+            len => [&[0xff, 0xff], &(len as u64).to_be_bytes()[..]].concat(),
+        };
+        encoded.extend_from_slice(aad);
+        mac = cbc_mac(aes, mac, &encoded);
+    }
+
+    cbc_mac(aes, mac, plaintext)
+}
+
+/// Encrypts and authenticates `plaintext` in counter with CBC-MAC (CCM) mode, as described in
+/// RFC 3610. Returns the ciphertext followed by a `tag_len` byte tag, which also covers `aad`.
+///
+/// `tag_len` must be an even number from 4 to 16, and the nonce must be 7 to 13 bytes long. The
+/// rest of the counter block holds the message length, so a 13 byte nonce limits the message to
+/// 64KiB.
+pub fn aes_ccm_seal(
+    key: &[u8],
+    nonce: &[u8],
+    aad: &[u8],
+    plaintext: &[u8],
+    tag_len: usize,
+) -> Result<Vec<u8>, AesError> {
+    let aes = Aes::new(key)?;
+    let counter = ccm_counter(nonce, plaintext.len(), tag_len)?;
+
+    let mut tag = ccm_mac(&aes, &counter, aad, plaintext, tag_len);
+    let s0 = aes.encrypt_block(&counter);
+    for (t, s) in tag.iter_mut().zip(s0) {
+        *t ^= s;
+    }
+
+    let mut output = vec![0; plaintext.len() + tag_len];
+    let first = u128::from_be_bytes(counter) + 1;
+    ctr_xor(&aes, plaintext, &mut output, &first.to_be_bytes());
+    output[plaintext.len()..].copy_from_slice(&tag[..tag_len]);
+
+    Ok(output)
+}
+
+/// Decrypts and verifies a message sealed with `aes_ccm_seal`, returning the plaintext. Fails if
+/// the tag doesn't match, in which case no plaintext is returned.
+pub fn aes_ccm_open(
+    key: &[u8],
+    nonce: &[u8],
+    aad: &[u8],
+    ciphertext: &[u8],
+    tag_len: usize,
+) -> Result<Vec<u8>, AesError> {
+    let aes = Aes::new(key)?;
+    if ciphertext.len() < tag_len {
+        return Err(AesError::CiphertextTooShort(ciphertext.len()));
+    }
+    let (ciphertext, tag) = ciphertext.split_at(ciphertext.len() - tag_len);
+    let counter = ccm_counter(nonce, ciphertext.len(), tag_len)?;
+
+    let first = u128::from_be_bytes(counter) + 1;
+    let mut plaintext = vec![0; ciphertext.len()];
+    ctr_xor(&aes, ciphertext, &mut plaintext, &first.to_be_bytes());
+
+    let mut expected = ccm_mac(&aes, &counter, aad, &plaintext, tag_len);
+    let s0 = aes.encrypt_block(&counter);
+    for (t, s) in expected.iter_mut().zip(s0) {
+        *t ^= s;
+    }
+
+    if !ct_eq(&expected[..tag_len], tag) {
+        return Err(AesError::AuthenticationFailed);
+    }
+
+    Ok(plaintext)
+}
+
+// Doubles a block in GF(2^128) with the reduction polynomial x^128 + x^7 + x^2 + x + 1, as used
+// to derive the CMAC subkeys.
+fn dbl(block: [u8; AES_BLOCK_SIZE]) -> [u8; AES_BLOCK_SIZE] {
+    let x = u128::from_be_bytes(block);
+    let carry = 0u128.wrapping_sub(x >> 127);
+    ((x << 1) ^ (carry & 0x87)).to_be_bytes()
+}
+
+/// Computes the CMAC (also known as OMAC1) of `data`, as described in NIST SP 800-38B.
+///
+/// CMAC is CBC-MAC with the last block XORed with one of two subkeys derived from the key, which
+/// makes it secure for messages of any length. A partial final block is padded with a single 1
+/// bit followed by zeroes, and uses a different subkey than a full one, so padded and unpadded
+/// messages can't be confused.
+pub fn aes_cmac(key: &[u8], data: &[u8]) -> Result<[u8; AES_BLOCK_SIZE], AesError> {
+    Ok(cmac(&Aes::new(key)?, data))
+}
+
+/// Checks `tag` against the CMAC of `data`, in constant time. Tags truncated to fewer than 16
+/// bytes are compared against the same prefix of the CMAC.
+pub fn aes_cmac_verify(key: &[u8], data: &[u8], tag: &[u8]) -> Result<bool, AesError> {
+    let expected = aes_cmac(key, data)?;
+    if tag.is_empty() || tag.len() > AES_BLOCK_SIZE {
+        return Err(AesError::InvalidTagLength(tag.len()));
+    }
+    Ok(ct_eq(&expected[..tag.len()], tag))
+}
+
+fn cmac(aes: &Aes, data: &[u8]) -> [u8; AES_BLOCK_SIZE] {
+    let k1 = dbl(aes.encrypt_block(&[0; AES_BLOCK_SIZE]));
+    let k2 = dbl(k1);
+
+    // every block but the last is processed as in CBC-MAC
+    let split = data.len().saturating_sub(1) / AES_BLOCK_SIZE * AES_BLOCK_SIZE;
+    let (head, last) = data.split_at(split);
+    let mac = cbc_mac(aes, [0; AES_BLOCK_SIZE], head);
+
+    let mut block = [0; AES_BLOCK_SIZE];
+    block[..last.len()].copy_from_slice(last);
+    let subkey = if last.len() == AES_BLOCK_SIZE {
+        k1
+    } else {
+        block[last.len()] = 0x80;
+        k2
+    };
+
+    for ((b, m), k) in block.iter_mut().zip(mac).zip(subkey) {
+        *b ^= m ^ k;
+    }
+    aes.encrypt_block(&block)
+}
+
+/// Derives a 96-bit nonce for CTR or GCM from a message number, as the CMAC of the big-endian
+/// message number truncated to 12 bytes.
+///
+/// CMAC is a pseudorandom function, so distinct message numbers give unrelated nonces, and the
+/// same key and message number always give the same nonce. This only prevents nonce reuse if the
+/// message number comes from a monotonic counter that is never reset or shared between senders
+/// under the same key. Truncation means collisions become likely after around $2^{48}$ messages,
+/// far more than a single key should encrypt. Use a key dedicated to nonce derivation rather than
+/// the encryption key.
+pub fn derive_nonce(key: &[u8], message_number: u64) -> Result<[u8; 12], AesError> {
+    let mac = aes_cmac(key, &message_number.to_be_bytes())?;
+    let mut nonce = [0; 12];
+    nonce.copy_from_slice(&mac[..12]);
+    Ok(nonce)
+}
+
+// EAX tweaks CMAC by prefixing the data with a whole block holding `t`, so the nonce, the aad and
+// the ciphertext are each authenticated under a different function.
+fn omac(aes: &Aes, t: u8, data: &[u8]) -> [u8; AES_BLOCK_SIZE] {
+    let mut input = vec![0; AES_BLOCK_SIZE];
+    input[AES_BLOCK_SIZE - 1] = t;
+    input.extend_from_slice(data);
+    cmac(aes, &input)
+}
+
+fn validate_eax_tag_len(tag_len: usize) -> Result<(), AesError> {
+    if !(1..=AES_BLOCK_SIZE).contains(&tag_len) {
+        return Err(AesError::InvalidTagLength(tag_len));
+    }
+    Ok(())
+}
+
+/// Encrypts and authenticates `plaintext` in EAX mode, returning the ciphertext followed by a
+/// `tag_len` byte tag, which also covers `aad`.
+///
+/// EAX encrypts in CTR mode, starting from the OMAC of the nonce, and the tag is the XOR of the
+/// OMACs of the nonce, the aad and the ciphertext. Unlike CCM, nothing depends on the length of
+/// the message up front, so it can be computed in a single streaming pass, and the nonce can be
+/// any length.
+pub fn aes_eax_seal(
+    key: &[u8],
+    nonce: &[u8],
+    aad: &[u8],
+    plaintext: &[u8],
+    tag_len: usize,
+) -> Result<Vec<u8>, AesError> {
+    let aes = Aes::new(key)?;
+    validate_eax_tag_len(tag_len)?;
+
+    let n = omac(&aes, 0, nonce);
+    let h = omac(&aes, 1, aad);
+
+    let mut output = vec![0; plaintext.len() + tag_len];
+    ctr_xor(&aes, plaintext, &mut output, &n);
+
+    let c = omac(&aes, 2, &output[..plaintext.len()]);
+    for (i, t) in output[plaintext.len()..].iter_mut().enumerate() {
+        *t = n[i] ^ h[i] ^ c[i];
+    }
+
+    Ok(output)
+}
+
+/// Decrypts and verifies a message sealed with `aes_eax_seal`, returning the plaintext. Fails if
+/// the tag doesn't match, in which case nothing is decrypted.
+pub fn aes_eax_open(
+    key: &[u8],
+    nonce: &[u8],
+    aad: &[u8],
+    ciphertext: &[u8],
+    tag_len: usize,
+) -> Result<Vec<u8>, AesError> {
+    let aes = Aes::new(key)?;
+    validate_eax_tag_len(tag_len)?;
+    if ciphertext.len() < tag_len {
+        return Err(AesError::CiphertextTooShort(ciphertext.len()));
+    }
+    let (ciphertext, tag) = ciphertext.split_at(ciphertext.len() - tag_len);
+
+    let n = omac(&aes, 0, nonce);
+    let h = omac(&aes, 1, aad);
+    let c = omac(&aes, 2, ciphertext);
+
+    let expected = (0..tag_len).map(|i| n[i] ^ h[i] ^ c[i]).collect::<Vec<_>>();
+    if !ct_eq(&expected, tag) {
+        return Err(AesError::AuthenticationFailed);
+    }
+
+    let mut plaintext = vec![0; ciphertext.len()];
+    ctr_xor(&aes, ciphertext, &mut plaintext, &n);
+    Ok(plaintext)
+}
+
+// XTS multiplies the tweak by the primitive element x with the same polynomial as CMAC, but
+// reads the block as a little-endian integer.
+fn xts_mul_alpha(tweak: [u8; AES_BLOCK_SIZE]) -> [u8; AES_BLOCK_SIZE] {
+    let mut t = tweak;
+    t.reverse();
+    t = dbl(t);
+    t.reverse();
+    t
+}
+
+// `cipher` is either `Aes::encrypt_block` or `Aes::decrypt_block`
+fn xts_block(
+    block: &[u8],
+    tweak: &[u8; AES_BLOCK_SIZE],
+    aes: &Aes,
+    cipher: fn(&Aes, &[u8; AES_BLOCK_SIZE]) -> [u8; AES_BLOCK_SIZE],
+) -> [u8; AES_BLOCK_SIZE] {
+    let mut x = [0; AES_BLOCK_SIZE];
+    for ((x, b), t) in x.iter_mut().zip(block).zip(tweak) {
+        *x = b ^ t;
+    }
+    let mut y = cipher(aes, &x);
+    for (y, t) in y.iter_mut().zip(tweak) {
+        *y ^= t;
+    }
+    y
+}
+
+fn validate_xts(key1: &[u8], key2: &[u8], data_len: usize) -> Result<(), AesError> {
+    validate_key_len(key1.len())?;
+    validate_key_len(key2.len())?;
+    if key1.len() != key2.len() {
+        return Err(AesError::MismatchedKeyLengths(key1.len(), key2.len()));
+    }
+    if data_len < AES_BLOCK_SIZE {
+        return Err(AesError::InvalidBlockLength(data_len));
+    }
+    Ok(())
+}
+
+/// Encrypts a sector in XTS mode, as described in IEEE 1619 and used for full-disk encryption.
+///
+/// Each block is XORed with a tweak before and after encryption under `key1`. The first tweak is
+/// the sector number, as a little-endian block, encrypted under `key2`, and each following block
+/// multiplies it by $x$ in GF(2^128). Identical plaintext blocks therefore encrypt differently
+/// at every position on the disk, while any block can still be decrypted on its own.
+///
+/// The data must be at least one block long. If it isn't a multiple of the block size, the
+/// final partial block uses ciphertext stealing: it borrows the tail of the previous
+/// ciphertext block, so the ciphertext is the same length as the plaintext.
+pub fn aes_xts_encrypt(
+    key1: &[u8],
+    key2: &[u8],
+    sector_index: u128,
+    data: &[u8],
+) -> Result<Vec<u8>, AesError> {
+    validate_xts(key1, key2, data.len())?;
+    let aes = Aes::new(key1)?;
+
+    let mut tweak = Aes::new(key2)?.encrypt_block(&sector_index.to_le_bytes());
+    let mut output = Vec::with_capacity(data.len());
+
+    let full = data.len() / AES_BLOCK_SIZE;
+    let remainder = data.len() % AES_BLOCK_SIZE;
+
+    for block in data.chunks_exact(AES_BLOCK_SIZE) {
+        output.extend_from_slice(&xts_block(block, &tweak, &aes, Aes::encrypt_block));
+        tweak = xts_mul_alpha(tweak);
+    }
+
+    if remainder > 0 {
+        // the last full ciphertext block is split: its head becomes the final partial block,
+        // and its tail pads the final plaintext block, which takes its place
+        let last = (full - 1) * AES_BLOCK_SIZE;
+        let mut stolen = [0; AES_BLOCK_SIZE];
+        stolen[..remainder].copy_from_slice(&data[full * AES_BLOCK_SIZE..]);
+        stolen[remainder..].copy_from_slice(&output[last + remainder..]);
+
+        let head = output[last..last + remainder].to_vec();
+        output.truncate(last);
+        output.extend_from_slice(&xts_block(&stolen, &tweak, &aes, Aes::encrypt_block));
+        output.extend_from_slice(&head);
+    }
+
+    Ok(output)
+}
+
+/// Decrypts a sector encrypted with `aes_xts_encrypt`.
+pub fn aes_xts_decrypt(
+    key1: &[u8],
+    key2: &[u8],
+    sector_index: u128,
+    data: &[u8],
+) -> Result<Vec<u8>, AesError> {
+    validate_xts(key1, key2, data.len())?;
+    let aes = Aes::new(key1)?;
+
+    let mut tweak = Aes::new(key2)?.encrypt_block(&sector_index.to_le_bytes());
+    let mut output = Vec::with_capacity(data.len());
+
+    let full = data.len() / AES_BLOCK_SIZE;
+    let remainder = data.len() % AES_BLOCK_SIZE;
+
+    // with ciphertext stealing, the last full block is handled separately below
+    let plain_blocks = if remainder > 0 { full - 1 } else { full };
+    for block in data.chunks_exact(AES_BLOCK_SIZE).take(plain_blocks) {
+        output.extend_from_slice(&xts_block(block, &tweak, &aes, Aes::decrypt_block));
+        tweak = xts_mul_alpha(tweak);
+    }
+
+    if remainder > 0 {
+        // the last full block was encrypted with the following tweak
+        let last = (full - 1) * AES_BLOCK_SIZE;
+        let next_tweak = xts_mul_alpha(tweak);
+        let stolen = xts_block(
+            &data[last..last + AES_BLOCK_SIZE],
+            &next_tweak,
+            &aes,
+            Aes::decrypt_block,
+        );
+
+        let mut block = [0; AES_BLOCK_SIZE];
+        block[..remainder].copy_from_slice(&data[full * AES_BLOCK_SIZE..]);
+        block[remainder..].copy_from_slice(&stolen[remainder..]);
+
+        output.extend_from_slice(&xts_block(&block, &tweak, &aes, Aes::decrypt_block));
+        output.extend_from_slice(&stolen[..remainder]);
+    }
+
+    Ok(output)
+}
+
+/// The block cipher modes `encrypt_reader` and `decrypt_reader` can stream.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StreamMode {
+    /// CBC with PKCS#7 padding, so the ciphertext is 1 to 16 bytes longer than the plaintext.
+    Cbc,
+    /// CTR, with the IV as the initial counter block. The ciphertext is the same length as the
+    /// plaintext.
+    Ctr,
+}
+
+const STREAM_BUFFER_SIZE: usize = 8192;
+
+/// Encrypts everything read from `reader` and writes the ciphertext to `writer`, a buffer at a
+/// time, so the data never has to fit in memory. Returns the number of bytes written.
+///
+/// An invalid key is reported as an `io::ErrorKind::InvalidInput` error wrapping the `AesError`.
+pub fn encrypt_reader<R: Read, W: Write>(
+    reader: R,
+    writer: W,
+    key: &[u8],
+    iv: &[u8; AES_BLOCK_SIZE],
+    mode: StreamMode,
+) -> io::Result<u64> {
+    stream(reader, writer, key, iv, mode, false)
+}
+
+/// Decrypts everything read from `reader` and writes the plaintext to `writer`, a buffer at a
+/// time. Returns the number of bytes written.
+///
+/// An invalid key is reported as an `io::ErrorKind::InvalidInput` error, and a CBC ciphertext
+/// that isn't a whole number of blocks or has bad padding as `io::ErrorKind::InvalidData`, both
+/// wrapping the `AesError`. With CBC, everything but the last block has already been written by
+/// the time bad padding is found.
+pub fn decrypt_reader<R: Read, W: Write>(
+    reader: R,
+    writer: W,
+    key: &[u8],
+    iv: &[u8; AES_BLOCK_SIZE],
+    mode: StreamMode,
+) -> io::Result<u64> {
+    stream(reader, writer, key, iv, mode, true)
+}
+
+// Runs the mode over `data` in place. `chain` is the previous ciphertext block for CBC and the
+// next counter block for CTR. Only CTR accepts a partial final block.
+fn stream_blocks(
+    aes: &Aes,
+    mode: StreamMode,
+    decrypt: bool,
+    chain: &mut [u8; AES_BLOCK_SIZE],
+    data: &mut [u8],
+) {
+    for block in data.chunks_mut(AES_BLOCK_SIZE) {
+        match (mode, decrypt) {
+            (StreamMode::Cbc, false) => {
+                let block: &mut [u8; AES_BLOCK_SIZE] = block.try_into().unwrap();
+                for (b, c) in block.iter_mut().zip(*chain) {
+                    *b ^= c;
+                }
+                aes.encrypt_block_in_place(block);
+                *chain = *block;
+            }
+            (StreamMode::Cbc, true) => {
+                let block: &mut [u8; AES_BLOCK_SIZE] = block.try_into().unwrap();
+                let ciphertext = *block;
+                aes.decrypt_block_in_place(block);
+                for (b, c) in block.iter_mut().zip(*chain) {
+                    *b ^= c;
+                }
+                *chain = ciphertext;
+            }
+            (StreamMode::Ctr, _) => {
+                let keystream = aes.encrypt_block(chain);
+                for (b, k) in block.iter_mut().zip(keystream) {
+                    *b ^= k;
+                }
+                *chain = u128::from_be_bytes(*chain).wrapping_add(1).to_be_bytes();
+            }
+        }
+    }
+}
+
+fn stream<R: Read, W: Write>(
+    mut reader: R,
+    mut writer: W,
+    key: &[u8],
+    iv: &[u8; AES_BLOCK_SIZE],
+    mode: StreamMode,
+    decrypt: bool,
+) -> io::Result<u64> {
+    let aes = Aes::new(key).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let invalid_data = |e: AesError| io::Error::new(io::ErrorKind::InvalidData, e);
+
+    let mut chain = *iv;
+    let mut buffer = vec![0; STREAM_BUFFER_SIZE];
+    let mut pending = Vec::with_capacity(STREAM_BUFFER_SIZE + AES_BLOCK_SIZE);
+    let mut written = 0;
+
+    loop {
+        let n = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        pending.extend_from_slice(&buffer[..n]);
+
+        // Only whole blocks can be processed before the end. CBC decryption also holds back the
+        // last whole block, since it might hold the padding.
+        let mut ready = pending.len() - pending.len() % AES_BLOCK_SIZE;
+        if mode == StreamMode::Cbc && decrypt && ready == pending.len() {
+            ready = ready.saturating_sub(AES_BLOCK_SIZE);
+        }
+
+        stream_blocks(&aes, mode, decrypt, &mut chain, &mut pending[..ready]);
+        writer.write_all(&pending[..ready])?;
+        written += ready as u64;
+        pending.drain(..ready);
+    }
+
+    let last = match (mode, decrypt) {
+        (StreamMode::Cbc, false) => {
+            let mut last = pkcs7_pad(&pending, AES_BLOCK_SIZE);
+            stream_blocks(&aes, mode, decrypt, &mut chain, &mut last);
+            last
+        }
+        (StreamMode::Cbc, true) => {
+            if pending.len() != AES_BLOCK_SIZE {
+                return Err(invalid_data(AesError::InvalidBlockLength(pending.len())));
+            }
+            stream_blocks(&aes, mode, decrypt, &mut chain, &mut pending);
+            pkcs7_unpad(&pending, AES_BLOCK_SIZE).map_err(invalid_data)?
+        }
+        (StreamMode::Ctr, _) => {
+            stream_blocks(&aes, mode, decrypt, &mut chain, &mut pending);
+            pending
+        }
+    };
+
+    writer.write_all(&last)?;
+    writer.flush()?;
+    Ok(written + last.len() as u64)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use quickcheck_macros::quickcheck;
+
+    #[test]
+    fn ex() {
+        // Test vectors for AES-128
+        let plaintext: [u8; AES_BLOCK_SIZE] = [
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00,
+        ];
+        let key: [u8; AES_128_KEY_SIZE] = [
+            0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd,
+            0xee, 0xff,
+        ];
+        let expected_ciphertext: [u8; AES_BLOCK_SIZE] = [
+            0xfd, 0xe4, 0xfb, 0xae, 0x4a, 0x09, 0xe0, 0x20, 0xef, 0xf7, 0x22, 0x96, 0x9f, 0x83,
+            0x83, 0x2b,
+        ];
+
+        // Perform AES-128 encryption
+        let ciphertext = aes_enc_block(&plaintext, &key).expect("Encryption failed");
+        assert_eq!(ciphertext, expected_ciphertext);
+
+        // Perform AES-128 decryption
+        let decrypted = aes_dec_block(&ciphertext, &key).expect("Decryption failed");
+        assert_eq!(decrypted, plaintext);
+    }
+
+    #[quickcheck]
+    fn enc_and_dec(plaintext: Vec<u8>, key: Vec<u8>) -> bool {
+        // we need enough bytes to generate the plaintext and key
+        if plaintext.len() < 16 || key.len() < 16 {
+            return true;
+        }
+        let plaintext: &[u8; 16] = &plaintext[..16].try_into().unwrap();
+        let key: &[u8; 16] = &key[..16].try_into().unwrap();
+        let ciphertext = aes_enc_block(plaintext, key).expect("Encryption failed");
+        let decrypted = aes_dec_block(&ciphertext, key).expect("Decryption failed");
+        decrypted == *plaintext
+    }
+
+    #[test]
+    fn ct_inverse_matches_table() {
+        use crate::util::ct_gf256_inverse;
+
+        for i in 0..=255 {
+            assert_eq!(ct_gf256_inverse(i, 0x1b), find_inverse(i));
+        }
+    }
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    // test vectors from the GCM spec, test cases 2 and 4
+    #[test]
+    fn gcm_verify() {
+        let key = [0u8; AES_128_KEY_SIZE];
+        let nonce = [0u8; 12];
+        let ciphertext = hex("0388dace60b6a392f328c2b971b2fe78");
+        let tag: [u8; 16] = hex("ab6e47d42cec13bdf53a67b21257bddf").try_into().unwrap();
+        assert!(aes_gcm_verify(&key, &nonce, &[], &ciphertext, &tag));
+
+        let key = hex("feffe9928665731c6d6a8f9467308308");
+        let nonce = hex("cafebabefacedbaddecaf888");
+        let aad = hex("feedfacedeadbeeffeedfacedeadbeefabaddad2");
+        let mut ciphertext = hex(concat!(
+            "42831ec2217774244b7221b784d0d49ce3aa212f2c02a4e035c17e2329aca12e",
+            "21d514b25466931c7d8f6a5aac84aa051ba30b396a0aac973d58e091"
+        ));
+        let tag: [u8; 16] = hex("5bc94fbc3221a5db94fae95ae7121a47").try_into().unwrap();
+        assert!(aes_gcm_verify(&key, &nonce, &aad, &ciphertext, &tag));
+
+        // tampering with the ciphertext or the aad invalidates the tag
+        ciphertext[7] ^= 1;
+        assert!(!aes_gcm_verify(&key, &nonce, &aad, &ciphertext, &tag));
+        ciphertext[7] ^= 1;
+        assert!(!aes_gcm_verify(&key, &nonce, &aad[1..], &ciphertext, &tag));
+    }
+
+    // test vectors from NIST SP 800-38A, F.5.1 and F.5.2
+    #[test]
+    fn ctr() {
+        let key = hex("2b7e151628aed2a6abf7158809cf4f3c");
+        let nonce: [u8; 16] = hex("f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff").try_into().unwrap();
+        let plaintext = hex(concat!(
+            "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51",
+            "30c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710"
+        ));
+        let ciphertext = hex(concat!(
+            "874d6191b620e3261bef6864990db6ce9806f66b7970fdff8617187bb9fffdff",
+            "5ae4df3edbd5d35e5b4f09020db03eab1e031dda2fbe03d1792170a0f3009cee"
+        ));
+
+        assert_eq!(aes_ctr(&plaintext, &key, &nonce).unwrap(), ciphertext);
+        assert_eq!(aes_ctr(&ciphertext, &key, &nonce).unwrap(), plaintext);
+
+        // a partial final block uses a truncated keystream
+        assert_eq!(
+            aes_ctr(&plaintext[..20], &key, &nonce).unwrap(),
+            &ciphertext[..20]
+        );
+    }
+
+    #[test]
+    fn ctr_round_trip() {
+        let key = [0x42; AES_256_KEY_SIZE];
+        let nonce = [0xff; AES_BLOCK_SIZE]; // wraps around after the first block
+        let data = (0..1000).map(|i| i as u8).collect::<Vec<_>>();
+
+        let encrypted = aes_ctr(&data, &key, &nonce).unwrap();
+        assert_ne!(encrypted, data);
+        assert_eq!(aes_ctr(&encrypted, &key, &nonce).unwrap(), data);
+    }
+
+    // test vectors from NIST SP 800-38A, F.3.13 and F.4.1
+    #[test]
+    fn cfb_and_ofb() {
+        let key = hex("2b7e151628aed2a6abf7158809cf4f3c");
+        let iv: [u8; 16] = hex("000102030405060708090a0b0c0d0e0f").try_into().unwrap();
+        let plaintext = hex(concat!(
+            "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51",
+            "30c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710"
+        ));
+
+        let cfb = hex(concat!(
+            "3b3fd92eb72dad20333449f8e83cfb4ac8a64537a0b3a93fcde3cdad9f1ce58b",
+            "26751f67a3cbb140b1808cf187a4f4dfc04b05357c5d1c0eeac4c66f9ff7f2e6"
+        ));
+        assert_eq!(aes_cfb_encrypt(&plaintext, &key, &iv).unwrap(), cfb);
+        assert_eq!(aes_cfb_decrypt(&cfb, &key, &iv).unwrap(), plaintext);
+
+        let ofb = hex(concat!(
+            "3b3fd92eb72dad20333449f8e83cfb4a7789508d16918f03f53c52dac54ed825",
+            "9740051e9c5fecf64344f7a82260edcc304c6528f659c77866a510d9c1d6ae5e"
+        ));
+        assert_eq!(aes_ofb(&plaintext, &key, &iv).unwrap(), ofb);
+        assert_eq!(aes_ofb(&ofb, &key, &iv).unwrap(), plaintext);
+
+        // partial final blocks are truncated
+        assert_eq!(
+            aes_cfb_encrypt(&plaintext[..40], &key, &iv).unwrap(),
+            &cfb[..40]
+        );
+        assert_eq!(aes_ofb(&plaintext[..40], &key, &iv).unwrap(), &ofb[..40]);
+    }
+
+    #[quickcheck]
+    fn cfb_and_ofb_round_trip(data: Vec<u8>, key: Vec<u8>, iv: Vec<u8>) -> bool {
+        if key.len() < 16 || iv.len() < 16 {
+            return true;
+        }
+        let key = &key[..16];
+        let iv: &[u8; 16] = &iv[..16].try_into().unwrap();
+
+        let cfb = aes_cfb_encrypt(&data, key, iv).expect("Encryption failed");
+        let ofb = aes_ofb(&data, key, iv).expect("Encryption failed");
+        aes_cfb_decrypt(&cfb, key, iv).expect("Decryption failed") == data
+            && aes_ofb(&ofb, key, iv).expect("Decryption failed") == data
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn ctr_parallel() {
+        // an odd length, so the last chunk and block are partial
+        let data = (0..4 * 1024 * 1024 + 7)
+            .map(|i: u32| (i.wrapping_mul(2654435761) >> 24) as u8)
+            .collect::<Vec<_>>();
+        let key = [0x24; AES_128_KEY_SIZE];
+
+        // a nonce near the top, so the counter wraps partway through
+        let mut nonce = [0xff; AES_BLOCK_SIZE];
+        nonce[15] = 0x00;
+        assert_eq!(
+            aes_ctr_par(&data, &key, &nonce).unwrap(),
+            aes_ctr(&data, &key, &nonce).unwrap()
+        );
+
+        assert!(aes_ctr_par(&[], &key, &nonce).unwrap().is_empty());
+    }
+
+    // test vectors from NIST SP 800-38A, F.2.1 and F.2.2
+    #[test]
+    fn cbc() {
+        let key = hex("2b7e151628aed2a6abf7158809cf4f3c");
+        let iv: [u8; 16] = hex("000102030405060708090a0b0c0d0e0f").try_into().unwrap();
+        let plaintext = hex(concat!(
+            "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51",
+            "30c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710"
+        ));
+        let ciphertext = hex(concat!(
+            "7649abac8119b246cee98e9b12e9197d5086cb9b507219ee95db113a917678b2",
+            "73bed6b8e3c1743b7116e69e222295163ff1caa1681fac09120eca307586e1a7"
+        ));
+
+        assert_eq!(aes_cbc_encrypt(&plaintext, &key, &iv).unwrap(), ciphertext);
+        assert_eq!(aes_cbc_decrypt(&ciphertext, &key, &iv).unwrap(), plaintext);
+
+        // data that isn't block aligned is rejected
+        assert!(aes_cbc_encrypt(&plaintext[..20], &key, &iv).is_err());
+        assert!(aes_cbc_decrypt(&ciphertext[..20], &key, &iv).is_err());
+    }
+
+    #[test]
+    fn into_matches_allocating() {
+        let key = [0x42; AES_128_KEY_SIZE];
+        let iv = [0x24; AES_BLOCK_SIZE];
+        let data = (0..160).map(|i| i as u8).collect::<Vec<_>>();
+
+        // the output buffer may be longer than the input
+        let mut output = vec![0xaa; data.len() + 5];
+        aes_ctr_xor_into(&data[..150], &mut output, &key, &iv).unwrap();
+        assert_eq!(output[..150], aes_ctr(&data[..150], &key, &iv).unwrap());
+        assert_eq!(output[150..], [0xaa; 15]);
+
+        let mut output = vec![0; data.len()];
+        aes_cbc_encrypt_into(&data, &mut output, &key, &iv).unwrap();
+        let encrypted = aes_cbc_encrypt(&data, &key, &iv).unwrap();
+        assert_eq!(output, encrypted);
+
+        aes_cbc_decrypt_into(&encrypted, &mut output, &key, &iv).unwrap();
+        assert_eq!(output, aes_cbc_decrypt(&encrypted, &key, &iv).unwrap());
+        assert_eq!(output, data);
+
+        // an output buffer that is too short is rejected
+        let mut short = vec![0; data.len() - 1];
+        assert!(aes_ctr_xor_into(&data, &mut short, &key, &iv).is_err());
+        assert!(aes_cbc_encrypt_into(&data, &mut short, &key, &iv).is_err());
+    }
+
+    // test vectors from RFC 3610, packet vectors 1 and 2
+    #[test]
+    fn ccm() {
+        let key = hex("c0c1c2c3c4c5c6c7c8c9cacbcccdcecf");
+        let aad = hex("0001020304050607");
+
+        let nonce = hex("00000003020100a0a1a2a3a4a5");
+        let plaintext = hex("08090a0b0c0d0e0f101112131415161718191a1b1c1d1e");
+        let sealed = hex(concat!(
+            "588c979a61c663d2f066d0c2c0f989806d5f6b61dac384",
+            "17e8d12cfdf926e0"
+        ));
+        assert_eq!(
+            aes_ccm_seal(&key, &nonce, &aad, &plaintext, 8).unwrap(),
+            sealed
+        );
+        assert_eq!(
+            aes_ccm_open(&key, &nonce, &aad, &sealed, 8).unwrap(),
+            plaintext
+        );
+
+        let nonce = hex("00000004030201a0a1a2a3a4a5");
+        let plaintext = hex("08090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f");
+        let mut sealed = hex(concat!(
+            "72c91a36e135f8cf291ca894085c87e3cc15c439c9e43a3b",
+            "a091d56e10400916"
+        ));
+        assert_eq!(
+            aes_ccm_seal(&key, &nonce, &aad, &plaintext, 8).unwrap(),
+            sealed
+        );
+        assert_eq!(
+            aes_ccm_open(&key, &nonce, &aad, &sealed, 8).unwrap(),
+            plaintext
+        );
+
+        // tampering with the ciphertext or the aad fails authentication
+        sealed[3] ^= 1;
+        assert!(aes_ccm_open(&key, &nonce, &aad, &sealed, 8).is_err());
+        sealed[3] ^= 1;
+        assert!(aes_ccm_open(&key, &nonce, &aad[1..], &sealed, 8).is_err());
+    }
+
+    #[test]
+    fn ccm_parameters() {
+        let key = [0; AES_128_KEY_SIZE];
+        let nonce = [0; 13];
+
+        for tag_len in [0, 2, 3, 5, 17] {
+            assert!(aes_ccm_seal(&key, &nonce, &[], b"data", tag_len).is_err());
+        }
+        assert!(aes_ccm_seal(&key, &nonce[..6], &[], b"data", 16).is_err());
+        assert!(aes_ccm_seal(&key, &[0; 14], &[], b"data", 16).is_err());
+
+        // a 13 byte nonce leaves two bytes for the message length
+        let long = vec![0; 1 << 16];
+        assert!(aes_ccm_seal(&key, &nonce, &[], &long, 16).is_err());
+        let sealed = aes_ccm_seal(&key, &nonce[..12], &[], &long, 16).unwrap();
+        assert_eq!(
+            aes_ccm_open(&key, &nonce[..12], &[], &sealed, 16).unwrap(),
+            long
+        );
+    }
+
+    // test vectors from NIST SP 800-38B, D.1
+    #[test]
+    fn cmac() {
+        let key = hex("2b7e151628aed2a6abf7158809cf4f3c");
+        let message = hex(concat!(
+            "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51",
+            "30c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710"
+        ));
+
+        assert_eq!(
+            aes_cmac(&key, &[]).unwrap()[..],
+            hex("bb1d6929e95937287fa37d129b756746")
+        );
+        assert_eq!(
+            aes_cmac(&key, &message[..16]).unwrap()[..],
+            hex("070a16b46b4d4144f79bdd9dd04a287c")
+        );
+        assert_eq!(
+            aes_cmac(&key, &message[..40]).unwrap()[..],
+            hex("dfa66747de9ae63030ca32611497c827")
+        );
+        assert_eq!(
+            aes_cmac(&key, &message).unwrap()[..],
+            hex("51f0bebf7e3b9d92fc49741779363cfe")
+        );
+
+        let mut tag = hex("51f0bebf7e3b9d92fc49741779363cfe");
+        assert_eq!(aes_cmac_verify(&key, &message, &tag), Ok(true));
+        assert_eq!(aes_cmac_verify(&key, &message, &tag[..8]), Ok(true));
+        tag[15] ^= 1;
+        assert_eq!(aes_cmac_verify(&key, &message, &tag), Ok(false));
+        assert_eq!(
+            aes_cmac_verify(&key, &message, &[]),
+            Err(AesError::InvalidTagLength(0))
+        );
+    }
+
+    // test vectors from the EAX paper
+    #[test]
+    fn eax() {
+        let key = hex("233952dee4d5ed5f9b9c6d6ff80ff478");
+        let nonce = hex("62ec67f9c3a4a407fcb2a8c49031a8b3");
+        let aad = hex("6bfb914fd07eae6b");
+        let sealed = hex("e037830e8389f27b025a2d6527e79d01");
+        assert_eq!(aes_eax_seal(&key, &nonce, &aad, &[], 16).unwrap(), sealed);
+        assert_eq!(aes_eax_open(&key, &nonce, &aad, &sealed, 16).unwrap(), []);
+
+        let key = hex("91945d3f4dcbee0bf45ef52255f095a4");
+        let nonce = hex("becaf043b0a23d843194ba972c66debd");
+        let aad = hex("fa3bfd4806eb53fa");
+        let plaintext = hex("f7fb");
+        let mut sealed = hex("19dd5c4c9331049d0bdab0277408f67967e5");
+        assert_eq!(
+            aes_eax_seal(&key, &nonce, &aad, &plaintext, 16).unwrap(),
+            sealed
+        );
+        assert_eq!(
+            aes_eax_open(&key, &nonce, &aad, &sealed, 16).unwrap(),
+            plaintext
+        );
+
+        // shorter tags are truncations of the full tag
+        assert_eq!(
+            aes_eax_seal(&key, &nonce, &aad, &plaintext, 8).unwrap(),
+            &sealed[..10]
+        );
+
+        // tampering with the tag fails decryption
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        assert!(aes_eax_open(&key, &nonce, &aad, &sealed, 16).is_err());
+        assert!(aes_eax_seal(&key, &nonce, &aad, &plaintext, 17).is_err());
+    }
+
+    // test vectors from IEEE 1619, vectors 1 and 2
+    #[test]
+    fn xts() {
+        let key1 = [0; AES_128_KEY_SIZE];
+        let key2 = [0; AES_128_KEY_SIZE];
+        let data = [0; 32];
+        let ciphertext = hex(concat!(
+            "917cf69ebd68b2ec9b9fe9a3eadda692",
+            "cd43d2f59598ed858c02c2652fbf922e"
+        ));
+        assert_eq!(aes_xts_encrypt(&key1, &key2, 0, &data).unwrap(), ciphertext);
+        assert_eq!(aes_xts_decrypt(&key1, &key2, 0, &ciphertext).unwrap(), data);
+
+        let key1 = [0x11; AES_128_KEY_SIZE];
+        let key2 = [0x22; AES_128_KEY_SIZE];
+        let data = [0x44; 32];
+        let ciphertext = hex(concat!(
+            "c454185e6a16936e39334038acef838b",
+            "fb186fff7480adc4289382ecd6d394f0"
+        ));
+        let sector = 0x3333333333;
+        assert_eq!(
+            aes_xts_encrypt(&key1, &key2, sector, &data).unwrap(),
+            ciphertext
+        );
+        assert_eq!(
+            aes_xts_decrypt(&key1, &key2, sector, &ciphertext).unwrap(),
+            data
+        );
+
+        // sectors that aren't a multiple of the block size use ciphertext stealing, checked
+        // against OpenSSL
+        let key1 = hex("fffefdfcfbfaf9f8f7f6f5f4f3f2f1f0");
+        let key2 = hex("bfbebdbcbbbab9b8b7b6b5b4b3b2b1b0");
+        let sector = 0x9a78563412;
+        let data = (0..20).collect::<Vec<u8>>();
+        for (len, ciphertext) in [
+            (17, hex("641610679dcbf92e505c41333fb06c2a95")),
+            (20, hex("a8ba0048d75084603eb8423a09b7bf7595c871f6")),
+        ] {
+            assert_eq!(
+                aes_xts_encrypt(&key1, &key2, sector, &data[..len]).unwrap(),
+                ciphertext
+            );
+            assert_eq!(
+                aes_xts_decrypt(&key1, &key2, sector, &ciphertext).unwrap(),
+                &data[..len]
+            );
+        }
+
+        // a sector must be at least one block long
+        assert!(aes_xts_encrypt(&key1, &key2, sector, &data[..15]).is_err());
+    }
+
+    #[quickcheck]
+    fn xts_round_trip(data: Vec<u8>, sector: u128) -> bool {
+        let key1 = [0x42; AES_256_KEY_SIZE];
+        let key2 = [0x24; AES_256_KEY_SIZE];
+        if data.len() < AES_BLOCK_SIZE {
+            return true;
+        }
+
+        let encrypted = aes_xts_encrypt(&key1, &key2, sector, &data).expect("Encryption failed");
+        encrypted.len() == data.len()
+            && aes_xts_decrypt(&key1, &key2, sector, &encrypted).expect("Decryption failed") == data
+    }
+
+    // test vectors from RFC 3962, appendix B
+    #[test]
+    fn cbc_cts() {
+        let key = b"chicken teriyaki";
+        let iv = [0; AES_BLOCK_SIZE];
+        let message = b"I would like the General Gau's Chicken, please, and wonton soup.";
+
+        for (len, expected) in [
+            (17, "c6353568f2bf8cb4d8a580362da7ff7f97"),
+            (31, "fc00783e0efdb2c1d445d4c8eff7ed2297687268d6ecccc0c07b25e25ecfe5"),
+            (32, "39312523a78662d5be7fcbcc98ebf5a897687268d6ecccc0c07b25e25ecfe584"),
+            (47, "97687268d6ecccc0c07b25e25ecfe584b3fffd940c16a18c1b5549d2f838029e39312523a78662d5be7fcbcc98ebf5"),
+            (48, "97687268d6ecccc0c07b25e25ecfe5849dad8bbb96c4cdc03bc103e1a194bbd839312523a78662d5be7fcbcc98ebf5a8"),
+            (64, "97687268d6ecccc0c07b25e25ecfe58439312523a78662d5be7fcbcc98ebf5a84807efe836ee89a526730dbc2f7bc8409dad8bbb96c4cdc03bc103e1a194bbd8"),
+        ] {
+            let ciphertext = aes_cbc_cts_encrypt(&message[..len], key, &iv).unwrap();
+            assert_eq!(ciphertext, hex(expected));
+            assert_eq!(aes_cbc_cts_decrypt(&ciphertext, key, &iv).unwrap(), &message[..len]);
+        }
+
+        let key = [0x17; AES_256_KEY_SIZE];
+        let iv = [0x99; AES_BLOCK_SIZE];
+        let data = (0..48).map(|i| i as u8).collect::<Vec<_>>();
+        for len in 16..=48 {
+            let ciphertext = aes_cbc_cts_encrypt(&data[..len], &key, &iv).unwrap();
+            assert_eq!(ciphertext.len(), len);
+            assert_eq!(
+                aes_cbc_cts_decrypt(&ciphertext, &key, &iv).unwrap(),
+                &data[..len]
+            );
+        }
+
+        assert_eq!(
+            aes_cbc_cts_encrypt(&data[..15], &key, &iv),
+            Err(AesError::InvalidBlockLength(15))
+        );
+        assert_eq!(
+            aes_cbc_cts_decrypt(&data[..15], &key, &iv),
+            Err(AesError::InvalidBlockLength(15))
+        );
+    }
+
+    #[test]
+    fn streaming() {
+        use std::io::Cursor;
+
+        let key = [0x3c; AES_192_KEY_SIZE];
+        let iv = [0x5a; AES_BLOCK_SIZE];
+        let data = (0..3 * STREAM_BUFFER_SIZE as u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 24) as u8)
+            .collect::<Vec<_>>();
+
+        for len in [
+            0,
+            1,
+            15,
+            16,
+            17,
+            1000,
+            STREAM_BUFFER_SIZE,
+            2 * STREAM_BUFFER_SIZE + 3,
+        ] {
+            let data = &data[..len];
+
+            for mode in [StreamMode::Cbc, StreamMode::Ctr] {
+                let mut ciphertext = vec![];
+                let written =
+                    encrypt_reader(Cursor::new(data), &mut ciphertext, &key, &iv, mode).unwrap();
+                assert_eq!(written, ciphertext.len() as u64);
+
+                let expected = match mode {
+                    StreamMode::Cbc => {
+                        aes_cbc_encrypt(&pkcs7_pad(data, AES_BLOCK_SIZE), &key, &iv).unwrap()
+                    }
+                    StreamMode::Ctr => aes_ctr(data, &key, &iv).unwrap(),
+                };
+                assert_eq!(ciphertext, expected);
+
+                let mut plaintext = vec![];
+                decrypt_reader(Cursor::new(&ciphertext), &mut plaintext, &key, &iv, mode).unwrap();
+                assert_eq!(plaintext, data);
+            }
+        }
+
+        // a truncated CBC ciphertext
+        let mut ciphertext = vec![];
+        encrypt_reader(
+            Cursor::new(&data[..40]),
+            &mut ciphertext,
+            &key,
+            &iv,
+            StreamMode::Cbc,
+        )
+        .unwrap();
+        let error = decrypt_reader(
+            Cursor::new(&ciphertext[..40]),
+            io::sink(),
+            &key,
+            &iv,
+            StreamMode::Cbc,
+        )
+        .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let error = encrypt_reader(Cursor::new(data), io::sink(), &[0; 7], &iv, StreamMode::Ctr)
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn pkcs7() {
+        assert_eq!(
+            pkcs7_pad(b"YELLOW SUBMARINE", 20),
+            b"YELLOW SUBMARINE\x04\x04\x04\x04"
+        );
+        assert_eq!(pkcs7_pad(b"", 4), [4, 4, 4, 4]);
+
+        // aligned data gets a whole extra block
+        let padded = pkcs7_pad(&[0x42; 16], AES_BLOCK_SIZE);
+        assert_eq!(padded.len(), 32);
+        assert_eq!(padded[16..], [16; 16]);
+        assert_eq!(pkcs7_unpad(&padded, AES_BLOCK_SIZE).unwrap(), [0x42; 16]);
+
+        for len in 0..40 {
+            let data = (0..len).collect::<Vec<u8>>();
+            let padded = pkcs7_pad(&data, AES_BLOCK_SIZE);
+            assert_eq!(padded.len() % AES_BLOCK_SIZE, 0);
+            assert_eq!(pkcs7_unpad(&padded, AES_BLOCK_SIZE).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn pkcs7_corrupted() {
+        let padded = pkcs7_pad(b"YELLOW SUB", AES_BLOCK_SIZE);
+
+        // a padding byte that doesn't match the length
+        let mut corrupted = padded.clone();
+        corrupted[12] ^= 1;
+        assert!(pkcs7_unpad(&corrupted, AES_BLOCK_SIZE).is_err());
+
+        // lengths of zero or more than a block
+        let mut corrupted = padded.clone();
+        corrupted[15] = 0;
+        assert!(pkcs7_unpad(&corrupted, AES_BLOCK_SIZE).is_err());
+        corrupted[15] = 17;
+        assert!(pkcs7_unpad(&corrupted, AES_BLOCK_SIZE).is_err());
+
+        // unaligned or empty data
+        assert!(pkcs7_unpad(&padded[..15], AES_BLOCK_SIZE).is_err());
+        assert!(pkcs7_unpad(&[], AES_BLOCK_SIZE).is_err());
+    }
+
+    #[test]
+    fn ct_lookup_matches_table() {
+        use crate::util::ct_lookup;
+
+        for i in 0..=255 {
+            assert_eq!(ct_lookup(&S_BOX, i), S_BOX[i as usize]);
+            assert_eq!(ct_lookup(&INV_S_BOX, i), INV_S_BOX[i as usize]);
+        }
+    }
+
+    #[cfg(all(feature = "aes-ni", target_arch = "x86_64"))]
+    #[quickcheck]
+    fn aes_ni_matches_software(block: Vec<u8>, key: Vec<u8>) -> bool {
+        if !is_x86_feature_detected!("aes") || block.len() < 16 || key.len() < 32 {
+            return true;
+        }
+        let block: [u8; 16] = block[..16].try_into().unwrap();
+
+        [AES_128_KEY_SIZE, AES_192_KEY_SIZE, AES_256_KEY_SIZE]
+            .into_iter()
+            .all(|len| {
+                let aes = Aes::new(&key[..len]).unwrap();
+
+                let mut software = block;
+                aes.encrypt_block_software(&mut software);
+                let mut hardware = block;
+                aes.encrypt_block_in_place(&mut hardware);
+                let encrypted = software == hardware;
+
+                aes.decrypt_block_software(&mut software);
+                aes.decrypt_block_in_place(&mut hardware);
+                encrypted && software == block && hardware == block
+            })
+    }
+
+    #[cfg(all(feature = "t-tables", not(feature = "ct-aes")))]
+    #[quickcheck]
+    fn t_tables_match_software(block: Vec<u8>, key: Vec<u8>) -> bool {
+        if block.len() < 16 || key.len() < 32 {
+            return true;
+        }
+        let block: [u8; 16] = block[..16].try_into().unwrap();
+
+        [AES_128_KEY_SIZE, AES_192_KEY_SIZE, AES_256_KEY_SIZE]
+            .into_iter()
+            .all(|len| {
+                let aes = Aes::new(&key[..len]).unwrap();
+
+                let mut software = block;
+                aes.encrypt_block_software(&mut software);
+                let mut tables = block;
+                t_table_encrypt(&aes.expanded_key, aes.nr, &mut tables);
+                software == tables
+            })
+    }
+
+    #[quickcheck]
+    fn equivalent_inverse_cipher(block: Vec<u8>, key: Vec<u8>) -> bool {
+        if block.len() < 16 || key.len() < 32 {
+            return true;
+        }
+        let block: [u8; 16] = block[..16].try_into().unwrap();
+
+        [AES_128_KEY_SIZE, AES_192_KEY_SIZE, AES_256_KEY_SIZE]
+            .into_iter()
+            .all(|len| {
+                let key = &key[..len];
+                let aes = Aes::new_for_decrypt(key).unwrap();
+
+                let mut software = block;
+                aes.decrypt_block_software(&mut software);
+                software == aes_dec_block(&block, key).unwrap()
+                    && aes.decrypt_block(&block) == software
+                    && aes.decrypt_block(&aes.encrypt_block(&block)) == block
+            })
+    }
+
+    #[test]
+    fn round_keys() {
+        for key in [
+            &hex("000102030405060708090a0b0c0d0e0f")[..],
+            &hex("000102030405060708090a0b0c0d0e0f1011121314151617")[..],
+            &hex("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f")[..],
+        ] {
+            let round_keys = expand_key(key).unwrap();
+            assert_eq!(round_keys.rounds, key.len() / 4 + 6);
+            // the first nk words are the key
+            assert_eq!(&round_keys.data[..key.len()], key);
+            assert_eq!(round_keys.data, Aes::new(key).unwrap().expanded_key);
+        }
+
+        // the last round key for AES-128 from FIPS 197, appendix A.1
+        let round_keys = expand_key(&hex("2b7e151628aed2a6abf7158809cf4f3c")).unwrap();
+        assert_eq!(
+            round_keys.round_key(10).to_vec(),
+            hex("d014f9a8c9ee2589e13f0cc8b6630ca6")
+        );
+        assert!(round_keys.data[16 * 11..].iter().all(|&b| b == 0));
+
+        assert_eq!(
+            expand_key(&[0; 20]).err(),
+            Some(AesError::InvalidKeyLength(20))
+        );
+    }
+
+    #[test]
+    fn s_box_matches_table() {
+        // with `ct-aes` this checks the arithmetic S-box against the tables
+        for i in 0..=255 {
+            assert_eq!(s_box(i), S_BOX[i as usize]);
+            assert_eq!(inv_s_box(i), INV_S_BOX[i as usize]);
+        }
+    }
+
+    #[test]
+    fn cached_key_schedule() {
+        for key in [
+            &[0x11; AES_128_KEY_SIZE][..],
+            &[0x22; AES_192_KEY_SIZE][..],
+            &[0x33; AES_256_KEY_SIZE][..],
+        ] {
+            let aes = Aes::new(key).unwrap();
+            assert_eq!(aes.nk() * 4, key.len());
+
+            let mut block = [0x42; AES_BLOCK_SIZE];
+            for _ in 0..10 {
+                let encrypted = aes.encrypt_block(&block);
+                assert_eq!(encrypted, aes_enc_block(&block, key).unwrap());
+                assert_eq!(aes.decrypt_block(&encrypted), block);
+                block = encrypted;
+            }
+        }
+
+        assert!(Aes::new(&[0; 15]).is_err());
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn zeroize_on_drop() {
+        use std::mem::ManuallyDrop;
+
+        // drop in place, so the memory is still ours to look at afterwards
+        let mut aes = ManuallyDrop::new(Aes::new(&[0x5a; AES_256_KEY_SIZE]).unwrap());
+        let schedule = aes.expanded_key.as_ptr();
+        assert!(aes.expanded_key.iter().any(|&b| b != 0));
+
+        unsafe {
+            ManuallyDrop::drop(&mut aes);
+            for i in 0..240 {
+                assert_eq!(schedule.add(i).read_volatile(), 0);
+            }
+        }
+    }
+
+    #[quickcheck]
+    fn in_place_matches_allocating(block: Vec<u8>, key: Vec<u8>) -> bool {
+        if block.len() < 16 || key.len() < 32 {
+            return true;
+        }
+        let block: [u8; 16] = block[..16].try_into().unwrap();
+        let key = &key[..32];
+
+        let mut in_place = block;
+        aes_enc_block_in_place(&mut in_place, key).expect("Encryption failed");
+        let encrypted = in_place == aes_enc_block(&block, key).expect("Encryption failed");
+
+        aes_dec_block_in_place(&mut in_place, key).expect("Decryption failed");
+        encrypted && in_place == block
+    }
+
+    #[test]
+    fn batch_blocks() {
+        let key = [0x42; AES_192_KEY_SIZE];
+        let data = (0..64).collect::<Vec<u8>>();
+
+        let mut batch = data.clone();
+        aes_enc_blocks(&mut batch, &key).unwrap();
+
+        let mut looped = vec![];
+        for chunk in data.chunks(AES_BLOCK_SIZE) {
+            looped.extend(aes_enc_block(chunk.try_into().unwrap(), &key).unwrap());
+        }
+        assert_eq!(batch, looped);
+
+        aes_dec_blocks(&mut batch, &key).unwrap();
+        assert_eq!(batch, data);
+
+        assert!(aes_enc_blocks(&mut batch[..20], &key).is_err());
+    }
+
+    #[test]
+    fn derived_nonces() {
+        let key = [0x42; AES_128_KEY_SIZE];
+
+        let nonces = (0..1000)
+            .map(|i| derive_nonce(&key, i).unwrap())
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(nonces.len(), 1000);
+
+        assert_eq!(
+            derive_nonce(&key, 7).unwrap(),
+            derive_nonce(&key, 7).unwrap()
+        );
+        assert_ne!(
+            derive_nonce(&key, 7).unwrap(),
+            derive_nonce(&[0x24; AES_128_KEY_SIZE], 7).unwrap()
+        );
+        assert!(derive_nonce(&key[..10], 7).is_err());
+    }
+
+    #[test]
+    fn random_keys() {
+        let key = generate_key_128();
+        assert_ne!(key, generate_key_128());
+        assert_ne!(generate_key_256(), generate_key_256());
+        assert_ne!(generate_iv(), generate_iv());
+
+        let iv = generate_iv();
+        let ciphertext = aes_cbc_encrypt(&[0x42; 32], &key, &iv).unwrap();
+        assert_eq!(aes_cbc_decrypt(&ciphertext, &key, &iv).unwrap(), [0x42; 32]);
+    }
+
+    #[test]
+    fn typed_key_sizes() {
+        let block = [0x42; AES_BLOCK_SIZE];
+
+        let key = [0x11; AES_128_KEY_SIZE];
+        let aes = Aes128::new(&key);
+        let encrypted = aes.encrypt_block(&block);
+        assert_eq!(encrypted, aes_enc_block(&block, &key).unwrap());
+        assert_eq!(aes.decrypt_block(&encrypted), block);
+
+        let key = [0x22; AES_192_KEY_SIZE];
+        let aes = Aes192::new(&key);
+        let encrypted = aes.encrypt_block(&block);
+        assert_eq!(encrypted, aes_enc_block(&block, &key).unwrap());
+        assert_eq!(aes.decrypt_block(&encrypted), block);
+
+        let key = [0x33; AES_256_KEY_SIZE];
+        let aes = Aes256::new(&key);
+        let mut in_place = block;
+        aes.encrypt_block_in_place(&mut in_place);
+        assert_eq!(in_place, aes_enc_block(&block, &key).unwrap());
+        assert_eq!(Aes::from(aes).nr(), 14);
+    }
+
+    #[test]
+    fn errors() {
+        let block = [0; AES_BLOCK_SIZE];
+        assert_eq!(
+            aes_enc_block(&block, &[0; 15]),
+            Err(AesError::InvalidKeyLength(15))
+        );
+        assert_eq!(
+            aes_dec_block(&block, &[0; 33]),
+            Err(AesError::InvalidKeyLength(33))
+        );
+
+        let key = [0; AES_128_KEY_SIZE];
+        assert_eq!(
+            aes_cbc_encrypt(&[0; 20], &key, &block),
+            Err(AesError::InvalidBlockLength(20))
+        );
+        assert_eq!(
+            aes_ccm_open(&key, &[0; 13], &[], &[0; 20], 8),
+            Err(AesError::AuthenticationFailed)
+        );
+        assert_eq!(
+            AesError::InvalidKeyLength(15).to_string(),
+            "Invalid key length. Expected 16, 24, or 32 bytes, got 15 bytes"
+        );
+    }
+
+    // test vectors from the GCM spec, test cases 2, 4 and 6
+    #[test]
+    fn gcm_seal_and_open() {
+        let key = [0u8; AES_128_KEY_SIZE];
+        let nonce = [0u8; 12];
+        let sealed = hex("0388dace60b6a392f328c2b971b2fe78ab6e47d42cec13bdf53a67b21257bddf");
+        assert_eq!(aes_gcm_seal(&key, &nonce, &[], &[0; 16]).unwrap(), sealed);
+        assert_eq!(aes_gcm_open(&key, &nonce, &[], &sealed).unwrap(), [0; 16]);
+
+        let key = hex("feffe9928665731c6d6a8f9467308308");
+        let aad = hex("feedfacedeadbeeffeedfacedeadbeefabaddad2");
+        let plaintext = hex(concat!(
+            "d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a72",
+            "1c3c0c95956809532fcf0e2449a6b525b16aedf5aa0de657ba637b39"
+        ));
+
+        let nonce = hex("cafebabefacedbaddecaf888");
+        let mut sealed = hex(concat!(
+            "42831ec2217774244b7221b784d0d49ce3aa212f2c02a4e035c17e2329aca12e",
+            "21d514b25466931c7d8f6a5aac84aa051ba30b396a0aac973d58e091",
+            "5bc94fbc3221a5db94fae95ae7121a47"
+        ));
+        assert_eq!(
+            aes_gcm_seal(&key, &nonce, &aad, &plaintext).unwrap(),
+            sealed
+        );
+        assert_eq!(
+            aes_gcm_open(&key, &nonce, &aad, &sealed).unwrap(),
+            plaintext
+        );
+
+        // nonces that aren't 96 bits are hashed into the counter block
+        let long_nonce = hex(concat!(
+            "9313225df88406e555909c5aff5269aa6a7a9538534f7da1e4c303d2a318a728",
+            "c3c0c95156809539fcf0e2429a6b525416aedbf5a0de6a57a637b39b"
+        ));
+        let long_sealed = hex(concat!(
+            "8ce24998625615b603a033aca13fb894be9112a5c3a211a8ba262a3cca7e2ca7",
+            "01e4a9a4fba43c90ccdcb281d48c7c6fd62875d2aca417034c34aee5",
+            "619cc5aefffe0bfa462af43c1699d050"
+        ));
+        assert_eq!(
+            aes_gcm_seal(&key, &long_nonce, &aad, &plaintext).unwrap(),
+            long_sealed
+        );
+
+        // tampering fails authentication
+        sealed[0] ^= 1;
+        assert_eq!(
+            aes_gcm_open(&key, &nonce, &aad, &sealed),
+            Err(AesError::AuthenticationFailed)
+        );
     }
 }
@@ -1,29 +1,469 @@
 use const_for::const_for;
-use gf256::p64;
+use gf256::{p128, p64};
+use std::hash::Hasher;
 
 const POLYNOMIAL: p64 = p64(0x104c11db7);
+const CASTAGNOLI_POLYNOMIAL: p64 = p64(0x11edc6f41);
+const CCITT_POLYNOMIAL: p64 = p64(0x11021);
+// ECMA-182's polynomial has degree 64, so it needs 65 bits
+const ECMA_POLYNOMIAL: p128 = p128(0x1_42f0e1eb_a9ea3693);
 
-const CRC_TABLE: [u32; 256] = {
+// Table for CRCs that process the least significant bit of each byte first (reflected)
+const fn reflected_table(polynomial: p64) -> [u32; 256] {
     let mut table = [0; 256];
     const_for!(i in 0..table.len() => {
         let x = (i as u32).reverse_bits();
-        let x = p64((x as u64) << 8).naive_rem(POLYNOMIAL).0 as u32;
+        let x = p64((x as u64) << 8).naive_rem(polynomial).0 as u32;
         table[i] = x.reverse_bits();
     });
 
     table
-};
+}
 
-pub fn crc32(data: &[u8]) -> u32 {
+// Table for CRCs that process the most significant bit of each byte first
+const fn table(polynomial: p64) -> [u32; 256] {
+    let mut table = [0; 256];
+    const_for!(i in 0..table.len() => {
+        table[i] = p64((i as u64) << 32).naive_rem(polynomial).0 as u32;
+    });
+
+    table
+}
+
+// Like `table`, for 16-bit CRCs
+const fn table16(polynomial: p64) -> [u16; 256] {
+    let mut table = [0; 256];
+    const_for!(i in 0..table.len() => {
+        table[i] = p64((i as u64) << 16).naive_rem(polynomial).0 as u16;
+    });
+
+    table
+}
+
+// Like `reflected_table`, for 64-bit CRCs
+const fn reflected_table64(polynomial: p128) -> [u64; 256] {
+    let mut table = [0; 256];
+    const_for!(i in 0..table.len() => {
+        let x = (i as u64).reverse_bits();
+        let x = p128((x as u128) << 8).naive_rem(polynomial).0 as u64;
+        table[i] = x.reverse_bits();
+    });
+
+    table
+}
+
+const CRC_TABLE: [u32; 256] = reflected_table(POLYNOMIAL);
+const CRC_MSB_TABLE: [u32; 256] = table(POLYNOMIAL);
+const CASTAGNOLI_TABLE: [u32; 256] = reflected_table(CASTAGNOLI_POLYNOMIAL);
+const CCITT_TABLE: [u16; 256] = table16(CCITT_POLYNOMIAL);
+const ECMA_TABLE: [u64; 256] = reflected_table64(ECMA_POLYNOMIAL);
+
+fn reflected_crc(table: &[u32; 256], data: &[u8]) -> u32 {
     let mut crc = 0xffffffff;
 
     for b in data {
-        crc = (crc >> 8) ^ CRC_TABLE[usize::from((crc as u8) ^ b)];
+        crc = (crc >> 8) ^ table[usize::from((crc as u8) ^ b)];
     }
 
     crc ^ 0xffffffff
 }
 
+fn msb_crc(table: &[u32; 256], data: &[u8]) -> u32 {
+    let mut crc = 0xffffffff;
+
+    for b in data {
+        crc = (crc << 8) ^ table[usize::from(((crc >> 24) as u8) ^ b)];
+    }
+
+    crc
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    reflected_crc(&CRC_TABLE, data)
+}
+
+/// The same as `crc32`, but usable in constants, so checksums of static data can be computed at
+/// compile time.
+pub const fn crc32_const(data: &[u8]) -> u32 {
+    let mut crc = 0xffffffff;
+
+    const_for!(i in 0..data.len() => {
+        crc = (crc >> 8) ^ CRC_TABLE[((crc as u8) ^ data[i]) as usize];
+    });
+
+    crc ^ 0xffffffff
+}
+
+// crc32(m || crc32(m) as little-endian bytes), for any m
+const CRC32_RESIDUE: u32 = 0x2144df1c;
+
+/// Checks a buffer that ends with the `crc32` of the rest of it, the way Ethernet checks frames.
+///
+/// Appending a message's CRC to it, least significant byte first, gives data whose CRC is always
+/// the same constant (the residue), because the appended CRC cancels the remainder. So the whole
+/// buffer can be checked in one pass without splitting off the trailer. The trailer has to be
+/// little-endian, the order `crc32` shifts bits out in; a big-endian trailer doesn't cancel out.
+pub fn crc32_valid(data_with_crc: &[u8]) -> bool {
+    data_with_crc.len() >= 4 && crc32(data_with_crc) == CRC32_RESIDUE
+}
+
+/// Computes the same CRC as `crc32` over data that arrives in pieces, without concatenating it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crc32 {
+    // the running register, before the final xor
+    crc: u32,
+}
+
+impl Crc32 {
+    pub fn new() -> Self {
+        Self { crc: 0xffffffff }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for b in data {
+            self.crc = (self.crc >> 8) ^ CRC_TABLE[usize::from((self.crc as u8) ^ b)];
+        }
+    }
+
+    pub fn finalize(self) -> u32 {
+        self.crc ^ 0xffffffff
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// CRC-32 as a `std::hash::Hasher`, for use with `HashMap` through
+/// `BuildHasherDefault<Crc32Hasher>`, or anywhere else that takes a `Hasher`. It isn't resistant
+/// to collisions chosen by an attacker, so it shouldn't be used for maps keyed by untrusted input.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Crc32Hasher(Crc32);
+
+impl Hasher for Crc32Hasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        u64::from(self.0.finalize())
+    }
+}
+
+// Multiplies a 32x32 matrix over GF(2), stored as columns, by a vector
+fn gf2_matrix_times(matrix: &[u32; 32], mut vector: u32) -> u32 {
+    let mut sum = 0;
+    for column in matrix {
+        if vector == 0 {
+            break;
+        }
+        if vector & 1 != 0 {
+            sum ^= column;
+        }
+        vector >>= 1;
+    }
+    sum
+}
+
+fn gf2_matrix_square(matrix: &[u32; 32]) -> [u32; 32] {
+    let mut square = [0; 32];
+    for (s, column) in square.iter_mut().zip(matrix) {
+        *s = gf2_matrix_times(matrix, *column);
+    }
+    square
+}
+
+/// Computes `crc32(a || b)` from `crc32(a)`, `crc32(b)` and the length of `b`, without the data.
+/// This lets chunks of a buffer be checksummed separately, say by parallel workers, and combined
+/// afterwards.
+///
+/// The CRC is linear, so appending `len2` bytes to `a` transforms its CRC by a fixed linear map:
+/// feeding in `len2` zero bytes, which is a 32x32 matrix over GF(2). The matrix for one zero bit is
+/// easy to write down, and squaring it repeatedly gives the matrices for 2, 4, 8, ... zero bits,
+/// so the ones needed for the bits of `len2` can be applied in $O(\log len2)$ steps. This is how
+/// zlib does it.
+pub fn crc32_combine(crc1: u32, crc2: u32, len2: usize) -> u32 {
+    if len2 == 0 {
+        return crc1;
+    }
+
+    // the operator for one zero bit: shift right, and XOR in the reflected polynomial if the
+    // bit shifted out was set
+    let mut odd = [0; 32];
+    odd[0] = 0xedb88320;
+    for (i, column) in odd.iter_mut().enumerate().skip(1) {
+        *column = 1 << (i - 1);
+    }
+
+    // two zero bits, then four
+    let mut even = gf2_matrix_square(&odd);
+    odd = gf2_matrix_square(&even);
+
+    // apply len2 zero bytes, one bit of len2 at a time, starting from one byte (eight bits)
+    let mut crc1 = crc1;
+    let mut len2 = len2;
+    loop {
+        even = gf2_matrix_square(&odd);
+        if len2 & 1 != 0 {
+            crc1 = gf2_matrix_times(&even, crc1);
+        }
+        len2 >>= 1;
+        if len2 == 0 {
+            break;
+        }
+
+        odd = gf2_matrix_square(&even);
+        if len2 & 1 != 0 {
+            crc1 = gf2_matrix_times(&odd, crc1);
+        }
+        len2 >>= 1;
+        if len2 == 0 {
+            break;
+        }
+    }
+
+    crc1 ^ crc2
+}
+
+/// CRC-32C, with the Castagnoli polynomial `0x1edc6f41`. It detects more errors than `crc32`'s
+/// polynomial for typical message lengths, and is what iSCSI, ext4, SCTP and many databases use.
+/// x86-64 and ARMv8 have instructions for it.
+pub fn crc32c(data: &[u8]) -> u32 {
+    reflected_crc(&CASTAGNOLI_TABLE, data)
+}
+
+/// The 16-bit CRC with polynomial `0x1021` and initial value `0xffff`, processed most significant
+/// bit first, commonly called CRC-16-CCITT (CRC-16/IBM-3740 in the CRC catalogue). XMODEM and
+/// Bluetooth use the same polynomial with different initial values.
+pub fn crc16_ccitt(data: &[u8]) -> u16 {
+    let mut crc = 0xffff;
+
+    for b in data {
+        crc = (crc << 8) ^ CCITT_TABLE[usize::from(((crc >> 8) as u8) ^ b)];
+    }
+
+    crc
+}
+
+/// A 64-bit CRC with the ECMA-182 polynomial, in the reflected form used by xz and Go's
+/// `crc64.ECMA` (CRC-64/XZ in the CRC catalogue). With 64 bits, accidental collisions are rare
+/// enough for deduplicating or checking very large amounts of data, where a 32-bit CRC would see
+/// one every few billion blocks.
+pub fn crc64(data: &[u8]) -> u64 {
+    let mut crc = u64::MAX;
+
+    for b in data {
+        crc = (crc >> 8) ^ ECMA_TABLE[usize::from((crc as u8) ^ b)];
+    }
+
+    crc ^ u64::MAX
+}
+
+/// The commonly used 32-bit CRC variants, named after their entries in the
+/// [CRC catalogue](https://reveng.sourceforge.io/crc-catalogue/17plus.htm).
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CrcVariant {
+    /// The CRC used by zip, gzip, png and ethernet, computed by `crc32`.
+    IsoHdlc,
+    /// The same polynomial as `IsoHdlc`, processed most significant bit first.
+    Bzip2,
+    /// Like `Bzip2`, but without the final xor.
+    Mpeg2,
+    /// CRC-32C, used by iSCSI, ext4 and SCTP.
+    Castagnoli,
+}
+
+impl CrcVariant {
+    pub const ALL: [CrcVariant; 4] = [
+        CrcVariant::IsoHdlc,
+        CrcVariant::Bzip2,
+        CrcVariant::Mpeg2,
+        CrcVariant::Castagnoli,
+    ];
+
+    pub fn checksum(self, data: &[u8]) -> u32 {
+        match self {
+            CrcVariant::IsoHdlc => reflected_crc(&CRC_TABLE, data),
+            CrcVariant::Bzip2 => msb_crc(&CRC_MSB_TABLE, data) ^ 0xffffffff,
+            CrcVariant::Mpeg2 => msb_crc(&CRC_MSB_TABLE, data),
+            CrcVariant::Castagnoli => crc32c(data),
+        }
+    }
+}
+
+/// Finds which CRC-32 variant produces `expected` for `data`, if any. This is useful when reading
+/// file formats that don't say which checksum they use.
+pub fn detect_crc32(data: &[u8], expected: u32) -> Option<CrcVariant> {
+    CrcVariant::ALL
+        .into_iter()
+        .find(|variant| variant.checksum(data) == expected)
+}
+
+/// A CRC with arbitrary parameters, using the model from the
+/// [CRC catalogue](https://reveng.sourceforge.io/crc-catalogue/). Any CRC from 8 to 64 bits wide
+/// can be described by:
+///
+/// - `poly`, the generator polynomial without its leading $x^{width}$ term
+/// - `init`, the initial value of the register
+/// - `reflect_in`, whether each byte is processed least significant bit first
+/// - `reflect_out`, whether the final register is bit reversed
+/// - `xor_out`, a value XORed into the result
+///
+/// `Crc::new` starts with everything but the width and polynomial zeroed or off, and the other
+/// methods set the rest. The CRC-32 computed by `crc32` is
+/// `Crc::new(32, 0x04c11db7).init(0xffffffff).reflect_in(true).reflect_out(true).xor_out(0xffffffff)`.
+#[derive(Debug, Clone)]
+pub struct Crc {
+    width: u32,
+    poly: u64,
+    init: u64,
+    reflect_in: bool,
+    reflect_out: bool,
+    xor_out: u64,
+    table: [u64; 256],
+}
+
+impl Crc {
+    pub fn new(width: u32, poly: u64) -> Self {
+        assert!((8..=64).contains(&width), "unsupported CRC width {}", width);
+
+        let mut crc = Self {
+            width,
+            poly: poly & Self::mask(width),
+            init: 0,
+            reflect_in: false,
+            reflect_out: false,
+            xor_out: 0,
+            table: [0; 256],
+        };
+        crc.table = crc.generate_table();
+        crc
+    }
+
+    pub fn init(mut self, init: u64) -> Self {
+        self.init = init & Self::mask(self.width);
+        self
+    }
+
+    pub fn reflect_in(mut self, reflect_in: bool) -> Self {
+        self.reflect_in = reflect_in;
+        self.table = self.generate_table();
+        self
+    }
+
+    pub fn reflect_out(mut self, reflect_out: bool) -> Self {
+        self.reflect_out = reflect_out;
+        self
+    }
+
+    pub fn xor_out(mut self, xor_out: u64) -> Self {
+        self.xor_out = xor_out & Self::mask(self.width);
+        self
+    }
+
+    fn mask(width: u32) -> u64 {
+        u64::MAX >> (64 - width)
+    }
+
+    fn reflect(&self, x: u64) -> u64 {
+        x.reverse_bits() >> (64 - self.width)
+    }
+
+    // The same construction as `table` and `reflected_table`, for any width
+    fn generate_table(&self) -> [u64; 256] {
+        let polynomial = p128((1 << self.width) | u128::from(self.poly));
+
+        let mut table = [0; 256];
+        for (i, entry) in table.iter_mut().enumerate() {
+            let i = if self.reflect_in {
+                (i as u8).reverse_bits()
+            } else {
+                i as u8
+            };
+            let x = p128(u128::from(i) << self.width).naive_rem(polynomial).0 as u64;
+            *entry = if self.reflect_in { self.reflect(x) } else { x };
+        }
+
+        table
+    }
+
+    pub fn checksum(&self, data: &[u8]) -> u64 {
+        // reflected CRCs keep the register bit reversed, so bytes go in at the bottom
+        let mut crc = if self.reflect_in {
+            self.reflect(self.init)
+        } else {
+            self.init
+        };
+
+        for &b in data {
+            crc = if self.reflect_in {
+                (crc >> 8) ^ self.table[usize::from((crc as u8) ^ b)]
+            } else {
+                let top = (crc >> (self.width - 8)) as u8;
+                ((crc << 8) ^ self.table[usize::from(top ^ b)]) & Self::mask(self.width)
+            };
+        }
+
+        if self.reflect_in != self.reflect_out {
+            crc = self.reflect(crc);
+        }
+        crc ^ self.xor_out
+    }
+}
+
+/// The weak rolling checksum from the rsync algorithm, an Adler-32 style pair of sums.
+///
+/// For a window $x_1 \ldots x_n$, $a = \sum x_i$ and $b = \sum (n - i + 1) x_i$, both mod $2^{16}$.
+/// Sliding the window by one byte only needs the byte leaving and the byte entering:
+/// $a' = a - x_1 + x_{n+1}$ and $b' = b - n x_1 + a'$, so checksumming every offset of a file is
+/// linear rather than quadratic. Matches are confirmed with a strong hash afterwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RollingChecksum {
+    a: u32,
+    b: u32,
+    len: u32,
+}
+
+impl RollingChecksum {
+    pub fn new(window: &[u8]) -> Self {
+        let len = window.len() as u32;
+        let mut a = 0u32;
+        let mut b = 0u32;
+
+        for (i, x) in window.iter().enumerate() {
+            a = a.wrapping_add(u32::from(*x));
+            b = b.wrapping_add((len - i as u32).wrapping_mul(u32::from(*x)));
+        }
+
+        Self {
+            a: a & 0xffff,
+            b: b & 0xffff,
+            len,
+        }
+    }
+
+    /// Slides the window forward by one byte, removing `out_byte` from the front and adding
+    /// `in_byte` to the end.
+    pub fn roll(&mut self, out_byte: u8, in_byte: u8) {
+        self.a = self
+            .a
+            .wrapping_sub(u32::from(out_byte))
+            .wrapping_add(u32::from(in_byte))
+            & 0xffff;
+        self.b = self
+            .b
+            .wrapping_sub(self.len.wrapping_mul(u32::from(out_byte)))
+            .wrapping_add(self.a)
+            & 0xffff;
+    }
+
+    pub fn digest(&self) -> u32 {
+        (self.b << 16) | self.a
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -34,4 +474,182 @@ mod tests {
         let expected = 0x1c291ca3;
         assert_eq!(crc32(input), expected);
     }
+
+    #[test]
+    fn const_crc() {
+        const CHECK: u32 = crc32_const(b"Hello World!");
+        assert_eq!(CHECK, 0x1c291ca3);
+        assert_eq!(crc32_const(b"123456789"), crc32(b"123456789"));
+    }
+
+    #[test]
+    fn residue() {
+        let message = b"Hello World!";
+        let mut framed = message.to_vec();
+        framed.extend_from_slice(&crc32(message).to_le_bytes());
+        assert!(crc32_valid(&framed));
+        assert!(crc32_valid(&0u32.to_le_bytes()));
+
+        for i in 0..framed.len() {
+            for bit in 0..8 {
+                framed[i] ^= 1 << bit;
+                assert!(!crc32_valid(&framed));
+                framed[i] ^= 1 << bit;
+            }
+        }
+
+        assert!(!crc32_valid(&framed[..3]));
+        assert!(!crc32_valid(&framed[1..]));
+    }
+
+    #[test]
+    fn incremental() {
+        let mut crc = Crc32::new();
+        crc.update(b"Hello ");
+        crc.update(b"");
+        crc.update(b"World!");
+        assert_eq!(crc.finalize(), crc32(b"Hello World!"));
+        assert_eq!(Crc32::default().finalize(), crc32(b""));
+    }
+
+    #[test]
+    fn hasher() {
+        use std::collections::HashMap;
+        use std::hash::BuildHasherDefault;
+
+        let mut hasher = Crc32Hasher::default();
+        hasher.write(b"Hello ");
+        hasher.write(b"World!");
+        assert_eq!(hasher.finish(), u64::from(crc32(b"Hello World!")));
+        // finish doesn't reset the state
+        assert_eq!(hasher.finish(), u64::from(crc32(b"Hello World!")));
+
+        let mut map = HashMap::<_, _, BuildHasherDefault<Crc32Hasher>>::default();
+        map.insert("key", 1);
+        assert_eq!(map.get("key"), Some(&1));
+    }
+
+    #[test]
+    fn combine() {
+        let data = (0..1000u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 24) as u8)
+            .collect::<Vec<_>>();
+        let expected = crc32(&data);
+
+        for split in [0, 1, 2, 3, 7, 8, 100, 255, 256, 257, 511, 999, 1000] {
+            let (a, b) = data.split_at(split);
+            assert_eq!(crc32_combine(crc32(a), crc32(b), b.len()), expected);
+        }
+    }
+
+    #[test]
+    fn castagnoli() {
+        assert_eq!(crc32c(b"123456789"), 0xe3069283);
+        assert_eq!(crc32c(b""), 0);
+        // from RFC 3720, appendix B.4: 32 bytes of zeroes
+        assert_eq!(crc32c(&[0; 32]), 0x8a9136aa);
+    }
+
+    #[test]
+    fn ccitt() {
+        assert_eq!(crc16_ccitt(b"123456789"), 0x29b1);
+        assert_eq!(crc16_ccitt(b""), 0xffff);
+        assert_eq!(
+            u64::from(crc16_ccitt(b"Hello World!")),
+            Crc::new(16, 0x1021).init(0xffff).checksum(b"Hello World!")
+        );
+    }
+
+    #[test]
+    fn crc64_xz() {
+        // check value from the CRC catalogue
+        assert_eq!(crc64(b"123456789"), 0x995dc9bbdf1939fa);
+        assert_eq!(crc64(b""), 0);
+        // checked against a bit-at-a-time implementation
+        assert_eq!(crc64(b"Hello World!\n"), 0xbe941c0740922d21);
+    }
+
+    #[test]
+    fn variants() {
+        // check values from the CRC catalogue
+        let input = b"123456789";
+        assert_eq!(CrcVariant::IsoHdlc.checksum(input), 0xcbf43926);
+        assert_eq!(CrcVariant::Bzip2.checksum(input), 0xfc891918);
+        assert_eq!(CrcVariant::Mpeg2.checksum(input), 0x0376e6e7);
+        assert_eq!(CrcVariant::Castagnoli.checksum(input), 0xe3069283);
+    }
+
+    #[test]
+    fn detect() {
+        let input = b"123456789";
+        assert_eq!(
+            detect_crc32(input, 0xe3069283),
+            Some(CrcVariant::Castagnoli)
+        );
+        assert_ne!(detect_crc32(input, 0xe3069283), Some(CrcVariant::IsoHdlc));
+        assert_eq!(detect_crc32(input, 0xcbf43926), Some(CrcVariant::IsoHdlc));
+        assert_eq!(detect_crc32(input, 0xdeadbeef), None);
+    }
+
+    #[test]
+    fn generic() {
+        let crc32_generic = Crc::new(32, 0x04c11db7)
+            .init(0xffffffff)
+            .reflect_in(true)
+            .reflect_out(true)
+            .xor_out(0xffffffff);
+        assert_eq!(crc32_generic.checksum(b"Hello World!"), 0x1c291ca3);
+
+        // check values from the CRC catalogue
+        let input = b"123456789";
+        for variant in CrcVariant::ALL {
+            let crc = match variant {
+                CrcVariant::IsoHdlc => crc32_generic.clone(),
+                CrcVariant::Bzip2 => Crc::new(32, 0x04c11db7)
+                    .init(0xffffffff)
+                    .xor_out(0xffffffff),
+                CrcVariant::Mpeg2 => Crc::new(32, 0x04c11db7).init(0xffffffff),
+                CrcVariant::Castagnoli => Crc::new(32, 0x1edc6f41)
+                    .init(0xffffffff)
+                    .reflect_in(true)
+                    .reflect_out(true)
+                    .xor_out(0xffffffff),
+            };
+            assert_eq!(crc.checksum(input), u64::from(variant.checksum(input)));
+        }
+
+        // CRC-8/SMBUS, CRC-16/ARC, CRC-64/XZ
+        assert_eq!(Crc::new(8, 0x07).checksum(input), 0xf4);
+        let arc = Crc::new(16, 0x8005).reflect_in(true).reflect_out(true);
+        assert_eq!(arc.checksum(input), 0xbb3d);
+        let xz = Crc::new(64, 0x42f0e1eba9ea3693)
+            .init(u64::MAX)
+            .reflect_in(true)
+            .reflect_out(true)
+            .xor_out(u64::MAX);
+        assert_eq!(xz.checksum(input), 0x995dc9bbdf1939fa);
+
+        // CRC-12/UMTS reflects the output but not the input
+        let umts = Crc::new(12, 0x80f).reflect_out(true);
+        assert_eq!(umts.checksum(input), 0xdaf);
+    }
+
+    #[test]
+    fn rolling() {
+        let data = (0..1024u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 24) as u8)
+            .collect::<Vec<_>>();
+        let window = 64;
+
+        let mut rolling = RollingChecksum::new(&data[..window]);
+        for i in 0..=data.len() - window {
+            assert_eq!(
+                rolling.digest(),
+                RollingChecksum::new(&data[i..i + window]).digest()
+            );
+            if i + window < data.len() {
+                rolling.roll(data[i], data[i + window]);
+            }
+        }
+    }
 }
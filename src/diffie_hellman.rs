@@ -9,19 +9,53 @@
 /// These result in the same result, the same secret between the pair.
 use getrandom::getrandom;
 use oorandom::Rand64;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DhError {
+    /// The modulus `p` isn't prime.
+    CompositeModulus(u64),
+    /// The generator `g` isn't between 1 and `p`, exclusive.
+    InvalidGenerator(u64),
+    /// The peer's public key is 0, 1, `p - 1`, or not less than `p`.
+    InvalidPublicKey(u64),
+}
+
+impl fmt::Display for DhError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DhError::CompositeModulus(p) => write!(f, "Modulus {} is not prime", p),
+            DhError::InvalidGenerator(g) => write!(f, "Invalid generator {}", g),
+            DhError::InvalidPublicKey(key) => write!(f, "Invalid public key {}", key),
+        }
+    }
+}
+
+impl Error for DhError {}
 
 pub fn private_key(p: u64) -> u64 {
     let mut seed: [u8; 16] = [0; 16];
     getrandom(&mut seed).unwrap();
 
-    Rand64::new(u128::from_ne_bytes(seed)).rand_range(2..p)
+    private_key_from_rng(p, &mut Rand64::new(u128::from_ne_bytes(seed)))
+}
+
+/// Like `private_key`, but draws from `rng` instead of seeding one from the OS, so tests can
+/// reproduce an exchange from a fixed seed, or callers can seed it from their own entropy.
+pub fn private_key_from_rng(p: u64, rng: &mut Rand64) -> u64 {
+    rng.rand_range(2..p)
 }
 
+// Both operands of every product are reduced below `modular` < 2^64 first, so the products fit
+// in a u128.
 fn modular_exponentiation(base: u128, exp: u64, modular: u64) -> u64 {
+    debug_assert!(modular > 0, "modulus must be positive");
+
     let mut e = exp;
-    let mut b = base;
+    let mut b = base % modular as u128;
 
-    let mut result = 1;
+    let mut result = 1 % modular as u128;
 
     while e > 0 {
         if e % 2 == 1 {
@@ -36,18 +70,249 @@ fn modular_exponentiation(base: u128, exp: u64, modular: u64) -> u64 {
     result as u64
 }
 
+// Testing against the first twelve primes as witnesses is enough to make Miller-Rabin exact for
+// every n below 3.3 * 10^24, which covers all of u64.
+const MILLER_RABIN_WITNESSES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+
+/// Tests whether `n` is prime with the Miller-Rabin test, using witnesses that make it
+/// deterministic for 64-bit numbers. Use this to check a modulus `p` before using it.
+///
+/// Write $n - 1 = 2^s d$ with $d$ odd. For a prime $n$, every $a$ has either $a^d \equiv 1$ or
+/// $a^{2^r d} \equiv -1 \pmod n$ for some $r < s$, since the only square roots of 1 mod a prime
+/// are $\pm 1$. A witness $a$ for which neither holds proves $n$ composite. Unlike the Fermat test,
+/// this catches Carmichael numbers.
+pub fn is_prime(n: u64) -> bool {
+    if n < 2 {
+        return false;
+    }
+    for p in MILLER_RABIN_WITNESSES {
+        if n.is_multiple_of(p) {
+            return n == p;
+        }
+    }
+
+    let s = (n - 1).trailing_zeros();
+    let d = (n - 1) >> s;
+
+    MILLER_RABIN_WITNESSES.iter().all(|&a| {
+        let mut x = modular_exponentiation(a as u128, d, n);
+        if x == 1 || x == n - 1 {
+            return true;
+        }
+        for _ in 1..s {
+            x = ((x as u128 * x as u128) % n as u128) as u64;
+            if x == n - 1 {
+                return true;
+            }
+        }
+        false
+    })
+}
+
+/// Generates a random safe prime of exactly `bits` bits: a prime $p = 2q + 1$ where $q$ is also
+/// prime. The multiplicative group mod a safe prime has only the subgroups of order 1, 2, $q$ and
+/// $2q$, so a peer can't force the shared secret into a small subgroup where it can be guessed.
+///
+/// # Panics
+///
+/// Panics if `bits` isn't between 3 and 63, or if the OS random number generator fails.
+pub fn generate_safe_prime(bits: u32) -> u64 {
+    assert!(
+        (3..=63).contains(&bits),
+        "safe primes must be between 3 and 63 bits, got {}",
+        bits
+    );
+
+    loop {
+        let mut bytes = [0; 8];
+        getrandom(&mut bytes).unwrap();
+
+        // q has bits - 1 bits, so p = 2q + 1 has exactly bits bits
+        let q = (u64::from_ne_bytes(bytes) >> (65 - bits)) | (1 << (bits - 2));
+        let p = 2 * q + 1;
+        if is_prime(q) && is_prime(p) {
+            return p;
+        }
+    }
+}
+
+/// Computes $base^{exp} \bmod modulus$ with a Montgomery ladder, for secret exponents.
+///
+/// Square-and-multiply only multiplies for the 1 bits of the exponent, so its running time leaks
+/// the private key. The ladder keeps two values $R_0 = base^k$ and $R_1 = base^{k+1}$ for the bits
+/// $k$ of the exponent seen so far, and for every bit does one multiplication and one squaring,
+/// swapping them with a mask rather than a branch depending on the bit. All 64 bits are processed,
+/// so leading zeros don't leak either. The `u128` remainder isn't guaranteed to be constant time
+/// on every CPU, so this is a best effort.
+pub fn modexp_ct(base: u64, exp: u64, modulus: u64) -> u64 {
+    debug_assert!(modulus > 0, "modulus must be positive");
+
+    let m = modulus as u128;
+    let mut r0 = 1 % m;
+    let mut r1 = base as u128 % m;
+
+    for i in (0..u64::BITS).rev() {
+        // when the bit is 1, swap so the same code computes R_0 = R_0 R_1 and R_1 = R_1^2
+        let mask = 0u128.wrapping_sub(((exp >> i) & 1) as u128);
+        let t = mask & (r0 ^ r1);
+        r0 ^= t;
+        r1 ^= t;
+
+        r1 = r0 * r1 % m;
+        r0 = r0 * r0 % m;
+
+        let t = mask & (r0 ^ r1);
+        r0 ^= t;
+        r1 ^= t;
+    }
+
+    r0 as u64
+}
+
 pub fn public_key(p: u64, g: u64, a: u64) -> u64 {
-    modular_exponentiation(g as u128, a, p)
+    modexp_ct(g, a, p)
 }
 
 pub fn secret(p: u64, b_pub: u64, a: u64) -> u64 {
-    modular_exponentiation(b_pub as u128, a, p)
+    modexp_ct(b_pub, a, p)
+}
+
+/// Like `secret`, but rejects degenerate public keys from the peer instead of computing a weak
+/// secret. The powers of 0, 1 and $p - 1$ are only 0, 1 and $\pm 1$, so a peer (or someone in the
+/// middle) sending one of them forces a shared secret that can be guessed. Keys of `p` or more
+/// aren't reduced mod `p` by an honest peer, so they are rejected too.
+pub fn checked_secret(p: u64, b_pub: u64, a: u64) -> Result<u64, DhError> {
+    if b_pub <= 1 || b_pub >= p.saturating_sub(1) {
+        return Err(DhError::InvalidPublicKey(b_pub));
+    }
+    Ok(secret(p, b_pub, a))
+}
+
+/// Encodes a public key for sending over the wire, as 8 big-endian bytes.
+pub fn public_key_to_be_bytes(key: u64) -> [u8; 8] {
+    key.to_be_bytes()
+}
+
+/// Decodes a public key encoded with `public_key_to_be_bytes`.
+pub fn public_key_from_be_bytes(bytes: [u8; 8]) -> u64 {
+    u64::from_be_bytes(bytes)
+}
+
+/// Encodes the public parameters `p` and `g` for sending over the wire, as `p` followed by `g`,
+/// each 8 big-endian bytes.
+pub fn params_to_be_bytes(p: u64, g: u64) -> [u8; 16] {
+    let mut bytes = [0; 16];
+    bytes[..8].copy_from_slice(&p.to_be_bytes());
+    bytes[8..].copy_from_slice(&g.to_be_bytes());
+    bytes
+}
+
+/// Decodes the public parameters encoded with `params_to_be_bytes`, returning `(p, g)`.
+pub fn params_from_be_bytes(bytes: [u8; 16]) -> (u64, u64) {
+    let (p, g) = bytes.split_at(8);
+    (
+        u64::from_be_bytes(p.try_into().unwrap()),
+        u64::from_be_bytes(g.try_into().unwrap()),
+    )
+}
+
+/// Validated public parameters for a key exchange: a prime modulus `p` and a generator `g` with
+/// $1 < g < p$. Checking these up front catches a composite modulus, which makes discrete
+/// logarithms much easier, or a generator like 0 or 1 whose powers are all the same.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct DhParams {
+    p: u64,
+    g: u64,
+}
+
+impl DhParams {
+    pub fn new(p: u64, g: u64) -> Result<Self, DhError> {
+        if !is_prime(p) {
+            return Err(DhError::CompositeModulus(p));
+        }
+        if g <= 1 || g >= p {
+            return Err(DhError::InvalidGenerator(g));
+        }
+        Ok(Self { p, g })
+    }
+
+    pub fn p(&self) -> u64 {
+        self.p
+    }
+
+    pub fn g(&self) -> u64 {
+        self.g
+    }
+
+    pub fn private_key(&self) -> u64 {
+        private_key(self.p)
+    }
+
+    pub fn public_key(&self, a: u64) -> u64 {
+        public_key(self.p, self.g, a)
+    }
+
+    /// Computes the shared secret like `checked_secret`, rejecting degenerate public keys.
+    pub fn secret(&self, b_pub: u64, a: u64) -> Result<u64, DhError> {
+        checked_secret(self.p, b_pub, a)
+    }
+
+    /// Encodes the parameters in the format of `params_to_be_bytes`.
+    pub fn to_be_bytes(&self) -> [u8; 16] {
+        params_to_be_bytes(self.p, self.g)
+    }
+
+    /// Decodes and validates parameters encoded with `to_be_bytes`.
+    pub fn from_be_bytes(bytes: [u8; 16]) -> Result<Self, DhError> {
+        let (p, g) = params_from_be_bytes(bytes);
+        Self::new(p, g)
+    }
+}
+
+/// One side's keys for an exchange under some `DhParams`. Each side generates a key pair, sends
+/// the other its public key, and calls `agree` with the key it receives to get the same secret.
+#[derive(Debug, Clone, Copy)]
+pub struct KeyPair {
+    params: DhParams,
+    private: u64,
+    public: u64,
+}
+
+impl KeyPair {
+    pub fn generate(params: &DhParams) -> Self {
+        Self::from_private(params, params.private_key())
+    }
+
+    /// Builds the key pair for a known private key, for example one from `private_key_from_rng`.
+    pub fn from_private(params: &DhParams, private: u64) -> Self {
+        Self {
+            params: *params,
+            private,
+            public: params.public_key(private),
+        }
+    }
+
+    pub fn private(&self) -> u64 {
+        self.private
+    }
+
+    /// The public key to send to the other side.
+    pub fn public(&self) -> u64 {
+        self.public
+    }
+
+    /// Computes the shared secret from the other side's public key, rejecting degenerate keys
+    /// like `checked_secret`.
+    pub fn agree(&self, their_public: u64) -> Result<u64, DhError> {
+        self.params.secret(their_public, self.private)
+    }
 }
 
 /// These tests come from exercism.io's Diffie Hellman tests.
 #[cfg(test)]
 mod tests {
     use super::*;
+    use quickcheck_macros::quickcheck;
 
     #[test]
     fn private_key_in_range_key() {
@@ -112,6 +377,173 @@ mod tests {
         assert_eq!(secret, expected);
     }
 
+    #[test]
+    fn bases_larger_than_modulus() {
+        // u64::MAX is congruent to u64::MAX - p
+        let expected = 1_590_236_558_078_409_617;
+        assert_eq!(
+            public_key(PRIME_64BIT_1, u64::MAX, PRIVATE_KEY_64BIT),
+            expected
+        );
+        assert_eq!(
+            public_key(PRIME_64BIT_1, u64::MAX - PRIME_64BIT_1, PRIVATE_KEY_64BIT),
+            expected
+        );
+        assert_eq!(
+            secret(PRIME_64BIT_1, PRIME_64BIT_1 + 5, 12345),
+            secret(PRIME_64BIT_1, 5, 12345)
+        );
+
+        assert_eq!(modular_exponentiation(u128::MAX, 3, PRIME_64BIT_1), {
+            let b = u128::MAX % PRIME_64BIT_1 as u128;
+            (b * b % PRIME_64BIT_1 as u128 * b % PRIME_64BIT_1 as u128) as u64
+        });
+        assert_eq!(modular_exponentiation(5, 0, 1), 0);
+    }
+
+    #[test]
+    fn ladder_matches_square_and_multiply() {
+        for (base, exp, modulus) in [
+            (5, 6, 23),
+            (8, 7, 11),
+            (8, 4_294_967_296, 4_294_967_299),
+            (843, 4_294_967_300, 4_294_967_927),
+            (PRIME_64BIT_2, PRIVATE_KEY_64BIT, PRIME_64BIT_1),
+            (u64::MAX, u64::MAX, PRIME_64BIT_1),
+            (0, 0, 7),
+            (3, 0, 1),
+        ] {
+            assert_eq!(
+                modexp_ct(base, exp, modulus),
+                modular_exponentiation(base as u128, exp, modulus)
+            );
+        }
+    }
+
+    #[quickcheck]
+    fn ladder_matches_random(base: u64, exp: u64, modulus: u64) -> bool {
+        modulus == 0
+            || modexp_ct(base, exp, modulus) == modular_exponentiation(base as u128, exp, modulus)
+    }
+
+    #[test]
+    fn primality() {
+        for p in [
+            2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 773, 967, 3461, 6131,
+        ] {
+            assert!(is_prime(p), "{} is prime", p);
+        }
+        assert!(is_prime(PRIME_64BIT_1));
+        assert!(is_prime(4_294_967_311));
+
+        for n in [0, 1, 4, 9, 91, 4_294_967_299, PRIME_64BIT_2, u64::MAX] {
+            assert!(!is_prime(n), "{} is composite", n);
+        }
+
+        // Carmichael numbers fool the Fermat test for every base coprime to them
+        for n in [561, 1105, 1729, 2465, 2821, 6601, 8911, 62_745, 825_265] {
+            assert!(!is_prime(n), "{} is a Carmichael number", n);
+        }
+
+        // strong pseudoprimes to the bases 2 to 7, and 2 to 23
+        assert!(!is_prime(3_215_031_751));
+        assert!(!is_prime(3_825_123_056_546_413_051));
+    }
+
+    #[test]
+    fn seeded_private_key() {
+        let a = private_key_from_rng(PRIME_64BIT_1, &mut Rand64::new(42));
+        assert_eq!(a, private_key_from_rng(PRIME_64BIT_1, &mut Rand64::new(42)));
+        assert_ne!(a, private_key_from_rng(PRIME_64BIT_1, &mut Rand64::new(43)));
+
+        let mut rng = Rand64::new(7);
+        for p in [3, 5, 23, 6131] {
+            for _ in 0..100 {
+                let a = private_key_from_rng(p, &mut rng);
+                assert!((2..p).contains(&a));
+            }
+        }
+    }
+
+    #[test]
+    fn params_validation() {
+        let params = DhParams::new(23, 5).unwrap();
+        assert_eq!(params.public_key(6), 8);
+        assert_eq!(
+            params.secret(params.public_key(4), 6),
+            Ok(secret(23, params.public_key(4), 6))
+        );
+        assert_eq!(DhParams::from_be_bytes(params.to_be_bytes()), Ok(params));
+
+        let a = params.private_key();
+        assert!(1 < a && a < params.p());
+
+        assert_eq!(DhParams::new(23, 0), Err(DhError::InvalidGenerator(0)));
+        assert_eq!(DhParams::new(23, 1), Err(DhError::InvalidGenerator(1)));
+        assert_eq!(DhParams::new(23, 23), Err(DhError::InvalidGenerator(23)));
+        assert_eq!(DhParams::new(21, 5), Err(DhError::CompositeModulus(21)));
+        assert_eq!(
+            DhParams::new(4_294_967_299, 8),
+            Err(DhError::CompositeModulus(4_294_967_299))
+        );
+        assert!(DhParams::from_be_bytes(params_to_be_bytes(561, 2)).is_err());
+    }
+
+    #[test]
+    fn degenerate_public_keys() {
+        let p = PRIME_64BIT_1;
+        for key in [0, 1, p - 1, p, u64::MAX] {
+            assert_eq!(
+                checked_secret(p, key, PRIVATE_KEY_64BIT),
+                Err(DhError::InvalidPublicKey(key))
+            );
+        }
+
+        assert_eq!(
+            checked_secret(p, PUBLIC_KEY_64BIT, 0xEFFF_FFFF_FFFF_FFC0),
+            Ok(secret(p, PUBLIC_KEY_64BIT, 0xEFFF_FFFF_FFFF_FFC0))
+        );
+        assert_eq!(checked_secret(11, 8, 7), Ok(2));
+
+        let params = DhParams::new(23, 5).unwrap();
+        assert_eq!(params.secret(22, 6), Err(DhError::InvalidPublicKey(22)));
+    }
+
+    #[test]
+    fn key_pair_exchange() {
+        let params = DhParams::new(PRIME_64BIT_1, 5).unwrap();
+        let alice = KeyPair::generate(&params);
+        let bob = KeyPair::generate(&params);
+
+        let alice_secret = alice.agree(bob.public()).unwrap();
+        assert_eq!(alice_secret, bob.agree(alice.public()).unwrap());
+        assert_eq!(
+            alice_secret,
+            secret(PRIME_64BIT_1, bob.public(), alice.private())
+        );
+
+        let params = DhParams::new(23, 5).unwrap();
+        let pair = KeyPair::from_private(&params, 6);
+        assert_eq!(pair.public(), 8);
+        assert_eq!(pair.agree(1), Err(DhError::InvalidPublicKey(1)));
+    }
+
+    #[test]
+    fn safe_primes() {
+        for bits in [3, 4, 8, 16, 32, 48, 63] {
+            let p = generate_safe_prime(bits);
+            assert_eq!(64 - p.leading_zeros(), bits);
+            assert!(is_prime(p));
+            assert!(is_prime((p - 1) / 2));
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn safe_primes_too_large() {
+        generate_safe_prime(64);
+    }
+
     // two biggest 64bit primes
     const PRIME_64BIT_1: u64 = 0xFFFF_FFFF_FFFF_FFC5;
     const PRIME_64BIT_2: u64 = 0xFFFF_FFFF_FFFF_FFAC;
@@ -176,4 +608,37 @@ mod tests {
 
         assert_eq!(secret_a, secret_b);
     }
+
+    #[test]
+    fn serialization_round_trip() {
+        let bytes = params_to_be_bytes(PRIME_64BIT_1, PRIME_64BIT_2);
+        assert_eq!(bytes[..8], PRIME_64BIT_1.to_be_bytes());
+        assert_eq!(params_from_be_bytes(bytes), (PRIME_64BIT_1, PRIME_64BIT_2));
+
+        let bytes = public_key_to_be_bytes(PUBLIC_KEY_64BIT);
+        assert_eq!(bytes, [0xb8, 0x51, 0xeb, 0x85, 0x1e, 0xb8, 0x51, 0xc1]);
+        assert_eq!(public_key_from_be_bytes(bytes), PUBLIC_KEY_64BIT);
+    }
+
+    #[test]
+    fn serialized_exchange() {
+        // Alice sends the parameters and her public key to Bob
+        let (p, g) = (PRIME_64BIT_1, PRIME_64BIT_2);
+        let private_key_a = private_key(p);
+        let mut message = params_to_be_bytes(p, g).to_vec();
+        message.extend_from_slice(&public_key_to_be_bytes(public_key(p, g, private_key_a)));
+
+        // Bob reads them and replies with his public key
+        let (p_b, g_b) = params_from_be_bytes(message[..16].try_into().unwrap());
+        let public_key_a = public_key_from_be_bytes(message[16..].try_into().unwrap());
+        let private_key_b = private_key(p_b);
+        let reply = public_key_to_be_bytes(public_key(p_b, g_b, private_key_b));
+
+        // both sides agree on the secret
+        let public_key_b = public_key_from_be_bytes(reply);
+        assert_eq!(
+            secret(p, public_key_b, private_key_a),
+            secret(p_b, public_key_a, private_key_b)
+        );
+    }
 }
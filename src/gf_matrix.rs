@@ -0,0 +1,257 @@
+//! # Matrices over finite fields
+//!
+//! Many erasure codes and secret sharing schemes can be phrased as linear algebra over a finite
+//! field. Encoding is a matrix multiplication, and decoding is solving a system of linear
+//! equations, which is done by inverting the matrix made from the rows that survived.
+//!
+//! A common choice is a Vandermonde matrix, where row $i$ is $[1, x_i, x_i^2, \ldots]$. Any
+//! square Vandermonde matrix built from distinct $x_i$ is invertible, so any $k$ rows of an
+//! $n \times k$ Vandermonde matrix are enough to recover the $k$ original values. This is the same
+//! fact that Shamir secret sharing relies on: $k$ points uniquely define a polynomial of degree
+//! $k - 1$.
+//!
+//! Inversion uses Gauss-Jordan elimination: the matrix is augmented with the identity matrix and
+//! row operations reduce the left side to the identity, at which point the right side is the
+//! inverse. Over a finite field there is no rounding error, so any non-zero pivot works.
+
+use gf256::gf256;
+use std::ops::{Add, Div, Index, IndexMut, Mul, Sub};
+
+/// The operations a matrix needs from its elements.
+pub trait Field:
+    Copy + PartialEq + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Div<Output = Self>
+{
+    fn zero() -> Self;
+    fn one() -> Self;
+}
+
+impl Field for gf256 {
+    fn zero() -> Self {
+        gf256::new(0)
+    }
+
+    fn one() -> Self {
+        gf256::new(1)
+    }
+}
+
+/// A dense, row-major matrix over a finite field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GfMatrix<F: Field> {
+    rows: usize,
+    cols: usize,
+    data: Vec<F>,
+}
+
+impl<F: Field> GfMatrix<F> {
+    /// Creates a `rows` x `cols` matrix filled with zeroes.
+    pub fn new(rows: usize, cols: usize) -> Self {
+        Self {
+            rows,
+            cols,
+            data: vec![F::zero(); rows * cols],
+        }
+    }
+
+    /// Creates the `n` x `n` identity matrix.
+    pub fn identity(n: usize) -> Self {
+        let mut m = Self::new(n, n);
+        for i in 0..n {
+            m[(i, i)] = F::one();
+        }
+        m
+    }
+
+    /// Creates a matrix from a list of rows, which must all be the same length.
+    pub fn from_rows<R: AsRef<[F]>>(rows: &[R]) -> Self {
+        let cols = rows.first().map(|r| r.as_ref().len()).unwrap_or(0);
+        assert!(
+            rows.iter().all(|r| r.as_ref().len() == cols),
+            "mismatched row length"
+        );
+
+        let mut data = Vec::with_capacity(rows.len() * cols);
+        for r in rows {
+            data.extend_from_slice(r.as_ref());
+        }
+
+        Self {
+            rows: rows.len(),
+            cols,
+            data,
+        }
+    }
+
+    /// Creates a Vandermonde matrix with one row per `x`, where row $i$ is
+    /// $[1, x_i, x_i^2, \ldots, x_i^{cols - 1}]$.
+    pub fn vandermonde(xs: &[F], cols: usize) -> Self {
+        let mut m = Self::new(xs.len(), cols);
+        for (i, x) in xs.iter().enumerate() {
+            let mut p = F::one();
+            for j in 0..cols {
+                m[(i, j)] = p;
+                p = p * *x;
+            }
+        }
+        m
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// Returns row `i` as a slice.
+    pub fn row(&self, i: usize) -> &[F] {
+        &self.data[i * self.cols..(i + 1) * self.cols]
+    }
+
+    fn swap_rows(&mut self, a: usize, b: usize) {
+        if a != b {
+            for j in 0..self.cols {
+                self.data.swap(a * self.cols + j, b * self.cols + j);
+            }
+        }
+    }
+
+    /// Multiplies `self` by `other`. Panics if the inner dimensions don't match.
+    pub fn mul(&self, other: &Self) -> Self {
+        assert!(self.cols == other.rows, "mismatched matrix dimensions");
+
+        let mut product = Self::new(self.rows, other.cols);
+        for i in 0..self.rows {
+            for j in 0..other.cols {
+                let mut sum = F::zero();
+                for k in 0..self.cols {
+                    sum = sum + self[(i, k)] * other[(k, j)];
+                }
+                product[(i, j)] = sum;
+            }
+        }
+        product
+    }
+
+    /// Multiplies `self` by the column vector `v`.
+    pub fn mul_vec(&self, v: &[F]) -> Vec<F> {
+        assert!(self.cols == v.len(), "mismatched matrix dimensions");
+
+        (0..self.rows)
+            .map(|i| {
+                self.row(i)
+                    .iter()
+                    .zip(v)
+                    .fold(F::zero(), |sum, (a, b)| sum + *a * *b)
+            })
+            .collect()
+    }
+
+    /// Inverts a square matrix using Gauss-Jordan elimination, returning `None` if the matrix is
+    /// singular.
+    pub fn invert(&self) -> Option<Self> {
+        assert!(
+            self.rows == self.cols,
+            "only square matrices can be inverted"
+        );
+        let n = self.rows;
+
+        let mut m = self.clone();
+        let mut inv = Self::identity(n);
+
+        for col in 0..n {
+            // find a row with a non-zero entry in this column to use as the pivot
+            let pivot = (col..n).find(|&r| m[(r, col)] != F::zero())?;
+            m.swap_rows(col, pivot);
+            inv.swap_rows(col, pivot);
+
+            // scale the pivot row so the pivot is 1
+            let scale = m[(col, col)];
+            for j in 0..n {
+                m[(col, j)] = m[(col, j)] / scale;
+                inv[(col, j)] = inv[(col, j)] / scale;
+            }
+
+            // eliminate this column from every other row
+            for r in 0..n {
+                if r == col {
+                    continue;
+                }
+                let factor = m[(r, col)];
+                if factor == F::zero() {
+                    continue;
+                }
+                for j in 0..n {
+                    m[(r, j)] = m[(r, j)] - factor * m[(col, j)];
+                    inv[(r, j)] = inv[(r, j)] - factor * inv[(col, j)];
+                }
+            }
+        }
+
+        Some(inv)
+    }
+
+    /// Solves $Mx = b$ for $x$, returning `None` if the matrix is singular.
+    pub fn solve(&self, b: &[F]) -> Option<Vec<F>> {
+        assert!(self.rows == b.len(), "mismatched matrix dimensions");
+        Some(self.invert()?.mul_vec(b))
+    }
+}
+
+impl<F: Field> Index<(usize, usize)> for GfMatrix<F> {
+    type Output = F;
+
+    fn index(&self, (i, j): (usize, usize)) -> &F {
+        &self.data[i * self.cols + j]
+    }
+}
+
+impl<F: Field> IndexMut<(usize, usize)> for GfMatrix<F> {
+    fn index_mut(&mut self, (i, j): (usize, usize)) -> &mut F {
+        &mut self.data[i * self.cols + j]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xs(values: &[u8]) -> Vec<gf256> {
+        values.iter().map(|x| gf256::new(*x)).collect()
+    }
+
+    #[test]
+    fn vandermonde_inverse() {
+        let m = GfMatrix::vandermonde(&xs(&[1, 2, 3, 4]), 4);
+        let inv = m.invert().expect("vandermonde matrix is invertible");
+
+        assert_eq!(m.mul(&inv), GfMatrix::identity(4));
+        assert_eq!(inv.mul(&m), GfMatrix::identity(4));
+    }
+
+    #[test]
+    fn singular() {
+        let m = GfMatrix::from_rows(&[xs(&[1, 2]), xs(&[1, 2])]);
+        assert_eq!(m.invert(), None);
+    }
+
+    #[test]
+    fn solve() {
+        // encode 4 values as evaluations of a polynomial at 6 points
+        let coeffs = xs(&[7, 42, 9, 200]);
+        let encoding = GfMatrix::vandermonde(&xs(&[1, 2, 3, 4, 5, 6]), 4);
+        let points = encoding.mul_vec(&coeffs);
+
+        // any 4 surviving rows recover the original values
+        let survivors = [0, 2, 3, 5];
+        let m = GfMatrix::from_rows(
+            &survivors
+                .iter()
+                .map(|&i| encoding.row(i).to_vec())
+                .collect::<Vec<_>>(),
+        );
+        let b = survivors.iter().map(|&i| points[i]).collect::<Vec<_>>();
+        assert_eq!(m.solve(&b), Some(coeffs));
+    }
+}
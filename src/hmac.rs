@@ -1,6 +1,7 @@
 use std::cmp::Ordering;
 
 use crate::sha1::Sha1;
+use crate::util::ct_eq;
 
 pub struct HMAC;
 
@@ -25,7 +26,7 @@ impl HMAC {
         let block_sized_key = Self::block_size_key(secret_key);
         // 2. Next, generate two keys.
         // The first key, the outer key, is xored with 0x36.
-        let mut padded = [0x36; 40];
+        let mut padded = [0x36; 64];
         for (p, &k) in padded.iter_mut().zip(block_sized_key.iter()) {
             *p ^= k;
         }
@@ -43,6 +44,44 @@ impl HMAC {
         Sha1::hash(&oh_input)
     }
 
+    /// Checks `tag` against the MAC of `input`, in constant time.
+    pub fn verify(input: &[u8], secret_key: &[u8], tag: &[u8]) -> bool {
+        ct_eq(&Self::mac(input, secret_key), tag)
+    }
+
+    /// The extract step of HKDF (RFC 5869): concentrates the entropy of the input keying material
+    /// `ikm`, like a Diffie-Hellman shared secret, into a pseudorandom key, by using it as the
+    /// message of an HMAC keyed with `salt`.
+    pub fn hkdf_extract(salt: &[u8], ikm: &[u8]) -> [u8; 20] {
+        Self::mac(ikm, salt)
+    }
+
+    /// The expand step of HKDF: stretches a pseudorandom key into `len` bytes of output keying
+    /// material. Each block is $T_i = HMAC(prk, T_{i-1} || info || i)$, so different `info`
+    /// strings give independent keys from the same secret. At most 255 blocks can be produced.
+    pub fn hkdf_expand(prk: &[u8], info: &[u8], len: usize) -> Vec<u8> {
+        assert!(len <= 255 * 20, "HKDF output too long: {} bytes", len);
+
+        let mut okm = Vec::with_capacity(len);
+        let mut t = vec![];
+        let mut i = 1u8;
+        while okm.len() < len {
+            t.extend_from_slice(info);
+            t.push(i);
+            t = Self::mac(&t, prk).to_vec();
+            okm.extend_from_slice(&t);
+            i = i.wrapping_add(1);
+        }
+        okm.truncate(len);
+        okm
+    }
+
+    /// Derives `len` bytes of keying material from `ikm`, extracting with `salt` and expanding
+    /// with `info`.
+    pub fn hkdf(salt: &[u8], ikm: &[u8], info: &[u8], len: usize) -> Vec<u8> {
+        Self::hkdf_expand(&Self::hkdf_extract(salt, ikm), info, len)
+    }
+
     fn block_size_key(secret_key: &[u8]) -> [u8; 64] {
         match secret_key.len().cmp(&64) {
             Ordering::Less => {
@@ -78,12 +117,128 @@ mod tests {
         assert_eq!(
             h,
             [
-                0x2c, 0x4c, 0x5d, 0xb0, // first
-                0x09, 0x76, 0xff, 0xdb, // second
-                0x10, 0xdb, 0xd5, 0x32, // third
-                0xe2, 0x78, 0x35, 0xa9, // fourth
-                0x84, 0x8e, 0x6c, 0xef, // fifth
+                0xfb, 0xdb, 0x1d, 0x1b, // first
+                0x18, 0xaa, 0x6c, 0x08, // second
+                0x32, 0x4b, 0x7d, 0x64, // third
+                0xb7, 0x1f, 0xb7, 0x63, // fourth
+                0x70, 0x69, 0x0e, 0x1d, // fifth
             ]
         );
     }
+
+    #[test]
+    fn verify() {
+        let tag = HMAC::mac(b"message", b"key");
+        assert!(HMAC::verify(b"message", b"key", &tag));
+        assert!(!HMAC::verify(b"message", b"other key", &tag));
+        assert!(!HMAC::verify(b"message", b"key", &tag[..19]));
+    }
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    // test vectors from RFC 2202, test cases 1 and 6
+    #[test]
+    fn rfc2202() {
+        assert_eq!(
+            HMAC::mac(b"Hi There", &[0x0b; 20]).to_vec(),
+            hex("b617318655057264e28bc0b6fb378c8ef146be00")
+        );
+        assert_eq!(
+            HMAC::mac(
+                b"Test Using Larger Than Block-Size Key - Hash Key First",
+                &[0xaa; 80]
+            )
+            .to_vec(),
+            hex("aa4ae5e15272d00e95705637ce8a3b55ed402112")
+        );
+    }
+
+    // test vectors from RFC 5869, A.4 to A.7
+    #[test]
+    fn hkdf_rfc5869() {
+        let cases = [
+            (
+                hex("000102030405060708090a0b0c"),
+                vec![0x0b; 11],
+                hex("f0f1f2f3f4f5f6f7f8f9"),
+                "9b6c18c432a7bf8f0e71c8eb88f4b30baa2ba243",
+                concat!(
+                    "085a01ea1b10f36933068b56efa5ad81a4f14b822f5b091568a9cdd4f155fda2",
+                    "c22e422478d305f3f896"
+                ),
+            ),
+            (
+                (0x60..=0xaf).collect(),
+                (0x00..=0x4f).collect(),
+                (0xb0..=0xff).collect(),
+                "8adae09a2a307059478d309b26c4115a224cfaf6",
+                concat!(
+                    "0bd770a74d1160f7c9f12cd5912a06ebff6adcae899d92191fe4305673ba2ffe",
+                    "8fa3f1a4e5ad79f3f334b3b202b2173c486ea37ce3d397ed034c7f9dfeb15c5e",
+                    "927336d0441f4c4300e2cff0d0900b52d3b4"
+                ),
+            ),
+            (
+                vec![],
+                vec![0x0b; 22],
+                vec![],
+                "da8c8a73c7fa77288ec6f5e7c297786aa0d32d01",
+                concat!(
+                    "0ac1af7002b3d761d1e55298da9d0506b9ae52057220a306e07b6b87e8df21d0",
+                    "ea00033de03984d34918"
+                ),
+            ),
+            // no salt, which is the same as 20 zero bytes
+            (
+                vec![0; 20],
+                vec![0x0c; 22],
+                vec![],
+                "2adccada18779e7c2077ad2eb19d3f3e731385dd",
+                concat!(
+                    "2c91117204d745f3500d636a62f64f0ab3bae548aa53d423b0d1f27ebba6f5e5",
+                    "673a081d70cce7acfc48"
+                ),
+            ),
+        ];
+
+        for (salt, ikm, info, prk, okm) in cases {
+            let okm = hex(okm);
+            assert_eq!(HMAC::hkdf_extract(&salt, &ikm).to_vec(), hex(prk));
+            assert_eq!(HMAC::hkdf_expand(&hex(prk), &info, okm.len()), okm);
+            assert_eq!(HMAC::hkdf(&salt, &ikm, &info, okm.len()), okm);
+        }
+        assert_eq!(
+            HMAC::hkdf(&[], &[0x0c; 22], &[], 42),
+            HMAC::hkdf(&[0; 20], &[0x0c; 22], &[], 42)
+        );
+    }
+
+    #[test]
+    fn hkdf() {
+        let prk = HMAC::hkdf_extract(b"salt", b"input keying material");
+        assert_eq!(prk, HMAC::mac(b"input keying material", b"salt"));
+
+        // the first block is HMAC(prk, info || 1), and each block chains from the last
+        let okm = HMAC::hkdf_expand(&prk, b"info", 50);
+        assert_eq!(okm.len(), 50);
+        assert_eq!(okm[..20], HMAC::mac(b"info\x01", &prk));
+        let mut second = okm[..20].to_vec();
+        second.extend_from_slice(b"info\x02");
+        assert_eq!(okm[20..40], HMAC::mac(&second, &prk));
+
+        // shorter outputs are prefixes of longer ones
+        assert_eq!(HMAC::hkdf_expand(&prk, b"info", 7), okm[..7]);
+        assert_eq!(
+            HMAC::hkdf(b"salt", b"input keying material", b"info", 50),
+            okm
+        );
+
+        // different info gives a different key
+        assert_ne!(HMAC::hkdf_expand(&prk, b"other", 50), okm);
+    }
 }
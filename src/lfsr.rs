@@ -1,17 +1,151 @@
 use gf256::p128;
+#[cfg(feature = "rand-core")]
+use rand_core::{impls, RngCore};
+use std::error::Error;
+use std::fmt;
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LfsrError {
+    /// The all-zero state never changes, so it can't be used.
+    ZeroState,
+    /// The feedback polynomial has no constant term, so the LFSR can't be run backwards.
+    InvalidPolynomial,
+}
+
+impl fmt::Display for LfsrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LfsrError::ZeroState => write!(f, "LFSR state must be non-zero"),
+            LfsrError::InvalidPolynomial => write!(f, "Invalid feedback polynomial"),
+        }
+    }
+}
+
+impl Error for LfsrError {}
+
+/// A 64-bit Galois LFSR: the state, and the feedback polynomial of degree 64.
 #[derive(Debug, Clone, PartialEq)]
-pub struct Lfsr(pub u64);
+pub struct Lfsr(u64, p128);
 
 const POLYNOMIAL: p128 = p128(0x1000000000000001b);
 
+// The prime factors of 2^64 - 1, the period of a maximal-length 64-bit LFSR.
+const PERIOD_FACTORS: [u64; 7] = [3, 5, 17, 257, 641, 65537, 6700417];
+
+// Multiplies two polynomials of degree < 64 modulo `poly`, of degree 64. The product has a degree
+// of at most 126, so it fits in a p128 before reducing.
+fn mul_mod(a: p128, b: p128, poly: p128) -> p128 {
+    (a * b) % poly
+}
+
+// Computes $a^e$ modulo `poly` by square-and-multiply.
+fn pow_mod(mut a: p128, mut e: u64, poly: p128) -> p128 {
+    let mut result = p128(1);
+    while e > 0 {
+        if e & 1 == 1 {
+            result = mul_mod(result, a, poly);
+        }
+        a = mul_mod(a, a, poly);
+        e >>= 1;
+    }
+    result
+}
+
 impl Lfsr {
-    pub fn new(mut seed: u64) -> Self {
+    pub fn new(seed: u64) -> Self {
+        Self::with_polynomial(seed, POLYNOMIAL)
+    }
+
+    /// Creates an LFSR with the feedback polynomial `poly` instead of the default
+    /// $x^{64} + x^4 + x^3 + x + 1$. The taps are the bits of `poly` below $x^{64}$.
+    ///
+    /// `poly` must have a degree of exactly 64, and a constant term of 1 so the LFSR can be run
+    /// backwards. The LFSR only runs through all $2^{64} - 1$ non-zero states if `poly` is
+    /// primitive; other polynomials give shorter cycles.
+    pub fn with_polynomial(mut seed: u64, poly: p128) -> Self {
+        assert!(poly.0 >> 64 == 1, "polynomial must have a degree of 64");
+        assert!(poly.0 & 1 == 1, "polynomial must have a constant term");
+
         if seed == 0 {
             seed = 1;
         }
 
-        Self(seed)
+        Self(seed, poly)
+    }
+
+    pub fn state(&self) -> u64 {
+        self.0
+    }
+
+    /// Sets the state, e.g. to resume a sequence. Unlike `new`, which replaces a zero seed with 1,
+    /// this rejects the all-zero state, since it would never change.
+    pub fn set_state(&mut self, state: u64) -> Result<(), LfsrError> {
+        if state == 0 {
+            return Err(LfsrError::ZeroState);
+        }
+        self.0 = state;
+        Ok(())
+    }
+
+    /// Serializes the LFSR as its state followed by the feedback polynomial without the implicit
+    /// $x^{64}$ term, both big-endian, so `from_bytes` can resume the sequence where it left off.
+    pub fn to_bytes(&self) -> [u8; 16] {
+        let mut bytes = [0; 16];
+        bytes[..8].copy_from_slice(&self.0.to_be_bytes());
+        bytes[8..].copy_from_slice(&(self.1 .0 as u64).to_be_bytes());
+        bytes
+    }
+
+    /// Restores an LFSR serialized by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8; 16]) -> Result<Self, LfsrError> {
+        let state = u64::from_be_bytes(bytes[..8].try_into().unwrap());
+        let taps = u64::from_be_bytes(bytes[8..].try_into().unwrap());
+        if state == 0 {
+            return Err(LfsrError::ZeroState);
+        }
+        if taps & 1 == 0 {
+            return Err(LfsrError::InvalidPolynomial);
+        }
+
+        Ok(Self(state, p128((1 << 64) | u128::from(taps))))
+    }
+
+    /// Returns the number of steps until the LFSR returns to its current state.
+    ///
+    /// Each step multiplies the state, read as a polynomial, by $x$ modulo the feedback
+    /// polynomial, so the period is the smallest $t$ with $s \cdot x^t = s$. For a primitive
+    /// polynomial that is $2^{64} - 1$ for every non-zero state. If $s \cdot x^{2^{64} - 1} = s$,
+    /// which is always the case for irreducible polynomials, the period divides $2^{64} - 1$, and
+    /// is found by dividing out the prime factors of $2^{64} - 1$ one at a time while the state
+    /// still repeats, with a few dozen polynomial exponentiations. Otherwise this falls back to
+    /// stepping until the state repeats, which is only fast for short cycles.
+    ///
+    /// The all-zero state never changes, so its period is 1.
+    pub fn period(&self) -> u64 {
+        let (state, poly) = (p128(u128::from(self.0)), self.1);
+        if state.0 == 0 {
+            return 1;
+        }
+
+        let repeats = |t| mul_mod(state, pow_mod(p128(2), t, poly), poly) == state;
+        if repeats(u64::MAX) {
+            let mut period = u64::MAX;
+            for p in PERIOD_FACTORS {
+                while period % p == 0 && repeats(period / p) {
+                    period /= p;
+                }
+            }
+            return period;
+        }
+
+        let mut lfsr = self.clone();
+        let mut period = 1;
+        lfsr.skip(1);
+        while lfsr.0 != self.0 {
+            lfsr.skip(1);
+            period += 1;
+        }
+        period
     }
 
     pub fn next(&mut self, bits: u64) -> u64 {
@@ -20,7 +154,7 @@ impl Lfsr {
         for _ in 0..bits {
             let msb = self.0 >> 63;
             x = (x << 1) | msb;
-            self.0 = (self.0 << 1) ^ if msb != 0 { POLYNOMIAL.0 as u64 } else { 0 };
+            self.0 = (self.0 << 1) ^ if msb != 0 { self.1 .0 as u64 } else { 0 };
         }
         x
     }
@@ -31,12 +165,7 @@ impl Lfsr {
         for _ in 0..bits {
             let lsb = self.0 & 1;
             x = (x >> 1) | (lsb << (bits - 1));
-            self.0 = (self.0 >> 1)
-                ^ if lsb != 0 {
-                    (POLYNOMIAL.0 >> 1) as u64
-                } else {
-                    0
-                };
+            self.0 = (self.0 >> 1) ^ if lsb != 0 { (self.1 .0 >> 1) as u64 } else { 0 };
         }
         x
     }
@@ -45,24 +174,304 @@ impl Lfsr {
         // just iterate naively
         for _ in 0..bits {
             let msb = self.0 >> 63;
-            self.0 = (self.0 << 1) ^ if msb != 0 { POLYNOMIAL.0 as u64 } else { 0 };
+            self.0 = (self.0 << 1) ^ if msb != 0 { self.1 .0 as u64 } else { 0 };
+        }
+    }
+
+    /// Advances the LFSR by `steps` steps at once, like `skip` but in $O(\log steps)$. Each step
+    /// multiplies the state by $x$ modulo the feedback polynomial, so `steps` steps multiply it by
+    /// $x^{steps}$, which is computed by square-and-multiply. This lets a sequence be split into
+    /// streams that start far apart, e.g. one per thread.
+    pub fn jump(&mut self, steps: u64) {
+        let state = p128(u128::from(self.0));
+        self.0 = mul_mod(state, pow_mod(p128(2), steps, self.1), self.1).0 as u64;
+    }
+
+    /// Returns an endless iterator over the output, one bit per item.
+    pub fn bits(&mut self) -> Bits<'_> {
+        Bits(self)
+    }
+
+    /// Returns an endless iterator over the output, one byte per item, most significant bit first
+    /// like `next`.
+    pub fn bytes(&mut self) -> Bytes<'_> {
+        Bytes(self)
+    }
+
+    /// Clocks the LFSR `n` times, returning every output bit in order.
+    pub fn take_bits(&mut self, n: usize) -> Vec<bool> {
+        self.bits().take(n).collect()
+    }
+
+    /// Clocks the LFSR `8 * n` times, returning the output packed into bytes like `bytes`.
+    pub fn take_bytes(&mut self, n: usize) -> Vec<u8> {
+        self.bytes().take(n).collect()
+    }
+
+    /// XORs `data` with the output of the LFSR, one byte of output per byte of data, in the same
+    /// order as `bytes`.
+    ///
+    /// This is an additive scrambler, which breaks up long runs of zeros or ones in a bit stream.
+    /// The receiver descrambles by XORing with the same output, so it needs an LFSR in the same
+    /// state, and a dropped bit throws it off for the rest of the stream. A self-synchronizing
+    /// scrambler avoids that by feeding the scrambled bits back into the register instead, at the
+    /// cost of multiplying bit errors.
+    pub fn scramble(&mut self, data: &mut [u8]) {
+        for (b, k) in data.iter_mut().zip(self.bytes()) {
+            *b ^= k;
         }
     }
 
+    /// Undoes `scramble` with an LFSR in the same state. XOR is its own inverse, so this is the
+    /// same operation.
+    pub fn descramble(&mut self, data: &mut [u8]) {
+        self.scramble(data)
+    }
+
     pub fn skip_backwards(&mut self, bits: u64) {
         // just iterate naively
         for _ in 0..bits {
             let lsb = self.0 & 1;
-            self.0 = (self.0 >> 1)
-                ^ if lsb != 0 {
-                    (POLYNOMIAL.0 >> 1) as u64
-                } else {
-                    0
-                };
+            self.0 = (self.0 >> 1) ^ if lsb != 0 { (self.1 .0 >> 1) as u64 } else { 0 };
         }
     }
 }
 
+/// With the `rand-core` feature, an `Lfsr` can be used wherever the `rand` ecosystem expects a
+/// random number generator, e.g. for simulations or reproducible test data. `fill_bytes` follows
+/// `rand_core`'s convention of filling from `next_u64` in little-endian order, so its bytes come
+/// out in a different order than from `bytes`.
+///
+/// **This is not a cryptographically secure generator.** The output is linear: 128 consecutive
+/// bits are enough to recover the polynomial with Berlekamp-Massey, and 64 bits are the state
+/// itself, after which every past and future output can be computed. Never use it for keys,
+/// nonces, or anything else that has to be unpredictable.
+#[cfg(feature = "rand-core")]
+impl RngCore for Lfsr {
+    fn next_u32(&mut self) -> u32 {
+        self.next(32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.next(64)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        impls::fill_bytes_via_next(self, dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// An iterator over the output bits of an `Lfsr`, from `Lfsr::bits`.
+pub struct Bits<'a>(&'a mut Lfsr);
+
+impl Iterator for Bits<'_> {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<bool> {
+        Some(self.0.next(1) == 1)
+    }
+}
+
+/// An iterator over the output bytes of an `Lfsr`, from `Lfsr::bytes`.
+pub struct Bytes<'a>(&'a mut Lfsr);
+
+impl Iterator for Bytes<'_> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        Some(self.0.next(8) as u8)
+    }
+}
+
+/// The shrinking generator, which combines two LFSRs into a sequence that isn't linear. Both are
+/// clocked together, and the bit from the second is output only when the first outputs a 1, so
+/// the first decides which bits of the second are kept.
+///
+/// This makes the output much harder to predict than a single LFSR: Berlekamp-Massey needs an
+/// exponentially longer sequence. Nonetheless, better attacks than brute force are known, so it
+/// shouldn't be relied on as a stream cipher either.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShrinkingGenerator {
+    selector: Lfsr,
+    source: Lfsr,
+}
+
+impl ShrinkingGenerator {
+    pub fn new(selector: Lfsr, source: Lfsr) -> Self {
+        Self { selector, source }
+    }
+
+    pub fn next_bit(&mut self) -> bool {
+        loop {
+            let select = self.selector.next(1);
+            let bit = self.source.next(1);
+            if select == 1 {
+                return bit == 1;
+            }
+        }
+    }
+
+    /// Fills `dest` with output bits, most significant bit of each byte first.
+    pub fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for b in dest.iter_mut() {
+            *b = (0..8).fold(0, |b, _| (b << 1) | u8::from(self.next_bit()));
+        }
+    }
+}
+
+/// A Fibonacci LFSR of up to 64 bits, as specified by scramblers and PRBS test patterns.
+///
+/// Where a Galois LFSR XORs the feedback into several bits of the state at once, a Fibonacci LFSR
+/// XORs the tapped bits of the state together, outputs the lowest bit, shifts right, and feeds
+/// the parity back into the highest bit. For the same polynomial both produce the same set of
+/// sequences, but specs that give an exact bit stream usually describe it this way.
+///
+/// The taps are the exponents of the feedback polynomial, with bit $e - 1$ set for $x^e$, so
+/// $x^{16} + x^{14} + x^{13} + x^{11} + 1$ is `0xb400`. The tap for $x^e$ reads bit `width - e`
+/// of the state, and the tap for $x^{width}$ is the output bit itself, which must be tapped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FibonacciLfsr {
+    state: u64,
+    mask: u64,
+    width: u32,
+}
+
+impl FibonacciLfsr {
+    pub fn new(width: u32, taps: u64, mut seed: u64) -> Self {
+        assert!((1..=64).contains(&width), "width must be between 1 and 64");
+        assert!(
+            (taps >> (width - 1)) == 1,
+            "taps must include x^{} and nothing higher",
+            width
+        );
+
+        let all = u64::MAX >> (64 - width);
+        seed &= all;
+        if seed == 0 {
+            seed = 1;
+        }
+
+        Self {
+            state: seed,
+            mask: taps.reverse_bits() >> (64 - width),
+            width,
+        }
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next(&mut self, bits: u64) -> u64 {
+        debug_assert!(bits <= 64);
+        let mut x = 0;
+        for _ in 0..bits {
+            let feedback = u64::from((self.state & self.mask).count_ones() & 1);
+            x = (x << 1) | (self.state & 1);
+            self.state = (self.state >> 1) | (feedback << (self.width - 1));
+        }
+        x
+    }
+
+    pub fn prev(&mut self, bits: u64) -> u64 {
+        debug_assert!(bits <= 64);
+        let all = u64::MAX >> (64 - self.width);
+        let mut x = 0;
+        for _ in 0..bits {
+            // the bit shifted out was tapped, so it's whatever makes the parity match the bit that
+            // was fed back
+            let feedback = self.state >> (self.width - 1);
+            let shifted = (self.state << 1) & all;
+            let lsb = feedback ^ u64::from((shifted & self.mask).count_ones() & 1);
+            x = (x >> 1) | (lsb << (bits - 1));
+            self.state = shifted | lsb;
+        }
+        x
+    }
+}
+
+/// Finds the shortest LFSR that produces `bits` with the Berlekamp-Massey algorithm, returning its
+/// feedback polynomial. The degree of the polynomial is the length of the LFSR, and the first that
+/// many bits are its initial output.
+///
+/// Berlekamp-Massey finds the connection polynomial $C(x) = 1 + c_1 x + \ldots + c_L x^L$ with
+/// $s_n = c_1 s_{n-1} + \ldots + c_L s_{n-L}$ for every bit. It goes through the bits one at a
+/// time, and whenever $C$ predicts the next bit wrong, adds a shifted copy of the polynomial from
+/// before the last change in length to fix the discrepancy, growing $L$ when the old one is too
+/// short. For a Galois LFSR like `Lfsr`, whose output has the feedback polynomial as its
+/// characteristic polynomial, $C$ is the reciprocal $x^L P(1/x)$ of the feedback polynomial, so
+/// this returns the reciprocal of $C$. That way the output of `Lfsr::with_polynomial(seed, poly)`
+/// gives back `poly`.
+///
+/// An LFSR of length $L$ is determined by $2L$ bits of its output, so 128 bits are enough to
+/// recover any 64-bit `Lfsr`. Panics if the shortest LFSR is longer than 127 bits.
+pub fn berlekamp_massey(bits: &[bool]) -> p128 {
+    let n = bits.len();
+    let mut c = vec![false; n + 1];
+    c[0] = true;
+    let mut b = c.clone();
+    let mut l = 0;
+    let mut m = 1;
+
+    for i in 0..n {
+        // the difference between the next bit and the one C predicts
+        let d = (1..=l).fold(bits[i], |d, j| d ^ (c[j] & bits[i - j]));
+        if !d {
+            m += 1;
+            continue;
+        }
+
+        let t = c.clone();
+        for j in 0..=n - m {
+            c[j + m] ^= b[j];
+        }
+        if 2 * l <= i {
+            l = i + 1 - l;
+            b = t;
+            m = 1;
+        } else {
+            m += 1;
+        }
+    }
+
+    assert!(l <= 127, "the shortest LFSR has {} bits", l);
+    p128((0..=l).filter(|&j| c[j]).fold(0, |p, j| p | (1 << (l - j))))
+}
+
+/// Computes the CRC remainder of `data` with the generator polynomial `poly`, by clocking the
+/// data through a Galois LFSR one bit at a time, most significant bit first.
+///
+/// This is the same shift register as `Lfsr`, except that each data bit is XORed into the bit
+/// being shifted out before deciding whether to apply the feedback polynomial. The register then
+/// holds $data \cdot x^d \bmod poly$, where $d$ is the degree of `poly`, which is exactly the
+/// remainder a CRC computes. Real CRCs add conventions on top, like a non-zero initial value,
+/// reflected bits and a final XOR, which this leaves to the caller.
+pub fn as_crc(poly: p128, data: &[u8]) -> u64 {
+    assert!(poly.0 > 1, "polynomial must have a degree of at least 1");
+    let degree = 127 - poly.0.leading_zeros();
+    assert!(degree <= 64, "polynomial degree must be at most 64");
+
+    let mask = u64::MAX >> (64 - degree);
+    let feedback = poly.0 as u64 & mask;
+
+    let mut register = 0u64;
+    for byte in data {
+        for i in (0..8).rev() {
+            let bit = ((register >> (degree - 1)) ^ u64::from(byte >> i)) & 1;
+            register = (register << 1) & mask;
+            if bit != 0 {
+                register ^= feedback;
+            }
+        }
+    }
+    register
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -104,4 +513,303 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn iterators() {
+        let mut lfsr = Lfsr::new(1);
+        let bytes = lfsr.bytes().take(16).collect::<Vec<_>>();
+        assert_eq!(bytes[..8], 0x0000000000000001u64.to_be_bytes());
+        assert_eq!(bytes[8..], 0x000000000000001bu64.to_be_bytes());
+
+        let bits = lfsr.bits().take(64).collect::<Vec<_>>();
+        let x = bits.iter().fold(0, |x, b| (x << 1) | u64::from(*b));
+        assert_eq!(x, 0x0000000000000145);
+
+        // the iterators work with the usual combinators
+        let mut a = Lfsr::new(1);
+        let mut b = Lfsr::new(1);
+        b.skip(64);
+        assert!(a
+            .bits()
+            .skip(64)
+            .zip(b.bits())
+            .take(200)
+            .all(|(x, y)| x == y));
+    }
+
+    #[cfg(feature = "rand-core")]
+    #[test]
+    fn rng_core() {
+        let mut rng = Lfsr::new(1);
+        let mut lfsr = Lfsr::new(1);
+
+        assert_eq!(rng.next_u64(), lfsr.next(64));
+        assert_eq!(rng.next_u32(), lfsr.next(32) as u32);
+
+        let mut buf = [0; 20];
+        rng.fill_bytes(&mut buf);
+        let mut expected = lfsr.next(64).to_le_bytes().to_vec();
+        expected.extend_from_slice(&lfsr.next(64).to_le_bytes());
+        expected.extend_from_slice(&(lfsr.next(32) as u32).to_le_bytes());
+        assert_eq!(buf[..], expected);
+        assert_eq!(rng, lfsr);
+    }
+
+    #[test]
+    fn take() {
+        let mut lfsr = Lfsr::new(1);
+        let mut expected = Lfsr::new(1);
+        for _ in 0..4 {
+            let bits = lfsr.take_bits(64);
+            let x = expected.next(64);
+            assert_eq!(bits.len(), 64);
+            for (i, b) in bits.iter().enumerate() {
+                assert_eq!(*b, (x >> (63 - i)) & 1 == 1);
+            }
+        }
+
+        assert_eq!(lfsr.take_bytes(8), expected.next(64).to_be_bytes());
+        assert!(lfsr.take_bits(0).is_empty());
+        assert_eq!(lfsr, expected);
+    }
+
+    #[test]
+    fn scrambler() {
+        let message = [0u8; 24];
+        let mut data = message;
+
+        let mut tx = Lfsr::new(0xace1);
+        let mut rx = tx.clone();
+        tx.scramble(&mut data[..10]);
+        tx.scramble(&mut data[10..]);
+        assert_ne!(data, message);
+        assert_eq!(data[..8], Lfsr::new(0xace1).next(64).to_be_bytes());
+
+        rx.descramble(&mut data);
+        assert_eq!(data, message);
+        assert_eq!(tx, rx);
+    }
+
+    #[test]
+    fn serialization() {
+        let mut lfsr = Lfsr::with_polynomial(0xace1, p128(0x1_b000_0000_0000_0001));
+        lfsr.skip(1000);
+
+        let bytes = lfsr.to_bytes();
+        assert_eq!(bytes[8..], 0xb000_0000_0000_0001u64.to_be_bytes());
+        let mut restored = Lfsr::from_bytes(&bytes).unwrap();
+        assert_eq!(restored, lfsr);
+        for _ in 0..10 {
+            assert_eq!(restored.next(64), lfsr.next(64));
+        }
+
+        assert_eq!(Lfsr::from_bytes(&[0; 16]), Err(LfsrError::ZeroState));
+        let mut bytes = Lfsr::new(1).to_bytes();
+        bytes[15] ^= 1;
+        assert_eq!(Lfsr::from_bytes(&bytes), Err(LfsrError::InvalidPolynomial));
+    }
+
+    #[test]
+    fn shrinking() {
+        // the first 64 bits out of an LFSR are its seed, so the selector keeps the high nibble of
+        // every byte of the source's seed
+        let mut gen = ShrinkingGenerator::new(
+            Lfsr::new(0xf0f0_f0f0_f0f0_f0f0),
+            Lfsr::new(0x0123_4567_89ab_cdef),
+        );
+        let mut buf = [0; 4];
+        gen.fill_bytes(&mut buf);
+        assert_eq!(buf, [0x02, 0x46, 0x8a, 0xce]);
+
+        // both LFSRs are clocked for every bit, including the ones that are dropped
+        let mut a = Lfsr::new(0xf0f0_f0f0_f0f0_f0f0);
+        let mut b = Lfsr::new(0x0123_4567_89ab_cdef);
+        a.skip(60);
+        b.skip(60);
+        assert_eq!(gen, ShrinkingGenerator::new(a, b));
+
+        let mut gen = ShrinkingGenerator::new(Lfsr::new(1), Lfsr::new(2));
+        let bits = iter::repeat_with(|| gen.next_bit())
+            .take(64)
+            .collect::<Vec<_>>();
+        assert!(bits.iter().any(|b| *b) && bits.iter().any(|b| !*b));
+    }
+
+    #[test]
+    fn custom_polynomial() {
+        // x^64 + x^63 + x^61 + x^60 + 1, also primitive
+        let mut lfsr = Lfsr::with_polynomial(1, p128(0x1_b000_0000_0000_0001));
+        let buf = iter::repeat_with(|| lfsr.next(64))
+            .take(4)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            buf,
+            &[
+                0x0000000000000001,
+                0xc71c71c71c71c71d,
+                0x21d21d21d21d21d3,
+                0xb791d3b791d3b790
+            ]
+        );
+        assert_eq!(lfsr.prev(64), 0xb791d3b791d3b790);
+
+        // x^64 + 1 just rotates the state, so it isn't maximal, but it still runs backwards
+        let mut lfsr = Lfsr::with_polynomial(0x1234, p128(0x1_0000_0000_0000_0001));
+        let x = lfsr.next(64);
+        assert_eq!(lfsr.0, 0x1234);
+        assert_eq!(lfsr.prev(64), x);
+        assert_eq!(lfsr.0, 0x1234);
+        lfsr.skip(5);
+        lfsr.skip_backwards(5);
+        assert_eq!(lfsr.0, 0x1234);
+    }
+
+    #[test]
+    fn period() {
+        assert_eq!(Lfsr::new(1).period(), u64::MAX);
+        assert_eq!(Lfsr::new(0xdead_beef).period(), u64::MAX);
+        assert_eq!(
+            Lfsr::with_polynomial(42, p128(0x1_b000_0000_0000_0001)).period(),
+            u64::MAX
+        );
+
+        // x^64 + 1 rotates the state, so the period depends on the state
+        let rotate = p128(0x1_0000_0000_0000_0001);
+        assert_eq!(Lfsr::with_polynomial(0x1234, rotate).period(), 64);
+        assert_eq!(
+            Lfsr::with_polynomial(0x0101_0101_0101_0101, rotate).period(),
+            8
+        );
+        assert_eq!(Lfsr::with_polynomial(u64::MAX, rotate).period(), 1);
+
+        // (x^32 + x^22 + x^2 + x + 1)(x^32 + x^7 + x^5 + x^3 + x^2 + x + 1) is the product of two
+        // primitive polynomials of degree 32, so the period is only 2^32 - 1
+        let lfsr = Lfsr::with_polynomial(1, p128(0x1_0040_00a8_2bc0_034d));
+        assert_eq!(lfsr.period(), 0xffff_ffff);
+
+        let mut zero = Lfsr::new(1);
+        zero.0 = 0;
+        assert_eq!(zero.period(), 1);
+    }
+
+    #[test]
+    fn state() {
+        let mut lfsr = Lfsr::new(0);
+        assert_eq!(lfsr.state(), 1);
+
+        assert_eq!(lfsr.set_state(0), Err(LfsrError::ZeroState));
+        assert_eq!(lfsr.state(), 1);
+
+        assert_eq!(lfsr.set_state(0x145), Ok(()));
+        assert_eq!(lfsr.state(), 0x145);
+        assert_eq!(lfsr.next(64), 0x145);
+        assert_eq!(lfsr.next(64), 0x1db7);
+    }
+
+    #[test]
+    fn jump() {
+        for steps in [0, 1, 63, 64, 65, 1000, 123_457] {
+            for poly in [POLYNOMIAL, p128(0x1_b000_0000_0000_0001)] {
+                let mut skipped = Lfsr::with_polynomial(0x1234_5678, poly);
+                let mut jumped = skipped.clone();
+                skipped.skip(steps);
+                jumped.jump(steps);
+                assert_eq!(skipped, jumped);
+            }
+        }
+
+        // far jumps compose, and a full period gets back to the start
+        let mut a = Lfsr::new(1);
+        a.jump(1 << 62);
+        a.jump(1 << 62);
+        a.skip(3);
+        let mut b = Lfsr::new(1);
+        b.jump((1 << 63) + 3);
+        assert_eq!(a, b);
+
+        a.jump(u64::MAX);
+        assert_eq!(a, b);
+
+        // and jumping back a period minus n steps is the same as going back n steps
+        a.jump(u64::MAX - 1000);
+        b.skip_backwards(1000);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn fibonacci() {
+        // the 16-bit example from Wikipedia's LFSR article, with taps 16, 14, 13 and 11
+        let mut lfsr = FibonacciLfsr::new(16, 0xb400, 0xace1);
+        assert_eq!(lfsr.next(1), 1);
+        assert_eq!(lfsr.state(), 0x5670);
+        assert_eq!(lfsr.next(1), 0);
+        assert_eq!(lfsr.state(), 0xab38);
+
+        // the first 16 bits out are the seed, lowest bit first
+        assert_eq!(lfsr.prev(2), 0b10);
+        assert_eq!(lfsr.next(16), 0xace1u16.reverse_bits() as u64);
+        assert_eq!(lfsr.next(32), 0x44e2ec23);
+        assert_eq!(lfsr.state(), 0xe39d);
+        assert_eq!(lfsr.prev(48), 0x8735_44e2ec23);
+        assert_eq!(lfsr.state(), 0xace1);
+
+        // which is maximal, with a period of 2^16 - 1
+        let mut steps = 1;
+        lfsr.next(1);
+        while lfsr.state() != 0xace1 {
+            lfsr.next(1);
+            steps += 1;
+        }
+        assert_eq!(steps, 65535);
+    }
+
+    #[test]
+    fn berlekamp_massey_recovers_polynomial() {
+        for poly in [POLYNOMIAL, p128(0x1_b000_0000_0000_0001)] {
+            let mut lfsr = Lfsr::with_polynomial(0xdead_beef, poly);
+            let bits = lfsr.bits().take(128).collect::<Vec<_>>();
+            assert_eq!(berlekamp_massey(&bits), poly);
+        }
+
+        // 1, 1, 0 repeating follows s_n = s_{n-1} + s_{n-2}, so C(x) = 1 + x + x^2
+        let bits = [true, true, false, true, true, false, true, true];
+        assert_eq!(berlekamp_massey(&bits), p128(0b111));
+
+        assert_eq!(berlekamp_massey(&[false; 10]), p128(1));
+        assert_eq!(berlekamp_massey(&[]), p128(1));
+    }
+
+    #[test]
+    fn lfsr_crc() {
+        use crate::crc::{crc32, CrcVariant};
+
+        let crc32_poly = p128(0x104c11db7);
+
+        // the register holds the remainder of data * x^32 by the polynomial
+        let data = b"123456789";
+        let mut shifted = 0u128;
+        for b in data {
+            shifted = (shifted << 8) | u128::from(*b);
+        }
+        assert_eq!(
+            as_crc(crc32_poly, data),
+            (p128(shifted << 32) % crc32_poly).0 as u64
+        );
+
+        // an initial value of all ones is the same as inverting the first 32 bits of the data
+        let mut inverted = data.to_vec();
+        inverted[..4].iter_mut().for_each(|b| *b ^= 0xff);
+        assert_eq!(
+            as_crc(crc32_poly, &inverted) as u32,
+            CrcVariant::Mpeg2.checksum(data)
+        );
+
+        // crc32 also reflects the bits of each byte and of the result, and inverts the result
+        let mut reflected = inverted.clone();
+        reflected.iter_mut().for_each(|b| *b = b.reverse_bits());
+        assert_eq!(
+            (as_crc(crc32_poly, &reflected) as u32).reverse_bits() ^ 0xffffffff,
+            crc32(data)
+        );
+    }
 }
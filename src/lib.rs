@@ -3,10 +3,13 @@ pub mod aes;
 pub mod chacha20;
 pub mod crc;
 pub mod diffie_hellman;
+pub mod gf_matrix;
 pub mod hamming;
 pub mod hmac;
 pub mod lfsr;
 pub mod md5;
 pub mod reed_solomon;
+pub mod secure_channel;
 pub mod sha1;
 pub mod shamir;
+pub mod util;
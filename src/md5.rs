@@ -1,3 +1,6 @@
+use crate::util::{ct_eq, to_hex};
+use std::io::{self, Read, Write};
+
 pub struct MD5 {}
 
 const SHIFTS: [u32; 64] = [
@@ -18,78 +21,305 @@ const K: [u32; 64] = [
     0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391,
 ];
 
-impl MD5 {
-    fn pad_message(message: &[u8]) -> Vec<u8> {
-        let message_length: u64 = message.len() as u64 * 8;
-        let mut result = message.to_owned();
+/// Runs the MD5 compression function on one 64-byte block, updating the state in place.
+///
+/// This is the 64-round core of MD5, for building other constructions on it. A hash starts from
+/// the state `[0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476]`, compresses each block of the
+/// padded message in turn, and writes the state out as little-endian words. Padding is up to the
+/// caller: a 0x80 byte, zeros up to 56 bytes mod 64, and the message length in bits as a
+/// little-endian `u64`.
+pub fn md5_compress(state: &mut [u32; 4], block: &[u8; 64]) {
+    // Little endian
+    let m: Vec<u32> = block
+        .chunks(4)
+        .map(|b| {
+            ((b[3] as u32) << 24) | ((b[2] as u32) << 16) | ((b[1] as u32) << 8) | (b[0] as u32)
+        })
+        .collect();
+
+    let [a0, b0, c0, d0] = *state;
+    let (mut a, mut b, mut c, mut d) = (a0, b0, c0, d0);
+
+    for i in 0..64 {
+        let (mut f, g) = match i {
+            0..=15 => ((b & c) | ((!b) & d), i),
+            16..=31 => ((d & b) | ((!d) & c), (5 * i + 1) % 16),
+            32..=47 => (b ^ c ^ d, (3 * i + 5) % 16),
+            _ => (c ^ (b | (!d)), (7 * i) % 16),
+        };
+
+        f = f.wrapping_add(a).wrapping_add(K[i]).wrapping_add(m[g]);
+        a = d;
+        d = c;
+        c = b;
+        b = b.wrapping_add(f.rotate_left(SHIFTS[i]));
+    }
+
+    *state = [
+        a0.wrapping_add(a),
+        b0.wrapping_add(b),
+        c0.wrapping_add(c),
+        d0.wrapping_add(d),
+    ];
+}
 
-        result.push(0x80);
+/// An incremental MD5 hasher, for input that arrives in pieces, like a stream or a file. Partial
+/// blocks are buffered between calls to `update`, and the padding is only added by `finalize`,
+/// so the digest is the same as `MD5::hash` of everything passed to `update`.
+#[derive(Clone)]
+pub struct Md5 {
+    state: [u32; 4],
+    buffer: [u8; 64],
+    buffered: usize,
+    length: u64,
+}
 
-        while (result.len() * 8 + 64) % 512 != 0 {
-            result.push(0);
+impl Md5 {
+    pub fn new() -> Self {
+        Self {
+            state: [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476],
+            buffer: [0; 64],
+            buffered: 0,
+            length: 0,
         }
+    }
+
+    /// Creates a hasher that resumes from a captured state, after `bytes_processed` bytes of
+    /// input. The state only changes once per 64-byte block, so `bytes_processed` must be a
+    /// multiple of 64.
+    ///
+    /// The digest is the state itself, so the digest of a message can be used to keep hashing
+    /// past its padding: with `from_state` and the digest of $m$, anyone can compute the digest of
+    /// $m || padding || m'$ without knowing $m$. This is the length extension attack, and why
+    /// `md5(key || message)` isn't a secure MAC.
+    pub fn from_state(a0: u32, b0: u32, c0: u32, d0: u32, bytes_processed: u64) -> Self {
+        assert!(
+            bytes_processed.is_multiple_of(64),
+            "bytes processed must be a whole number of blocks"
+        );
 
-        for b in 0..8 {
-            result.push((message_length >> (b * 8)) as u8);
+        Self {
+            state: [a0, b0, c0, d0],
+            buffer: [0; 64],
+            buffered: 0,
+            length: bytes_processed,
         }
+    }
 
-        result
+    /// Returns the state after the last complete block, and the number of bytes hashed up to that
+    /// block, for resuming with `from_state`. Bytes past the last complete block are only
+    /// buffered, so they have to be passed to `update` again after resuming.
+    pub fn state(&self) -> ([u32; 4], u64) {
+        (self.state, self.length - self.buffered as u64)
     }
 
-    pub fn hash(input: &[u8]) -> [u8; 16] {
-        let mut a0 = 0x67452301u32;
-        let mut b0 = 0xefcdab89u32;
-        let mut c0 = 0x98badcfeu32;
-        let mut d0 = 0x10325476u32;
-
-        let padded_msg = Self::pad_message(input);
-
-        for chunk in padded_msg.chunks(64) {
-            // Little endian
-            let m: Vec<u32> = chunk
-                .chunks(4)
-                .map(|b| {
-                    ((b[3] as u32) << 24)
-                        | ((b[2] as u32) << 16)
-                        | ((b[1] as u32) << 8)
-                        | (b[0] as u32)
-                })
-                .collect();
-
-            let (mut a, mut b, mut c, mut d) = (a0, b0, c0, d0);
-
-            for i in 0..64 {
-                let (mut f, g) = match i {
-                    0..=15 => ((b & c) | ((!b) & d), i),
-                    16..=31 => ((d & b) | ((!d) & c), (5 * i + 1) % 16),
-                    32..=47 => (b ^ c ^ d, (3 * i + 5) % 16),
-                    _ => (c ^ (b | (!d)), (7 * i) % 16),
-                };
-
-                f = f.wrapping_add(a).wrapping_add(K[i]).wrapping_add(m[g]);
-                a = d;
-                d = c;
-                c = b;
-                b = b.wrapping_add(f.rotate_left(SHIFTS[i]));
+    pub fn update(&mut self, mut input: &[u8]) {
+        self.length = self.length.wrapping_add(input.len() as u64);
+
+        // top up a partial block first
+        if self.buffered > 0 {
+            let n = input.len().min(64 - self.buffered);
+            self.buffer[self.buffered..self.buffered + n].copy_from_slice(&input[..n]);
+            self.buffered += n;
+            input = &input[n..];
+
+            if self.buffered < 64 {
+                return;
             }
+            md5_compress(&mut self.state, &self.buffer);
+            self.buffered = 0;
+        }
 
-            a0 = a0.wrapping_add(a);
-            b0 = b0.wrapping_add(b);
-            c0 = c0.wrapping_add(c);
-            d0 = d0.wrapping_add(d);
+        let mut blocks = input.chunks_exact(64);
+        for block in &mut blocks {
+            md5_compress(&mut self.state, block.try_into().unwrap());
         }
 
+        let rest = blocks.remainder();
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.buffered = rest.len();
+    }
+
+    pub fn finalize(mut self) -> [u8; 16] {
+        // append a 1 bit, zeros up to 56 bytes mod 64, and the length in bits, little-endian
+        let message_length = self.length.wrapping_mul(8);
+        let zeros = (55 - self.buffered as isize).rem_euclid(64) as usize;
+        self.update(&[0x80]);
+        self.update(&[0; 64][..zeros]);
+        self.update(&message_length.to_le_bytes());
+        debug_assert_eq!(self.buffered, 0);
+
         let mut result = [0; 16];
 
-        for (i, v) in [a0, b0, c0, d0].into_iter().enumerate() {
-            result[i * 4] = v as u8;
-            result[i * 4 + 1] = (v >> 8) as u8;
-            result[i * 4 + 2] = (v >> 16) as u8;
-            result[i * 4 + 3] = (v >> 24) as u8;
+        for (i, v) in self.state.into_iter().enumerate() {
+            result[i * 4..i * 4 + 4].copy_from_slice(&v.to_le_bytes());
         }
 
         result
     }
+
+    /// Finishes the hash and formats the digest as lowercase hex, like `MD5::hash_hex`.
+    pub fn finalize_hex(self) -> String {
+        to_hex(&self.finalize())
+    }
+}
+
+impl Default for Md5 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Hashes everything written to it, so an `Md5` can be the end of an I/O pipeline, e.g. with
+/// `io::copy`. Writes never fail, and `flush` does nothing: the digest comes from `finalize`.
+impl Write for Md5 {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl MD5 {
+    pub fn hash(input: &[u8]) -> [u8; 16] {
+        let mut md5 = Md5::new();
+        md5.update(input);
+        md5.finalize()
+    }
+
+    /// Hashes each of `inputs`, returning the digests in the same order.
+    pub fn hash_many(inputs: &[&[u8]]) -> Vec<[u8; 16]> {
+        inputs.iter().map(|input| Self::hash(input)).collect()
+    }
+
+    /// Hashes `input` and checks it against `expected` in constant time, so the time taken doesn't
+    /// reveal how much of the expected digest a guess got right.
+    pub fn verify(input: &[u8], expected: &[u8; 16]) -> bool {
+        ct_eq(&Self::hash(input), expected)
+    }
+
+    /// Hashes everything `reader` produces until the end of its input, a buffer at a time, so
+    /// large files don't have to fit in memory.
+    pub fn hash_reader<R: Read>(reader: &mut R) -> io::Result<[u8; 16]> {
+        let mut md5 = Md5::new();
+        let mut buffer = [0; 8192];
+
+        loop {
+            let n = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            md5.update(&buffer[..n]);
+        }
+
+        Ok(md5.finalize())
+    }
+
+    /// Hashes `input` and formats the digest as lowercase hex, matching the output of `md5sum`.
+    ///
+    /// MD5 works on little-endian words, so the digest bytes are the four state words each written
+    /// least significant byte first. SHA-1 writes its words big-endian instead, so printing the
+    /// state words of the two hashes the same way gives the wrong answer for one of them. Going
+    /// through the digest bytes, as this does, is always correct.
+    pub fn hash_hex(input: &[u8]) -> String {
+        to_hex(&Self::hash(input))
+    }
+
+    /// Hashes `input` and returns the digest as a big-endian integer, so formatting it with
+    /// `{:032x}` gives the same string as `hash_hex`.
+    pub fn hash_u128(input: &[u8]) -> u128 {
+        u128::from_be_bytes(Self::hash(input))
+    }
+}
+
+// The alphabet crypt(3) uses for its base 64 encoding, which differs from RFC 4648's.
+const CRYPT_ALPHABET: &[u8; 64] =
+    b"./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// Hashes a password with the `$1$` MD5 crypt scheme from FreeBSD, which old `/etc/shadow` files
+/// and many appliances still use, returning the string `$1$salt$hash`.
+///
+/// The salt is at most 8 characters, and a leading `$1$` and anything from the next `$` on are
+/// ignored, so a stored hash can be passed as the salt to check a password against it. The
+/// password, salt and an intermediate digest are mixed together, then rehashed 1000 times in a
+/// pattern depending on the round number, to slow down guessing. That was a lot of work in 1994;
+/// today it is fast enough to brute force, so this is only for checking or migrating old hashes.
+pub fn md5_crypt(password: &[u8], salt: &[u8]) -> String {
+    let salt = salt.strip_prefix(b"$1$").unwrap_or(salt);
+    let salt = salt.split(|&b| b == b'$').next().unwrap_or(salt);
+    let salt = &salt[..salt.len().min(8)];
+
+    let alternate = {
+        let mut md5 = Md5::new();
+        md5.update(password);
+        md5.update(salt);
+        md5.update(password);
+        md5.finalize()
+    };
+
+    let mut md5 = Md5::new();
+    md5.update(password);
+    md5.update(b"$1$");
+    md5.update(salt);
+    for chunk in password.chunks(16) {
+        md5.update(&alternate[..chunk.len()]);
+    }
+    // a quirk of the original: a 0 byte for each 1 bit of the length, and the first byte of the
+    // password for each 0 bit
+    let mut i = password.len();
+    while i > 0 {
+        if i & 1 == 1 {
+            md5.update(&[0]);
+        } else {
+            md5.update(&password[..1]);
+        }
+        i >>= 1;
+    }
+    let mut digest = md5.finalize();
+
+    for round in 0..1000 {
+        let mut md5 = Md5::new();
+        if round % 2 == 1 {
+            md5.update(password);
+        } else {
+            md5.update(&digest);
+        }
+        if round % 3 != 0 {
+            md5.update(salt);
+        }
+        if round % 7 != 0 {
+            md5.update(password);
+        }
+        if round % 2 == 1 {
+            md5.update(&digest);
+        } else {
+            md5.update(password);
+        }
+        digest = md5.finalize();
+    }
+
+    let mut result = format!("$1${}$", String::from_utf8_lossy(salt));
+    let mut encode = |mut v: u32, n: usize| {
+        for _ in 0..n {
+            result.push(CRYPT_ALPHABET[(v & 0x3f) as usize] as char);
+            v >>= 6;
+        }
+    };
+    // the digest bytes are shuffled into groups of three, 4 characters each
+    for [a, b, c] in [[0, 6, 12], [1, 7, 13], [2, 8, 14], [3, 9, 15], [4, 10, 5]] {
+        encode(
+            u32::from(digest[a]) << 16 | u32::from(digest[b]) << 8 | u32::from(digest[c]),
+            4,
+        );
+    }
+    encode(u32::from(digest[11]), 2);
+
+    result
 }
 
 #[cfg(test)]
@@ -137,4 +367,172 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn incremental() {
+        let data = (0..1000).map(|i| (i * 31 + 7) as u8).collect::<Vec<_>>();
+        assert_eq!(
+            MD5::hash(&data),
+            0x2b1e78d5765de9e10495a01412a1cf22u128.to_be_bytes()
+        );
+
+        for chunk_size in [1, 3, 55, 56, 63, 64, 65, 127, 1000] {
+            let mut md5 = Md5::new();
+            for chunk in data.chunks(chunk_size) {
+                md5.update(chunk);
+            }
+            assert_eq!(md5.finalize(), MD5::hash(&data));
+        }
+
+        // odd-sized pieces that straddle block boundaries
+        let mut md5 = Md5::new();
+        md5.update(&data[..7]);
+        md5.update(&data[7..120]);
+        md5.update(&[]);
+        md5.update(&data[120..641]);
+        md5.update(&data[641..]);
+        assert_eq!(md5.finalize(), MD5::hash(&data));
+
+        assert_eq!(Md5::new().finalize(), MD5::hash(b""));
+    }
+
+    #[test]
+    fn resume() {
+        let data = (0..300).map(|i| (i * 7) as u8).collect::<Vec<_>>();
+
+        let mut md5 = Md5::new();
+        md5.update(&data[..150]);
+        let ([a, b, c, d], processed) = md5.state();
+        assert_eq!(processed, 128);
+
+        let mut resumed = Md5::from_state(a, b, c, d, processed);
+        resumed.update(&data[128..]);
+        assert_eq!(resumed.finalize(), MD5::hash(&data));
+
+        // length extension: keep hashing from the digest of "abc", after its padding
+        let digest = MD5::hash(b"abc");
+        let words = digest
+            .chunks(4)
+            .map(|w| u32::from_le_bytes(w.try_into().unwrap()))
+            .collect::<Vec<_>>();
+        let mut extended = Md5::from_state(words[0], words[1], words[2], words[3], 64);
+        extended.update(b"def");
+
+        let mut message = b"abc".to_vec();
+        message.push(0x80);
+        message.resize(56, 0);
+        message.extend_from_slice(&24u64.to_le_bytes());
+        message.extend_from_slice(b"def");
+        assert_eq!(extended.finalize(), MD5::hash(&message));
+    }
+
+    #[test]
+    fn reader() {
+        let data = (0..20000)
+            .map(|i| (i * 13 + i / 256) as u8)
+            .collect::<Vec<_>>();
+        let mut cursor = io::Cursor::new(&data);
+        assert_eq!(MD5::hash_reader(&mut cursor).unwrap(), MD5::hash(&data));
+
+        let mut empty = io::empty();
+        assert_eq!(MD5::hash_reader(&mut empty).unwrap(), MD5::hash(b""));
+    }
+
+    #[test]
+    fn writer() {
+        let data = (0..20000)
+            .map(|i| (i * 13 + i / 256) as u8)
+            .collect::<Vec<_>>();
+
+        let mut md5 = Md5::new();
+        let copied = io::copy(&mut io::Cursor::new(&data), &mut md5).unwrap();
+        assert_eq!(copied, 20000);
+        assert_eq!(md5.finalize(), MD5::hash(&data));
+
+        let mut md5 = Md5::new();
+        md5.write_all(b"a").unwrap();
+        md5.write_all(b"bc").unwrap();
+        md5.flush().unwrap();
+        assert_eq!(md5.finalize_hex(), "900150983cd24fb0d6963f7d28e17f72");
+    }
+
+    #[test]
+    fn crypt() {
+        // checked against `openssl passwd -1`
+        assert_eq!(
+            md5_crypt(b"password", b"saltsalt"),
+            "$1$saltsalt$qjXMvbEw8oaL.CzflDtaK/"
+        );
+        assert_eq!(md5_crypt(b"hello", b"abc"), "$1$abc$jWy0FsfCoVBK.I85XpXf30");
+
+        // a stored hash works as the salt, and long salts are cut to 8 characters
+        let stored = md5_crypt(b"password", b"saltsaltsalt");
+        assert_eq!(stored, "$1$saltsalt$qjXMvbEw8oaL.CzflDtaK/");
+        assert_eq!(md5_crypt(b"password", stored.as_bytes()), stored);
+        assert_ne!(md5_crypt(b"passw0rd", stored.as_bytes()), stored);
+    }
+
+    #[test]
+    fn compress() {
+        let message = b"The quick brown fox jumps over the lazy dog, twice over and again";
+
+        let mut padded = message.to_vec();
+        padded.push(0x80);
+        while padded.len() % 64 != 56 {
+            padded.push(0);
+        }
+        padded.extend_from_slice(&(message.len() as u64 * 8).to_le_bytes());
+        assert_eq!(padded.len(), 128);
+
+        let mut state = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+        for block in padded.chunks(64) {
+            md5_compress(&mut state, block.try_into().unwrap());
+        }
+
+        let digest = state
+            .iter()
+            .flat_map(|w| w.to_le_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(digest, MD5::hash(message));
+    }
+
+    #[test]
+    fn many() {
+        let long = vec![0x5a; 1000];
+        let inputs: [&[u8]; 4] = [b"abc", b"", &long, b"abc"];
+
+        let digests = MD5::hash_many(&inputs);
+        assert_eq!(digests.len(), 4);
+        for (digest, input) in digests.iter().zip(inputs) {
+            assert_eq!(*digest, MD5::hash(input));
+        }
+        assert!(MD5::hash_many(&[]).is_empty());
+    }
+
+    #[test]
+    fn verify() {
+        let digest = MD5::hash(b"abc");
+        assert!(MD5::verify(b"abc", &digest));
+        assert!(!MD5::verify(b"abd", &digest));
+
+        let mut wrong = digest;
+        wrong[15] ^= 1;
+        assert!(!MD5::verify(b"abc", &wrong));
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(MD5::hash_hex(b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(MD5::hash_hex(b""), "d41d8cd98f00b204e9800998ecf8427e");
+
+        let mut md5 = Md5::new();
+        md5.update(b"a");
+        md5.update(b"bc");
+        assert_eq!(md5.finalize_hex(), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(MD5::hash_u128(b"abc"), 0x900150983cd24fb0d6963f7d28e17f72);
+        assert_eq!(
+            format!("{:032x}", MD5::hash_u128(b"abc")),
+            MD5::hash_hex(b"abc")
+        );
+    }
 }
@@ -37,7 +37,9 @@
 //
 
 use gf256::gf256;
+use std::borrow::Cow;
 use std::fmt;
+use std::sync::OnceLock;
 
 pub const DATA_SIZE: usize = 223;
 
@@ -104,394 +106,644 @@ pub const GENERATOR_POLY: [gf256; ECC_SIZE + 1] = {
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum Error {
     TooManyErrors,
+    /// The codeword was shorter than `ECC_SIZE` or longer than `BLOCK_SIZE`.
+    InvalidLength(usize),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Error::TooManyErrors => write!(f, "Too many errors to correct"),
+            Error::InvalidLength(len) => write!(f, "Invalid codeword length {}", len),
         }
     }
 }
 
-fn poly_eval(f: &[gf256], x: gf256) -> gf256 {
-    let mut y = gf256::new(0);
-    for c in f {
-        y = y * x + c;
-    }
-    y
+// Encode using Reed-Solomon error correction
+//
+// Much like in CRC, we want to make the message a multiple of G(x),
+// our generator polynomial. We can do this by appending the remainder
+// of our message after division by G(x).
+//
+// ``` text
+// c(x) = m(x) - (m(x) % G(x))
+// ```
+//
+// Note we expect the message to only take up the first message.len()-ECC_SIZE
+// bytes, but this can be smaller than BLOCK_SIZE
+//
+// The functions below all run on one RsCodec over the gf256 crate's field,
+// built from GENERATOR_POLY so the generator isn't computed again at
+// runtime, see RsCodec for the decoder itself.
+//
+
+fn codec() -> &'static RsCodec {
+    static CODEC: OnceLock<RsCodec> = OnceLock::new();
+    CODEC.get_or_init(|| {
+        RsCodec::from_generator(GaloisField::qr(), ECC_SIZE, &GENERATOR_POLY.map(|g| g.0))
+            .expect("GENERATOR_POLY is a valid generator")
+    })
 }
 
-fn poly_scale(f: &mut [gf256], c: gf256) {
-    for i in 0..f.len() {
-        f[i] *= c;
-    }
+pub fn encode(message: &mut [u8]) {
+    codec().encode(message)
 }
 
-fn poly_add(f: &mut [gf256], g: &[gf256]) {
-    debug_assert!(f.len() >= g.len());
+pub fn is_correct(codeword: &[u8]) -> bool {
+    codec().is_correct(codeword)
+}
 
-    // note g.len() may be <= f.len()!
-    for i in 0..f.len() {
-        f[f.len() - 1 - i] += g[g.len() - 1 - i];
-    }
+pub fn correct_erasures(codeword: &mut [u8], erasures: &[usize]) -> Result<usize, Error> {
+    codec().correct_erasures(codeword, erasures)
 }
 
-fn poly_mul(f: &mut [gf256], g: &[gf256]) {
-    debug_assert!(f[..g.len() - 1].iter().all(|x| *x == gf256::new(0)));
+pub fn correct_errors(codeword: &mut [u8]) -> Result<usize, Error> {
+    codec().correct_errors(codeword)
+}
 
-    // This is in-place, at the cost of being a bit confusing,
-    // note that we only write to i+j, and i+j is always >= i
-    //
-    // What makes this confusing is that f and g are both big-endian
-    // polynomials, reverse order from what you would expect. And in
-    // order to leverage the i+j non-overlap, we need to write to
-    // f in reverse-reverse order.
-    //
-    for i in (0..f.len() - g.len() + 1).rev() {
-        let fi = f[f.len() - 1 - i];
-        f[f.len() - 1 - i] = gf256::new(0);
+pub fn correct(codeword: &mut [u8], erasures: &[usize]) -> Result<usize, Error> {
+    codec().correct(codeword, erasures)
+}
 
-        for j in 0..g.len() {
-            f[f.len() - 1 - (i + j)] += fi * g[g.len() - 1 - j];
-        }
-    }
+/// Diagnostics from decoding a single codeword, for monitoring error rates.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct DecodeStats {
+    /// The number of non-zero syndromes before correction, zero for a clean codeword.
+    pub syndrome_weight: usize,
+    /// The number of errors at unknown locations that were found.
+    pub errors_found: usize,
+    /// The number of erasures passed in.
+    pub erasures_given: usize,
+    /// The positions that were corrected, both errors and erasures, in ascending order.
+    pub corrected_positions: Vec<usize>,
+    /// The number of non-zero syndromes after correction, non-zero when decoding failed.
+    pub residual_weight: usize,
 }
 
-fn poly_divrem(f: &mut [gf256], g: &[gf256]) {
-    debug_assert!(f.len() >= g.len());
+/// Like `correct_errors`, but also returns `DecodeStats` describing what the decoder found.
+pub fn correct_errors_verbose(codeword: &mut [u8]) -> (Result<usize, Error>, DecodeStats) {
+    codec().correct_verbose(codeword, &[])
+}
 
-    // find leading coeff to normalize g, note you could avoid
-    // this if g is already normalized
-    let leading_coeff = g[0];
+/// Like `correct`, but also returns `DecodeStats` describing what the decoder found.
+pub fn correct_verbose(
+    codeword: &mut [u8],
+    erasures: &[usize],
+) -> (Result<usize, Error>, DecodeStats) {
+    codec().correct_verbose(codeword, erasures)
+}
+
+// Verify a codeword, correcting it only if needed
+//
+// Most blocks read back from storage are clean, so this checks the
+// syndromes first and returns the data portion of the codeword without
+// copying it. Only when errors are found is a copy made and corrected.
+// Codewords that can't have come from encode are an error.
+//
 
-    for i in 0..(f.len() - g.len() + 1) {
-        if f[i] != gf256::new(0) {
-            f[i] /= leading_coeff;
+pub fn verify_or_correct(codeword: &[u8]) -> Result<Cow<'_, [u8]>, Error> {
+    if !(ECC_SIZE..=BLOCK_SIZE).contains(&codeword.len()) {
+        return Err(Error::InvalidLength(codeword.len()));
+    }
+    let data_len = codeword.len() - ECC_SIZE;
 
-            for j in 1..g.len() {
-                f[i + j] -= f[i] * g[j];
-            }
-        }
+    if is_correct(codeword) {
+        return Ok(Cow::Borrowed(&codeword[..data_len]));
     }
+
+    let mut corrected = codeword.to_vec();
+    correct_errors(&mut corrected)?;
+    corrected.truncate(data_len);
+    Ok(Cow::Owned(corrected))
 }
 
-// Encode using Reed-Solomon error correction
+// Configurable fields
 //
-// Much like in CRC, we want to make the message a multiple of G(x),
-// our generator polynomial. We can do this by appending the remainder
-// of our message after division by G(x).
-//
-// ``` text
-// c(x) = m(x) - (m(x) % G(x))
-// ```
+// The functions above use the gf256 crate's field, GF(2^8) with the
+// primitive polynomial 0x11d and generator 0x02. This happens to be what
+// QR codes and DVB use, but other standards pick a different primitive
+// polynomial, 0x187 for example, which gives different parity bytes for
+// the same message.
 //
-// Note we expect the message to only take up the first message.len()-ECC_SIZE
-// bytes, but this can be smaller than BLOCK_SIZE
+// GaloisField builds log/antilog tables for any primitive polynomial at
+// runtime, and RsCodec runs the encoder and decoder over it, with a
+// configurable number of ECC bytes. The roots of the generator polynomial
+// always start at g^0, so codes that start at another power of the
+// generator can't be expressed.
 //
 
-pub fn encode(message: &mut [u8]) {
-    assert!(message.len() <= BLOCK_SIZE);
-    assert!(message.len() >= ECC_SIZE);
-    let data_len = message.len() - ECC_SIZE;
-
-    // create copy for polynomial division
-    //
-    // note if message is < DATA_SIZE we just treat it as a smaller polynomial,
-    // this is equivalent to prepending zeros
-    //
-    let mut divrem = message.to_vec();
-    divrem[data_len..].fill(0);
-
-    // divide by our generator polynomial
-    poly_divrem(
-        unsafe { gf256::slice_from_slice_mut_unchecked(&mut divrem) },
-        &GENERATOR_POLY,
-    );
-
-    // return message + remainder, this new message is a polynomial
-    // perfectly divisable by our generator polynomial
-    message[data_len..].copy_from_slice(&divrem[data_len..]);
+/// GF(2^8) defined by a primitive polynomial and a generator of its
+/// multiplicative group.
+#[derive(Debug, Clone)]
+pub struct GaloisField {
+    polynomial: u16,
+    generator: u8,
+    // exp is repeated twice so sums of two logs never need reducing
+    exp: [u8; 510],
+    log: [u8; 256],
 }
 
-fn find_syndromes(f: &[gf256]) -> Vec<gf256> {
-    let mut S = vec![];
-    for i in 0..ECC_SIZE {
-        S.push(poly_eval(f, gf256::GENERATOR.pow(u8::try_from(i).unwrap())));
-    }
-    S
-}
+impl GaloisField {
+    /// Creates the field GF(2^8) modulo `polynomial`, which must have
+    /// degree 8. Returns `None` unless `generator` generates all 255
+    /// non-zero elements, which also rejects polynomials that aren't
+    /// irreducible.
+    pub fn new(polynomial: u16, generator: u8) -> Option<Self> {
+        if polynomial >> 8 != 1 {
+            return None;
+        }
 
-fn find_forney_syndromes(codeword: &[gf256], S: &[gf256], erasures: &[usize]) -> Vec<gf256> {
-    let mut S = S.to_vec();
-    for j in erasures {
-        let Xj = gf256::GENERATOR.pow(u8::try_from(codeword.len() - 1 - j).unwrap());
-        for i in 0..S.len() - 1 {
-            S[i] = S[i + 1] - S[i] * Xj;
+        let mut exp = [0u8; 510];
+        let mut log = [0u8; 256];
+        let mut x = 1u8;
+        for i in 0..255 {
+            // the generator's order must be exactly 255
+            if i > 0 && x == 1 {
+                return None;
+            }
+            exp[i] = x;
+            exp[i + 255] = x;
+            log[usize::from(x)] = i as u8;
+            x = Self::naive_mul(x, generator, polynomial);
+        }
+        if x != 1 {
+            return None;
         }
-    }
 
-    // trim unnecessary syndromes
-    S.drain(S.len() - erasures.len()..);
-    S
-}
+        Some(Self {
+            polynomial,
+            generator,
+            exp,
+            log,
+        })
+    }
 
-fn find_erasure_locator(codeword: &[gf256], erasures: &[usize]) -> Vec<gf256> {
-    let mut Λ = vec![gf256::new(0); erasures.len() + 1];
-    let Λ_len = Λ.len();
-    Λ[Λ_len - 1] = gf256::new(1);
-
-    for j in erasures {
-        poly_mul(
-            &mut Λ,
-            &[
-                -gf256::GENERATOR.pow(u8::try_from(codeword.len() - 1 - j).unwrap()),
-                gf256::new(1),
-            ],
-        );
+    /// The field used by QR codes and DVB, and by the gf256 crate, with
+    /// polynomial 0x11d and generator 0x02.
+    pub fn qr() -> Self {
+        Self::new(0x11d, 0x02).unwrap()
     }
 
-    Λ
-}
+    fn naive_mul(a: u8, b: u8, polynomial: u16) -> u8 {
+        let mut p = 0u16;
+        for i in 0..8 {
+            if (b >> i) & 1 == 1 {
+                p ^= u16::from(a) << i;
+            }
+        }
+        for i in (8..15).rev() {
+            if (p >> i) & 1 == 1 {
+                p ^= polynomial << (i - 8);
+            }
+        }
+        p as u8
+    }
 
-fn find_error_locator(S: &[gf256]) -> Vec<gf256> {
-    // the current estimate for the error locator polynomial
-    let mut Λ = vec![gf256::new(0); S.len() + 1];
-    let Λ_len = Λ.len();
-    Λ[Λ_len - 1] = gf256::new(1);
+    pub fn polynomial(&self) -> u16 {
+        self.polynomial
+    }
 
-    let mut prev_Λ = Λ.clone();
-    let mut delta_Λ = Λ.clone();
+    pub fn generator(&self) -> u8 {
+        self.generator
+    }
 
-    // the current estimate for the number of errors
-    let mut v = 0;
+    pub fn mul(&self, a: u8, b: u8) -> u8 {
+        if a == 0 || b == 0 {
+            return 0;
+        }
+        self.exp[usize::from(self.log[usize::from(a)]) + usize::from(self.log[usize::from(b)])]
+    }
 
-    for i in 0..S.len() {
-        let mut delta = S[i];
-        for j in 1..v + 1 {
-            delta += Λ[Λ.len() - 1 - j] * S[i - j];
+    pub fn div(&self, a: u8, b: u8) -> u8 {
+        assert!(b != 0, "division by zero");
+        if a == 0 {
+            return 0;
         }
+        self.exp
+            [usize::from(self.log[usize::from(a)]) + 255 - usize::from(self.log[usize::from(b)])]
+    }
 
-        prev_Λ.rotate_left(1);
+    pub fn recip(&self, a: u8) -> u8 {
+        self.div(1, a)
+    }
 
-        if delta != gf256::new(0) {
-            if 2 * v <= i {
-                core::mem::swap(&mut Λ, &mut prev_Λ);
-                poly_scale(&mut Λ, delta);
-                poly_scale(&mut prev_Λ, delta.recip());
-                v = i + 1 - v;
-            }
+    /// Returns the generator raised to the power `i`.
+    pub fn generator_pow(&self, i: usize) -> u8 {
+        self.exp[i % 255]
+    }
 
-            delta_Λ.copy_from_slice(&prev_Λ);
-            poly_scale(&mut delta_Λ, delta);
-            poly_add(&mut Λ, &delta_Λ);
+    // Polynomials are big-endian, highest degree coefficient first, and
+    // addition and subtraction are both XOR.
+
+    fn poly_eval(&self, f: &[u8], x: u8) -> u8 {
+        let mut y = 0;
+        for c in f {
+            y = self.mul(y, x) ^ c;
         }
+        y
     }
 
-    // trim leading zeros
-    let zeros = Λ.iter().take_while(|x| **x == gf256::new(0)).count();
-    Λ.drain(0..zeros);
+    fn poly_scale(&self, f: &mut [u8], c: u8) {
+        for x in f.iter_mut() {
+            *x = self.mul(*x, c);
+        }
+    }
 
-    Λ
-}
+    fn poly_add(f: &mut [u8], g: &[u8]) {
+        debug_assert!(f.len() >= g.len());
 
-fn find_error_locations(codeword: &[gf256], Λ: &[gf256]) -> Vec<usize> {
-    let mut error_locations = vec![];
-    for j in 0..codeword.len() {
-        let Xj = gf256::GENERATOR.pow(u8::try_from(codeword.len() - 1 - j).unwrap());
-        let zero = poly_eval(&Λ, Xj.recip());
-        if zero == gf256::new(0) {
-            // found an error location!
-            error_locations.push(j);
+        // note g.len() may be <= f.len()!
+        let (f_len, g_len) = (f.len(), g.len());
+        for i in 0..g_len {
+            f[f_len - 1 - i] ^= g[g_len - 1 - i];
         }
     }
 
-    error_locations
-}
-
-fn find_error_magnitudes(
-    codeword: &[gf256],
-    S: &[gf256],
-    Λ: &[gf256],
-    error_locations: &[usize],
-) -> Vec<gf256> {
-    // find the erasure evaluator polynomial
-    //
-    // Ω(x) = S(x)*Λ(x) mod x^2v
-    //
-    let mut Ω = vec![gf256::new(0); S.len() + Λ.len() - 1];
-    let Ω_len = Ω.len();
-    Ω[Ω_len - S.len()..].copy_from_slice(&S);
-    Ω[Ω_len - S.len()..].reverse();
-    poly_mul(&mut Ω, &Λ);
-    Ω.drain(..Ω.len() - S.len());
-
-    // find the formal derivative of Λ
-    //
-    // Λ'(x) = Σ i*Λi*x^(i-1)
-    //        i=1
-    //
-    let mut Λ_prime = vec![gf256::new(0); Λ.len() - 1];
-    for i in 1..Λ.len() {
-        let mut sum = gf256::new(0);
-        for _ in 0..i {
-            sum += Λ[Λ.len() - 1 - i];
+    fn poly_mul(&self, f: &mut [u8], g: &[u8]) {
+        debug_assert!(f[..g.len() - 1].iter().all(|x| *x == 0));
+
+        // This is in-place, at the cost of being a bit confusing,
+        // note that we only write to i+j, and i+j is always >= i
+        //
+        // What makes this confusing is that f and g are both big-endian
+        // polynomials, reverse order from what you would expect. And in
+        // order to leverage the i+j non-overlap, we need to write to
+        // f in reverse-reverse order.
+        //
+        let f_len = f.len();
+        for i in (0..f_len - g.len() + 1).rev() {
+            let fi = f[f_len - 1 - i];
+            f[f_len - 1 - i] = 0;
+
+            for j in 0..g.len() {
+                f[f_len - 1 - (i + j)] ^= self.mul(fi, g[g.len() - 1 - j]);
+            }
         }
-        let Λ_prime_len = Λ_prime.len();
-        Λ_prime[Λ_prime_len - 1 - (i - 1)] = sum;
     }
 
-    // find the error magnitudes
-    //
-    //        Xj*Ω(Xj^-1)
-    // Yj = - -----------
-    //         Λ'(Xj^-1)
-    //
-    // we need to be careful to avoid a divide-by-zero here, this can happen
-    // in some cases (provided with incorrect erasures?)
-    //
-    let mut error_magnitudes = vec![];
-    for j in error_locations {
-        let Xj = gf256::GENERATOR.pow(u8::try_from(codeword.len() - 1 - j).unwrap());
-        let Yj = (-Xj * poly_eval(&Ω, Xj.recip()))
-            .checked_div(poly_eval(&Λ_prime, Xj.recip()))
-            .unwrap_or(gf256::new(0));
-        error_magnitudes.push(Yj);
+    fn poly_divrem(&self, f: &mut [u8], g: &[u8]) {
+        debug_assert!(f.len() >= g.len());
+
+        // find leading coeff to normalize g, note you could avoid
+        // this if g is already normalized
+        let leading_coeff = g[0];
+
+        for i in 0..(f.len() - g.len() + 1) {
+            if f[i] != 0 {
+                f[i] = self.div(f[i], leading_coeff);
+
+                for j in 1..g.len() {
+                    f[i + j] ^= self.mul(f[i], g[j]);
+                }
+            }
+        }
     }
+}
 
-    error_magnitudes
+/// A Reed-Solomon codec over a configurable field with `ecc_size` ECC
+/// bytes per codeword. Codewords are at most 255 bytes, with the ECC bytes
+/// at the end.
+#[derive(Debug, Clone)]
+pub struct RsCodec {
+    field: GaloisField,
+    ecc_size: usize,
+    generator_poly: Vec<u8>,
 }
 
-pub fn is_correct(codeword: &[u8]) -> bool {
-    let codeword = unsafe { gf256::slice_from_slice_unchecked(codeword) };
+// the decoder uses the usual names from the literature, S for the syndromes,
+// Λ for the error locator, Ω for the error evaluator and Xj for error locations
+#[allow(non_snake_case)]
+impl RsCodec {
+    pub fn new(field: GaloisField, ecc_size: usize) -> Self {
+        assert!(ecc_size > 0 && ecc_size < 255);
+
+        // find G(x) = ∏ (x - g^i), see GENERATOR_POLY
+        let mut generator_poly = vec![0u8; ecc_size + 1];
+        generator_poly[ecc_size] = 1;
+        for i in 0..ecc_size {
+            field.poly_mul(&mut generator_poly, &[1, field.generator_pow(i)]);
+        }
 
-    // find syndromes, syndromes of all zero means there are no errors
-    let syndromes = find_syndromes(codeword);
-    syndromes.iter().all(|s| *s == gf256::new(0))
-}
+        Self {
+            field,
+            ecc_size,
+            generator_poly,
+        }
+    }
 
-pub fn correct_erasures(codeword: &mut [u8], erasures: &[usize]) -> Result<usize, Error> {
-    let codeword = unsafe { gf256::slice_from_slice_mut_unchecked(codeword) };
+    /// Creates a codec from a precomputed generator polynomial, highest degree coefficient first,
+    /// for example one loaded from a file, skipping its computation.
+    ///
+    /// Returns `None` unless the generator is monic, has degree `ecc_size`, and has the
+    /// first `ecc_size` powers of the field's generator as roots, since the decoder relies on
+    /// them.
+    pub fn from_generator(field: GaloisField, ecc_size: usize, generator: &[u8]) -> Option<Self> {
+        if ecc_size == 0 || ecc_size >= 255 || generator.len() != ecc_size + 1 {
+            return None;
+        }
+        if generator[0] != 1 {
+            return None;
+        }
+        if (0..ecc_size).any(|i| field.poly_eval(generator, field.generator_pow(i)) != 0) {
+            return None;
+        }
 
-    // too many erasures?
-    if erasures.len() > ECC_SIZE {
-        return Err(Error::TooManyErrors);
+        Some(Self {
+            field,
+            ecc_size,
+            generator_poly: generator.to_vec(),
+        })
     }
 
-    // find syndromes, syndromes of all zero means there are no errors
-    let S = find_syndromes(codeword);
-    if S.iter().all(|s| *s == gf256::new(0)) {
-        return Ok(0);
+    pub fn field(&self) -> &GaloisField {
+        &self.field
     }
 
-    // find erasure locator polynomial
-    let Λ = find_erasure_locator(codeword, &erasures);
+    pub fn ecc_size(&self) -> usize {
+        self.ecc_size
+    }
 
-    // find erasure magnitudes using Forney's algorithm
-    let erasure_magnitudes = find_error_magnitudes(codeword, &S, &Λ, &erasures);
+    /// The generator polynomial, highest degree coefficient first.
+    pub fn generator_poly(&self) -> &[u8] {
+        &self.generator_poly
+    }
 
-    // correct the errors
-    for (&Xj, Yj) in erasures.iter().zip(erasure_magnitudes) {
-        codeword[Xj] += Yj;
+    /// Fills in the last `ecc_size` bytes of `message` with ECC, see `encode`.
+    pub fn encode(&self, message: &mut [u8]) {
+        assert!(message.len() <= 255);
+        assert!(message.len() >= self.ecc_size);
+        let data_len = message.len() - self.ecc_size;
+
+        // create copy for polynomial division
+        //
+        // note if message is shorter than 255 bytes we just treat it as a
+        // smaller polynomial, this is equivalent to prepending zeros
+        //
+        let mut divrem = message.to_vec();
+        divrem[data_len..].fill(0);
+
+        // divide by our generator polynomial
+        self.field.poly_divrem(&mut divrem, &self.generator_poly);
+
+        // return message + remainder, this new message is a polynomial
+        // perfectly divisable by our generator polynomial
+        message[data_len..].copy_from_slice(&divrem[data_len..]);
     }
 
-    // re-find the syndromes to check if we were able to find all errors
-    let S = find_syndromes(codeword);
-    if !S.iter().all(|s| *s == gf256::new(0)) {
-        return Err(Error::TooManyErrors);
+    pub fn is_correct(&self, codeword: &[u8]) -> bool {
+        // find syndromes, syndromes of all zero means there are no errors
+        self.find_syndromes(codeword).iter().all(|s| *s == 0)
     }
 
-    Ok(erasures.len())
-}
+    fn find_syndromes(&self, f: &[u8]) -> Vec<u8> {
+        (0..self.ecc_size)
+            .map(|i| self.field.poly_eval(f, self.field.generator_pow(i)))
+            .collect()
+    }
 
-pub fn correct_errors(codeword: &mut [u8]) -> Result<usize, Error> {
-    let codeword = unsafe { gf256::slice_from_slice_mut_unchecked(codeword) };
+    fn find_forney_syndromes(&self, codeword: &[u8], S: &[u8], erasures: &[usize]) -> Vec<u8> {
+        let mut S = S.to_vec();
+        for j in erasures {
+            let Xj = self.field.generator_pow(codeword.len() - 1 - j);
+            for i in 0..S.len() - 1 {
+                S[i] = S[i + 1] ^ self.field.mul(S[i], Xj);
+            }
+        }
 
-    // find syndromes, syndromes of all zero means there are no errors
-    let S = find_syndromes(codeword);
-    if S.iter().all(|s| *s == gf256::new(0)) {
-        return Ok(0);
+        // trim unnecessary syndromes
+        S.drain(S.len() - erasures.len()..);
+        S
     }
 
-    // find error locator polynomial
-    let Λ = find_error_locator(&S);
+    fn find_erasure_locator(&self, codeword: &[u8], erasures: &[usize]) -> Vec<u8> {
+        let mut Λ = vec![0u8; erasures.len() + 1];
+        let Λ_len = Λ.len();
+        Λ[Λ_len - 1] = 1;
+
+        for j in erasures {
+            let Xj = self.field.generator_pow(codeword.len() - 1 - j);
+            self.field.poly_mul(&mut Λ, &[Xj, 1]);
+        }
 
-    // too many errors?
-    let error_count = Λ.len() - 1;
-    if error_count * 2 > ECC_SIZE {
-        return Err(Error::TooManyErrors);
+        Λ
     }
 
-    // find error locations
-    let error_locations = find_error_locations(codeword, &Λ);
+    // Berlekamp-Massey
+    fn find_error_locator(&self, S: &[u8]) -> Vec<u8> {
+        // the current estimate for the error locator polynomial
+        let mut Λ = vec![0u8; S.len() + 1];
+        let Λ_len = Λ.len();
+        Λ[Λ_len - 1] = 1;
 
-    // find erasure magnitude using Forney's algorithm
-    let error_magnitudes = find_error_magnitudes(codeword, &S, &Λ, &error_locations);
+        let mut prev_Λ = Λ.clone();
+        let mut delta_Λ = Λ.clone();
 
-    // correct the errors
-    for (&Xj, Yj) in error_locations.iter().zip(error_magnitudes) {
-        codeword[Xj] += Yj;
-    }
+        // the current estimate for the number of errors
+        let mut v = 0;
 
-    // re-find the syndromes to check if we were able to find all errors
-    let S = find_syndromes(codeword);
-    if !S.iter().all(|s| *s == gf256::new(0)) {
-        return Err(Error::TooManyErrors);
-    }
+        for i in 0..S.len() {
+            let mut delta = S[i];
+            for j in 1..v + 1 {
+                delta ^= self.field.mul(Λ[Λ.len() - 1 - j], S[i - j]);
+            }
 
-    Ok(error_locations.len())
-}
+            prev_Λ.rotate_left(1);
 
-pub fn correct(codeword: &mut [u8], erasures: &[usize]) -> Result<usize, Error> {
-    let codeword = unsafe { gf256::slice_from_slice_mut_unchecked(codeword) };
+            if delta != 0 {
+                if 2 * v <= i {
+                    core::mem::swap(&mut Λ, &mut prev_Λ);
+                    self.field.poly_scale(&mut Λ, delta);
+                    self.field.poly_scale(&mut prev_Λ, self.field.recip(delta));
+                    v = i + 1 - v;
+                }
 
-    // too many erasures?
-    if erasures.len() > ECC_SIZE {
-        return Err(Error::TooManyErrors);
-    }
+                delta_Λ.copy_from_slice(&prev_Λ);
+                self.field.poly_scale(&mut delta_Λ, delta);
+                GaloisField::poly_add(&mut Λ, &delta_Λ);
+            }
+        }
 
-    // find syndromes, syndromes of all zero means there are no errors
-    let S = find_syndromes(codeword);
-    if S.iter().all(|s| *s == gf256::new(0)) {
-        return Ok(0);
+        // trim leading zeros
+        let zeros = Λ.iter().take_while(|x| **x == 0).count();
+        Λ.drain(0..zeros);
+
+        Λ
     }
 
-    // find Forney syndromes, hiding known erasures from the syndromes
-    let forney_S = find_forney_syndromes(codeword, &S, &erasures);
+    fn find_error_locations(&self, codeword: &[u8], Λ: &[u8]) -> Vec<usize> {
+        // an error at j is a root of Λ at Xj^-1
+        (0..codeword.len())
+            .filter(|j| {
+                let Xj = self.field.generator_pow(codeword.len() - 1 - j);
+                self.field.poly_eval(Λ, self.field.recip(Xj)) == 0
+            })
+            .collect()
+    }
 
-    // find error locator polynomial
-    let Λ = find_error_locator(&forney_S);
+    // Forney's algorithm
+    fn find_error_magnitudes(
+        &self,
+        codeword: &[u8],
+        S: &[u8],
+        Λ: &[u8],
+        error_locations: &[usize],
+    ) -> Vec<u8> {
+        // find the erasure evaluator polynomial
+        //
+        // Ω(x) = S(x)*Λ(x) mod x^2v
+        //
+        let mut Ω = vec![0u8; S.len() + Λ.len() - 1];
+        let Ω_len = Ω.len();
+        Ω[Ω_len - S.len()..].copy_from_slice(S);
+        Ω[Ω_len - S.len()..].reverse();
+        self.field.poly_mul(&mut Ω, Λ);
+        Ω.drain(..Ω.len() - S.len());
+
+        // find the formal derivative of Λ
+        //
+        // Λ'(x) = Σ i*Λi*x^(i-1)
+        //        i=1
+        //
+        // in GF(2^8) i*Λi is Λi for odd i and 0 otherwise
+        //
+        let mut Λ_prime = vec![0u8; Λ.len() - 1];
+        let Λ_prime_len = Λ_prime.len();
+        for i in (1..Λ.len()).step_by(2) {
+            Λ_prime[Λ_prime_len - 1 - (i - 1)] = Λ[Λ.len() - 1 - i];
+        }
 
-    // too many errors/erasures?
-    let error_count = Λ.len() - 1;
-    let erasure_count = erasures.len();
-    if error_count * 2 + erasure_count > ECC_SIZE {
-        return Err(Error::TooManyErrors);
+        // find the error magnitudes
+        //
+        //        Xj*Ω(Xj^-1)
+        // Yj = - -----------
+        //         Λ'(Xj^-1)
+        //
+        // we need to be careful to avoid a divide-by-zero here, this can happen
+        // in some cases (provided with incorrect erasures?)
+        //
+        error_locations
+            .iter()
+            .map(|j| {
+                let Xj = self.field.generator_pow(codeword.len() - 1 - j);
+                let Xj_inv = self.field.recip(Xj);
+                let numerator = self.field.mul(Xj, self.field.poly_eval(&Ω, Xj_inv));
+                match self.field.poly_eval(&Λ_prime, Xj_inv) {
+                    0 => 0,
+                    denominator => self.field.div(numerator, denominator),
+                }
+            })
+            .collect()
     }
 
-    // find all error locations
-    let mut error_locations = find_error_locations(codeword, &Λ);
-    error_locations.extend_from_slice(&erasures);
+    pub fn correct_erasures(
+        &self,
+        codeword: &mut [u8],
+        erasures: &[usize],
+    ) -> Result<usize, Error> {
+        // too many erasures?
+        if erasures.len() > self.ecc_size {
+            return Err(Error::TooManyErrors);
+        }
+
+        // find syndromes, syndromes of all zero means there are no errors
+        let S = self.find_syndromes(codeword);
+        if S.iter().all(|s| *s == 0) {
+            return Ok(0);
+        }
+
+        // find erasure locator polynomial
+        let Λ = self.find_erasure_locator(codeword, erasures);
+
+        // find erasure magnitudes using Forney's algorithm
+        let erasure_magnitudes = self.find_error_magnitudes(codeword, &S, &Λ, erasures);
+
+        // correct the errors
+        for (&Xj, Yj) in erasures.iter().zip(erasure_magnitudes) {
+            codeword[Xj] ^= Yj;
+        }
 
-    // re-find error locator polynomial, this time including both
-    // errors and erasures
-    let Λ = find_erasure_locator(codeword, &error_locations);
+        // re-find the syndromes to check if we were able to find all errors
+        if !self.is_correct(codeword) {
+            return Err(Error::TooManyErrors);
+        }
 
-    // find erasure magnitude using Forney's algorithm
-    let error_magnitudes = find_error_magnitudes(codeword, &S, &Λ, &error_locations);
+        Ok(erasures.len())
+    }
 
-    // correct the errors
-    for (&Xj, Yj) in error_locations.iter().zip(error_magnitudes) {
-        codeword[Xj] += Yj;
+    pub fn correct_errors(&self, codeword: &mut [u8]) -> Result<usize, Error> {
+        self.correct(codeword, &[])
     }
 
-    // re-find the syndromes to check if we were able to find all errors
-    let S = find_syndromes(codeword);
-    if !S.iter().all(|s| *s == gf256::new(0)) {
-        return Err(Error::TooManyErrors);
+    pub fn correct(&self, codeword: &mut [u8], erasures: &[usize]) -> Result<usize, Error> {
+        self.correct_verbose(codeword, erasures).0
     }
 
-    Ok(error_locations.len())
+    /// Like `correct`, but also returns `DecodeStats` describing what the decoder found.
+    pub fn correct_verbose(
+        &self,
+        codeword: &mut [u8],
+        erasures: &[usize],
+    ) -> (Result<usize, Error>, DecodeStats) {
+        let weight = |S: &[u8]| S.iter().filter(|s| **s != 0).count();
+
+        let mut stats = DecodeStats {
+            erasures_given: erasures.len(),
+            ..DecodeStats::default()
+        };
+
+        // find syndromes, syndromes of all zero means there are no errors
+        let S = self.find_syndromes(codeword);
+        stats.syndrome_weight = weight(&S);
+        stats.residual_weight = stats.syndrome_weight;
+
+        // too many erasures?
+        if erasures.len() > self.ecc_size {
+            return (Err(Error::TooManyErrors), stats);
+        }
+
+        if stats.syndrome_weight == 0 {
+            return (Ok(0), stats);
+        }
+
+        // find Forney syndromes, hiding known erasures from the syndromes
+        let forney_S = self.find_forney_syndromes(codeword, &S, erasures);
+
+        // find error locator polynomial
+        let Λ = self.find_error_locator(&forney_S);
+
+        // too many errors/erasures?
+        let error_count = Λ.len() - 1;
+        if error_count * 2 + erasures.len() > self.ecc_size {
+            return (Err(Error::TooManyErrors), stats);
+        }
+
+        // find all error locations
+        let mut error_locations = self.find_error_locations(codeword, &Λ);
+        stats.errors_found = error_locations.len();
+        error_locations.extend_from_slice(erasures);
+
+        // re-find error locator polynomial, this time including both
+        // errors and erasures
+        let Λ = self.find_erasure_locator(codeword, &error_locations);
+
+        // find erasure magnitude using Forney's algorithm
+        let error_magnitudes = self.find_error_magnitudes(codeword, &S, &Λ, &error_locations);
+
+        // correct the errors
+        for (&Xj, Yj) in error_locations.iter().zip(error_magnitudes) {
+            codeword[Xj] ^= Yj;
+        }
+        stats.corrected_positions = error_locations.clone();
+        stats.corrected_positions.sort_unstable();
+
+        // re-find the syndromes to check if we were able to find all errors
+        stats.residual_weight = weight(&self.find_syndromes(codeword));
+        if stats.residual_weight != 0 {
+            return (Err(Error::TooManyErrors), stats);
+        }
+
+        (Ok(error_locations.len()), stats)
+    }
 }
 
 #[cfg(test)]
@@ -572,4 +824,147 @@ mod tests {
             assert_eq!(&data[0..8], &(0..8).collect::<Vec<u8>>());
         }
     }
+
+    #[test]
+    fn reed_solomon_verify_or_correct() {
+        let mut data = (0..255).collect::<Vec<u8>>();
+        encode(&mut data);
+
+        // clean codewords are borrowed
+        let res = verify_or_correct(&data);
+        assert!(matches!(res, Ok(Cow::Borrowed(_))));
+        assert_eq!(&*res.unwrap(), &(0..223).collect::<Vec<u8>>());
+
+        // corrupted codewords are corrected into a copy
+        data[10] = b'x';
+        data[100] = b'x';
+        let res = verify_or_correct(&data);
+        assert!(matches!(res, Ok(Cow::Owned(_))));
+        assert_eq!(&*res.unwrap(), &(0..223).collect::<Vec<u8>>());
+
+        // too many errors
+        data[0..ECC_SIZE].fill(b'x');
+        assert_eq!(verify_or_correct(&data), Err(Error::TooManyErrors));
+
+        // too short or too long to be a codeword
+        assert_eq!(verify_or_correct(&[1, 2, 3]), Err(Error::InvalidLength(3)));
+        assert_eq!(verify_or_correct(&[0; 256]), Err(Error::InvalidLength(256)));
+        assert!(matches!(
+            verify_or_correct(&[0; ECC_SIZE]),
+            Ok(Cow::Borrowed(&[]))
+        ));
+    }
+
+    #[test]
+    fn reed_solomon_stats() {
+        let mut data = (0..255).collect::<Vec<u8>>();
+        encode(&mut data);
+
+        let (res, stats) = correct_errors_verbose(&mut data);
+        assert_eq!(res, Ok(0));
+        assert_eq!(stats, DecodeStats::default());
+
+        // two errors and one erasure
+        data[3] = b'x';
+        data[200] = b'x';
+        data[50] = b'x';
+        let (res, stats) = correct_verbose(&mut data, &[50]);
+        assert_eq!(res, Ok(3));
+        assert!(stats.syndrome_weight > 0);
+        assert_eq!(stats.errors_found, 2);
+        assert_eq!(stats.erasures_given, 1);
+        assert_eq!(stats.corrected_positions, vec![3, 50, 200]);
+        assert_eq!(stats.residual_weight, 0);
+        assert_eq!(&data[0..223], &(0..223).collect::<Vec<u8>>());
+
+        // too many errors
+        data[0..ECC_SIZE].fill(b'x');
+        let (res, stats) = correct_errors_verbose(&mut data);
+        assert_eq!(res, Err(Error::TooManyErrors));
+        assert!(stats.residual_weight > 0);
+    }
+
+    #[test]
+    fn codec_qr() {
+        // "HELLO WORLD" as a version 1-M QR code, from https://www.thonky.com/qr-code-tutorial/
+        let codec = RsCodec::new(GaloisField::qr(), 10);
+        let mut data = vec![
+            32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236, 17, 236, 17,
+        ];
+        data.resize(data.len() + 10, 0);
+        codec.encode(&mut data);
+        assert_eq!(&data[16..], &[196, 35, 39, 119, 235, 215, 231, 226, 93, 23]);
+
+        // which is the same field as the gf256 crate, with the same generator polynomial
+        let codec = RsCodec::new(GaloisField::qr(), ECC_SIZE);
+        assert_eq!(codec.generator_poly(), &GENERATOR_POLY.map(|g| g.0)[..]);
+        let mut data = (0..255).collect::<Vec<u8>>();
+        let mut expected = data.clone();
+        codec.encode(&mut data);
+        encode(&mut expected);
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn codec_from_generator() {
+        let computed = RsCodec::new(GaloisField::qr(), 10);
+        let supplied =
+            RsCodec::from_generator(GaloisField::qr(), 10, computed.generator_poly()).unwrap();
+
+        let mut a = (0..50).collect::<Vec<u8>>();
+        let mut b = a.clone();
+        computed.encode(&mut a);
+        supplied.encode(&mut b);
+        assert_eq!(a, b);
+
+        // the degree has to match the number of ECC bytes
+        let generator = computed.generator_poly();
+        assert!(RsCodec::from_generator(GaloisField::qr(), 9, generator).is_none());
+        assert!(RsCodec::from_generator(GaloisField::qr(), 10, &generator[1..]).is_none());
+
+        // and the generator has to be the right polynomial
+        let mut corrupted = generator.to_vec();
+        corrupted[3] ^= 1;
+        assert!(RsCodec::from_generator(GaloisField::qr(), 10, &corrupted).is_none());
+    }
+
+    #[test]
+    fn codec_fields() {
+        // 2 isn't a generator of the field AES uses, but 3 is
+        assert!(GaloisField::new(0x11b, 0x02).is_none());
+        assert!(GaloisField::new(0x11b, 0x03).is_some());
+        // x^8 + 1 isn't irreducible
+        assert!(GaloisField::new(0x101, 0x02).is_none());
+
+        // another primitive polynomial gives different parity, but corrects all the same
+        let codec = RsCodec::new(GaloisField::new(0x187, 0x02).unwrap(), 16);
+        let mut data = (0..64).collect::<Vec<u8>>();
+        codec.encode(&mut data);
+        assert!(codec.is_correct(&data));
+
+        let mut qr = (0..64).collect::<Vec<u8>>();
+        RsCodec::new(GaloisField::qr(), 16).encode(&mut qr);
+        assert_ne!(data, qr);
+
+        for i in 0..8 {
+            data[i * 7] = b'x';
+        }
+        assert_eq!(codec.correct_errors(&mut data), Ok(8));
+        assert_eq!(&data[..48], &(0..48).collect::<Vec<u8>>());
+
+        data[0..16].fill(b'x');
+        assert_eq!(
+            codec.correct_erasures(&mut data, &(0..16).collect::<Vec<_>>()),
+            Ok(16)
+        );
+        assert_eq!(&data[..48], &(0..48).collect::<Vec<u8>>());
+
+        data[0..10].fill(b'x');
+        data[20..23].fill(b'x');
+        assert_eq!(
+            codec.correct(&mut data, &(0..10).collect::<Vec<_>>()),
+            Ok(13)
+        );
+        assert_eq!(&data[..48], &(0..48).collect::<Vec<u8>>());
+    }
 }
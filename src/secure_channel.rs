@@ -0,0 +1,308 @@
+//! # Secure channel
+//!
+//! A worked example that ties the other modules together into an encrypted, authenticated
+//! channel between two parties, in the spirit of TLS or Noise:
+//!
+//! 1. The initiator picks a Diffie-Hellman private key $a$ and sends the public parameters $p$
+//!    and $g$ along with its public key $A = g^a \bmod p$.
+//! 2. The responder picks its own private key $b$ and computes the shared secret $A^b \bmod p$.
+//!    HKDF turns the secret into three keys, salted with the whole handshake so both sides must
+//!    have seen the same messages: one AES-GCM key per direction, and a key confirmation key.
+//!    The responder replies with its public key $B$ and an HMAC over the handshake, proving it
+//!    derived the same keys.
+//! 3. The initiator computes the same secret from $B$, derives the same keys, and checks the HMAC.
+//!
+//! After that, each message is sealed with AES-GCM under the sender's key. The nonce is a counter
+//! that both sides increment, so it is never reused, and messages that are dropped, replayed or
+//! reordered fail to decrypt.
+//!
+//! This is a teaching example, not a secure protocol. The Diffie-Hellman implementation uses
+//! 64-bit numbers, where discrete logarithms are easy to compute, and neither side is
+//! authenticated, so a man in the middle can run a separate handshake with each of them.
+
+use crate::aes::{aes_gcm_open, aes_gcm_seal, AES_128_KEY_SIZE};
+use crate::diffie_hellman::{
+    checked_secret, params_to_be_bytes, private_key, public_key, public_key_from_be_bytes,
+    public_key_to_be_bytes, DhParams,
+};
+use crate::hmac::HMAC;
+use std::error::Error;
+use std::fmt;
+
+/// The length of the initiator's handshake message: $p$, $g$ and $A$.
+pub const HELLO_SIZE: usize = 24;
+/// The length of the responder's handshake message: $B$ and the key confirmation tag.
+pub const REPLY_SIZE: usize = 28;
+
+const CONFIRM_KEY_SIZE: usize = 20;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ChannelError {
+    /// A handshake message had the wrong length.
+    MalformedMessage,
+    /// The peer's public key was out of range, or the key confirmation didn't match.
+    HandshakeFailed,
+    /// A message didn't decrypt, because it was tampered with, replayed or reordered.
+    AuthenticationFailed,
+}
+
+impl fmt::Display for ChannelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChannelError::MalformedMessage => write!(f, "Malformed handshake message"),
+            ChannelError::HandshakeFailed => write!(f, "Handshake failed"),
+            ChannelError::AuthenticationFailed => write!(f, "Message authentication failed"),
+        }
+    }
+}
+
+impl Error for ChannelError {}
+
+struct Keys {
+    initiator: [u8; AES_128_KEY_SIZE],
+    responder: [u8; AES_128_KEY_SIZE],
+    confirm: [u8; CONFIRM_KEY_SIZE],
+}
+
+// Derives the channel keys from the shared secret, salted with the handshake transcript.
+fn derive_keys(shared_secret: u64, transcript: &[u8]) -> Keys {
+    let okm = HMAC::hkdf(
+        transcript,
+        &shared_secret.to_be_bytes(),
+        b"secure channel keys",
+        2 * AES_128_KEY_SIZE + CONFIRM_KEY_SIZE,
+    );
+
+    let (initiator, rest) = okm.split_at(AES_128_KEY_SIZE);
+    let (responder, confirm) = rest.split_at(AES_128_KEY_SIZE);
+    Keys {
+        initiator: initiator.try_into().unwrap(),
+        responder: responder.try_into().unwrap(),
+        confirm: confirm.try_into().unwrap(),
+    }
+}
+
+/// The initiator's side of a handshake that is waiting for the responder's reply.
+pub struct Initiator {
+    p: u64,
+    private_key: u64,
+    hello: [u8; HELLO_SIZE],
+}
+
+impl Initiator {
+    /// Completes the handshake with the responder's reply, checking that it derived the same
+    /// keys.
+    pub fn finish(self, reply: &[u8]) -> Result<SecureChannel, ChannelError> {
+        let reply: &[u8; REPLY_SIZE] = reply
+            .try_into()
+            .map_err(|_| ChannelError::MalformedMessage)?;
+        let (responder_key, tag) = reply.split_at(8);
+
+        let responder_key = public_key_from_be_bytes(responder_key.try_into().unwrap());
+        let shared_secret = checked_secret(self.p, responder_key, self.private_key)
+            .map_err(|_| ChannelError::HandshakeFailed)?;
+
+        let mut transcript = self.hello.to_vec();
+        transcript.extend_from_slice(&reply[..8]);
+        let keys = derive_keys(shared_secret, &transcript);
+
+        if !HMAC::verify(&transcript, &keys.confirm, tag) {
+            return Err(ChannelError::HandshakeFailed);
+        }
+
+        Ok(SecureChannel::new(keys.initiator, keys.responder))
+    }
+}
+
+/// One endpoint of an established channel.
+pub struct SecureChannel {
+    send_key: [u8; AES_128_KEY_SIZE],
+    recv_key: [u8; AES_128_KEY_SIZE],
+    send_counter: u64,
+    recv_counter: u64,
+}
+
+impl SecureChannel {
+    fn new(send_key: [u8; AES_128_KEY_SIZE], recv_key: [u8; AES_128_KEY_SIZE]) -> Self {
+        Self {
+            send_key,
+            recv_key,
+            send_counter: 0,
+            recv_counter: 0,
+        }
+    }
+
+    /// Starts a handshake with the public parameters `p` and `g`, returning the initiator's
+    /// state and the message to send to the responder.
+    pub fn initiate(p: u64, g: u64) -> (Initiator, [u8; HELLO_SIZE]) {
+        let private_key = private_key(p);
+
+        let mut hello = [0; HELLO_SIZE];
+        hello[..16].copy_from_slice(&params_to_be_bytes(p, g));
+        hello[16..].copy_from_slice(&public_key_to_be_bytes(public_key(p, g, private_key)));
+
+        (
+            Initiator {
+                p,
+                private_key,
+                hello,
+            },
+            hello,
+        )
+    }
+
+    /// Responds to an initiator's handshake message, returning the responder's end of the
+    /// channel and the reply to send back.
+    pub fn respond(hello: &[u8]) -> Result<(Self, [u8; REPLY_SIZE]), ChannelError> {
+        let hello: &[u8; HELLO_SIZE] = hello
+            .try_into()
+            .map_err(|_| ChannelError::MalformedMessage)?;
+
+        // the parameters come from the initiator, so a composite modulus or a weak generator has
+        // to be rejected here
+        let params = DhParams::from_be_bytes(hello[..16].try_into().unwrap())
+            .map_err(|_| ChannelError::HandshakeFailed)?;
+        if params.p() < 5 {
+            return Err(ChannelError::HandshakeFailed);
+        }
+        let initiator_key = public_key_from_be_bytes(hello[16..].try_into().unwrap());
+
+        let private_key = params.private_key();
+        let shared_secret = params
+            .secret(initiator_key, private_key)
+            .map_err(|_| ChannelError::HandshakeFailed)?;
+        let mut reply = [0; REPLY_SIZE];
+        reply[..8].copy_from_slice(&public_key_to_be_bytes(params.public_key(private_key)));
+
+        let mut transcript = hello.to_vec();
+        transcript.extend_from_slice(&reply[..8]);
+        let keys = derive_keys(shared_secret, &transcript);
+        reply[8..].copy_from_slice(&HMAC::mac(&transcript, &keys.confirm));
+
+        Ok((Self::new(keys.responder, keys.initiator), reply))
+    }
+
+    /// Runs both sides of the handshake locally, returning the initiator's and the responder's
+    /// ends of the channel.
+    pub fn establish(p: u64, g: u64) -> Result<(Self, Self), ChannelError> {
+        let (initiator, hello) = Self::initiate(p, g);
+        let (responder, reply) = Self::respond(&hello)?;
+        Ok((initiator.finish(&reply)?, responder))
+    }
+
+    fn nonce(counter: u64) -> [u8; 12] {
+        let mut nonce = [0; 12];
+        nonce[4..].copy_from_slice(&counter.to_be_bytes());
+        nonce
+    }
+
+    /// Encrypts and authenticates a message for the other end of the channel.
+    pub fn send(&mut self, plaintext: &[u8]) -> Vec<u8> {
+        let nonce = Self::nonce(self.send_counter);
+        self.send_counter = self
+            .send_counter
+            .checked_add(1)
+            .expect("nonce counter exhausted");
+
+        aes_gcm_seal(&self.send_key, &nonce, &[], plaintext).unwrap()
+    }
+
+    /// Decrypts and verifies the next message from the other end of the channel. Messages must be
+    /// received in the order they were sent. A failed message doesn't advance the counter.
+    pub fn recv(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>, ChannelError> {
+        let nonce = Self::nonce(self.recv_counter);
+        let plaintext = aes_gcm_open(&self.recv_key, &nonce, &[], ciphertext)
+            .map_err(|_| ChannelError::AuthenticationFailed)?;
+
+        self.recv_counter += 1;
+        Ok(plaintext)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the largest 64-bit prime
+    const P: u64 = 0xFFFF_FFFF_FFFF_FFC5;
+    const G: u64 = 5;
+
+    #[test]
+    fn exchange() {
+        let (mut alice, mut bob) = SecureChannel::establish(P, G).unwrap();
+
+        for i in 0..5 {
+            let message = format!("message {} from alice", i);
+            let sealed = alice.send(message.as_bytes());
+            assert_ne!(&sealed[..message.len()], message.as_bytes());
+            assert_eq!(bob.recv(&sealed).unwrap(), message.as_bytes());
+
+            let reply = format!("reply {} from bob", i);
+            let sealed = bob.send(reply.as_bytes());
+            assert_eq!(alice.recv(&sealed).unwrap(), reply.as_bytes());
+        }
+
+        // the same plaintext encrypts differently every time
+        assert_ne!(alice.send(b"hello"), alice.send(b"hello"));
+    }
+
+    #[test]
+    fn tampering() {
+        let (mut alice, mut bob) = SecureChannel::establish(P, G).unwrap();
+
+        let mut sealed = alice.send(b"attack at dawn");
+        sealed[3] ^= 1;
+        assert_eq!(bob.recv(&sealed), Err(ChannelError::AuthenticationFailed));
+        sealed[3] ^= 1;
+        assert_eq!(bob.recv(&sealed).unwrap(), b"attack at dawn");
+
+        // replaying or reordering messages fails
+        assert_eq!(bob.recv(&sealed), Err(ChannelError::AuthenticationFailed));
+        let first = alice.send(b"first");
+        let second = alice.send(b"second");
+        assert_eq!(bob.recv(&second), Err(ChannelError::AuthenticationFailed));
+        assert_eq!(bob.recv(&first).unwrap(), b"first");
+        assert_eq!(bob.recv(&second).unwrap(), b"second");
+
+        // a message can't be reflected back to its sender
+        let sealed = alice.send(b"echo");
+        assert_eq!(alice.recv(&sealed), Err(ChannelError::AuthenticationFailed));
+    }
+
+    #[test]
+    fn handshake_tampering() {
+        let (initiator, hello) = SecureChannel::initiate(P, G);
+        let (_, mut reply) = SecureChannel::respond(&hello).unwrap();
+
+        // a modified reply fails key confirmation
+        reply[0] ^= 1;
+        assert!(matches!(
+            initiator.finish(&reply),
+            Err(ChannelError::HandshakeFailed)
+        ));
+
+        // degenerate public keys are rejected
+        let mut hello = hello;
+        hello[16..].copy_from_slice(&1u64.to_be_bytes());
+        assert!(matches!(
+            SecureChannel::respond(&hello),
+            Err(ChannelError::HandshakeFailed)
+        ));
+        assert!(matches!(
+            SecureChannel::respond(&hello[..10]),
+            Err(ChannelError::MalformedMessage)
+        ));
+
+        // so are a composite modulus and a generator of 1
+        let (_, hello) = SecureChannel::initiate(P - 2, G);
+        assert!(matches!(
+            SecureChannel::respond(&hello),
+            Err(ChannelError::HandshakeFailed)
+        ));
+        let (_, hello) = SecureChannel::initiate(P, 1);
+        assert!(matches!(
+            SecureChannel::respond(&hello),
+            Err(ChannelError::HandshakeFailed)
+        ));
+    }
+}
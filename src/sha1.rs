@@ -1,5 +1,15 @@
-#[derive(Default, Clone, Copy, PartialEq)]
-pub struct Sha1;
+use crate::util::{ct_eq, to_hex};
+
+/// An incremental SHA-1 hasher. Input can be passed to `update` in pieces of any size: partial
+/// blocks are buffered between calls, and the padding is only added by `finalize`, so the digest
+/// is the same as `Sha1::hash` of everything passed to `update`.
+#[derive(Clone)]
+pub struct Sha1 {
+    state: [u32; 5],
+    buffer: [u8; 64],
+    buffered: usize,
+    length: u64,
+}
 
 impl Sha1 {
     // SHA-1 hashing algorithm initial hash values.
@@ -10,61 +20,119 @@ impl Sha1 {
     const H3: u32 = 0x10325476;
     const H4: u32 = 0xC3D2E1F0;
 
+    pub fn new() -> Self {
+        Self {
+            state: [Self::H0, Self::H1, Self::H2, Self::H3, Self::H4],
+            buffer: [0; 64],
+            buffered: 0,
+            length: 0,
+        }
+    }
+
+    /// Creates a hasher that resumes from a known state, after `total_len` bytes of input. The
+    /// state only changes once per 64-byte block, so `total_len` must be a multiple of 64.
+    ///
+    /// A SHA-1 digest is the whole internal state, so the digest of a message, together with the
+    /// length of the message and its padding, is enough to keep hashing past that padding. This is
+    /// the length extension attack, and why `sha1(secret || message)` isn't a secure MAC:
+    ///
+    /// ```
+    /// use crypto::sha1::Sha1;
+    ///
+    /// let secret = b"hunter2";
+    /// let message = b"user=alice";
+    /// let mac = Sha1::hash(&[&secret[..], message].concat());
+    ///
+    /// // the attacker knows the message, the MAC and the length of the secret, but not the secret
+    /// let secret_len = 7;
+    /// let len = secret_len + message.len();
+    /// let mut glue = vec![0x80];
+    /// glue.resize((55 - len as isize).rem_euclid(64) as usize + 1, 0);
+    /// glue.extend_from_slice(&(len as u64 * 8).to_be_bytes());
+    ///
+    /// let state = [0, 4, 8, 12, 16].map(|i| u32::from_be_bytes(mac[i..i + 4].try_into().unwrap()));
+    /// let mut sha1 = Sha1::from_state(state, (len + glue.len()) as u64);
+    /// sha1.update(b"&admin=true");
+    /// let forged = sha1.finalize();
+    ///
+    /// // the forged MAC is valid for the extended message
+    /// let extended = [&secret[..], message, &glue, b"&admin=true"].concat();
+    /// assert_eq!(forged, Sha1::hash(&extended));
+    /// ```
+    pub fn from_state(state: [u32; 5], total_len: u64) -> Self {
+        assert!(
+            total_len.is_multiple_of(64),
+            "total length must be a whole number of blocks"
+        );
+
+        Self {
+            state,
+            buffer: [0; 64],
+            buffered: 0,
+            length: total_len,
+        }
+    }
+
     /// Computes the SHA-1 hash of the input string by taking in either a String of str type.
     pub fn hash(key: &[u8]) -> [u8; 20] {
-        // 1. Initialize variables to the SHA-1's initial hash values.
-        let (mut h0, mut h1, mut h2, mut h3, mut h4) =
-            (Self::H0, Self::H1, Self::H2, Self::H3, Self::H4);
-
-        // 2. Pad the key
-        let msg = Self::pad_message(key);
-
-        // 3. Process each 512-bit chunk of the padded message.
-        for chunk in msg.chunks(64) {
-            // 4. Get the message schedule and copies initial SHA-1 values.
-            let schedule = Self::build_schedule(chunk);
-
-            // 5. initialize the schedule
-            let (mut a, mut b, mut c, mut d, mut e) = (h0, h1, h2, h3, h4);
-
-            // 6. Main loop of the SHA-1 algorithm using predefind values based on primes numbers.
-            for i in 0..80 {
-                let (f, k) = match i {
-                    0..=19 => ((b & c) | ((!b) & d), 0x5A827999),
-                    20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
-                    40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
-                    _ => (b ^ c ^ d, 0xCA62C1D6),
-                };
-
-                // 7. Update the temporary variable and then update the hash values
-                // in a manner that enforces both diffusion and confusion. Note
-                // how the "scrambled" data trickles through the variables as we
-                // loop through.
-                let temp = a
-                    .rotate_left(5)
-                    .wrapping_add(f)
-                    .wrapping_add(e)
-                    .wrapping_add(k)
-                    .wrapping_add(schedule[i]);
-                e = d;
-                d = c;
-                c = b.rotate_left(30);
-                b = a;
-                a = temp;
+        let mut sha1 = Self::new();
+        sha1.update(key);
+        sha1.finalize()
+    }
+
+    /// Hashes `input` and formats the digest as lowercase hex, matching the output of `sha1sum`.
+    pub fn hash_hex(input: &[u8]) -> String {
+        to_hex(&Self::hash(input))
+    }
+
+    /// Hashes `input` and checks it against `expected` in constant time, so the time taken doesn't
+    /// reveal how much of the expected digest a guess got right.
+    pub fn verify(input: &[u8], expected: &[u8; 20]) -> bool {
+        ct_eq(&Self::hash(input), expected)
+    }
+
+    pub fn update(&mut self, mut input: &[u8]) {
+        self.length = self.length.wrapping_add(input.len() as u64);
+
+        // top up a partial block first
+        if self.buffered > 0 {
+            let n = input.len().min(64 - self.buffered);
+            self.buffer[self.buffered..self.buffered + n].copy_from_slice(&input[..n]);
+            self.buffered += n;
+            input = &input[n..];
+
+            if self.buffered < 64 {
+                return;
             }
+            Self::compress(&mut self.state, &self.buffer);
+            self.buffered = 0;
+        }
 
-            // 8. Add the compressed chunk to the current hash value.
-            h0 = h0.wrapping_add(a);
-            h1 = h1.wrapping_add(b);
-            h2 = h2.wrapping_add(c);
-            h3 = h3.wrapping_add(d);
-            h4 = h4.wrapping_add(e);
+        let mut blocks = input.chunks_exact(64);
+        for block in &mut blocks {
+            Self::compress(&mut self.state, block);
         }
 
-        // 9. Produce the final hash value as a 20-byte array.
+        let rest = blocks.remainder();
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.buffered = rest.len();
+    }
+
+    pub fn finalize(mut self) -> [u8; 20] {
+        // 1. Append the byte 0x80 (1) at the end to delineate the padding start, pad with '0'
+        // bytes until the message's length in bits % 512 is 448, and append the original message
+        // length in bits.
+        let original_bit_length = self.length.wrapping_mul(8);
+        let zeros = (55 - self.buffered as isize).rem_euclid(64) as usize;
+        self.update(&[0x80]);
+        self.update(&[0; 64][..zeros]);
+        self.update(&original_bit_length.to_be_bytes());
+        debug_assert_eq!(self.buffered, 0);
+
+        // 2. Produce the final hash value as a 20-byte array.
         let mut hash = [0u8; 20];
 
-        for (i, h) in [h0, h1, h2, h3, h4].iter().enumerate() {
+        for (i, h) in self.state.iter().enumerate() {
             let (start, end) = (i * 4, (i + 1) * 4);
             hash[start..end].copy_from_slice(&h.to_be_bytes());
         }
@@ -72,26 +140,49 @@ impl Sha1 {
         hash
     }
 
-    /// Pads the input message according to SHA-1 specifications.
-    fn pad_message(input: &[u8]) -> Vec<u8> {
-        // 1. turn the input into a vec.
-        let mut bytes = input.to_vec();
+    /// Processes one 512-bit chunk of the padded message.
+    fn compress(state: &mut [u32; 5], chunk: &[u8]) {
+        // 1. Get the message schedule and copies initial SHA-1 values.
+        let schedule = Self::build_schedule(chunk);
 
-        // 2. Save the original message length
-        let original_bit_length = bytes.len() as u64 * 8;
+        // 2. initialize the schedule
+        let [h0, h1, h2, h3, h4] = *state;
+        let (mut a, mut b, mut c, mut d, mut e) = (h0, h1, h2, h3, h4);
 
-        // 3. Append the byte 0x80 (1) at the end to delineate the padding start
-        bytes.push(0x80);
+        // 3. Main loop of the SHA-1 algorithm using predefind values based on primes numbers.
+        for i in 0..80 {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | ((!b) & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
 
-        // 4. Pad with '0' bytes until the message's length in bits % 512 is 448.
-        while (bytes.len() * 8) % 512 != 448 {
-            bytes.push(0);
+            // 4. Update the temporary variable and then update the hash values
+            // in a manner that enforces both diffusion and confusion. Note
+            // how the "scrambled" data trickles through the variables as we
+            // loop through.
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(schedule[i]);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
         }
 
-        // 5. Append the original message length.
-        bytes.extend_from_slice(&original_bit_length.to_be_bytes());
-
-        bytes
+        // 5. Add the compressed chunk to the current hash value.
+        *state = [
+            h0.wrapping_add(a),
+            h1.wrapping_add(b),
+            h2.wrapping_add(c),
+            h3.wrapping_add(d),
+            h4.wrapping_add(e),
+        ];
     }
 
     /// Builds the message schedule array from a 512-bit chunk.
@@ -114,6 +205,12 @@ impl Sha1 {
     }
 }
 
+impl Default for Sha1 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -162,4 +259,72 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn incremental() {
+        let input: Vec<u8> = (0..300).map(|i| i as u8).collect();
+        assert_eq!(
+            Sha1::hash(&input),
+            [
+                0xbf, 0x77, 0xec, 0xf1, // first
+                0x43, 0xce, 0xb2, 0x1f, // second
+                0x16, 0x76, 0xc3, 0x4b, // third
+                0x8d, 0x89, 0xc8, 0xbb, // fourth
+                0x3c, 0x43, 0xcc, 0x4e, // fifth
+            ]
+        );
+
+        for chunk_size in [1, 3, 55, 56, 63, 64, 65, 128, 300] {
+            let mut sha1 = Sha1::new();
+            for chunk in input.chunks(chunk_size) {
+                sha1.update(chunk);
+            }
+            assert_eq!(sha1.finalize(), Sha1::hash(&input));
+        }
+
+        for len in 0..=130 {
+            let (a, b) = input[..len].split_at(len / 3);
+            let mut sha1 = Sha1::default();
+            sha1.update(a);
+            sha1.update(&[]);
+            sha1.update(b);
+            assert_eq!(sha1.finalize(), Sha1::hash(&input[..len]));
+        }
+    }
+
+    #[test]
+    fn resume() {
+        let input: Vec<u8> = (0..200).map(|i| i as u8).collect();
+
+        // resuming from the state after two blocks gives the same digest
+        let mut state = [Sha1::H0, Sha1::H1, Sha1::H2, Sha1::H3, Sha1::H4];
+        Sha1::compress(&mut state, &input[..64]);
+        Sha1::compress(&mut state, &input[64..128]);
+        let mut sha1 = Sha1::from_state(state, 128);
+        sha1.update(&input[128..]);
+        assert_eq!(sha1.finalize(), Sha1::hash(&input));
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(
+            Sha1::hash_hex(b"abc"),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            Sha1::hash_hex(b""),
+            "da39a3ee5e6b4b0d3255bfef95601890afd80709"
+        );
+    }
+
+    #[test]
+    fn verify() {
+        let digest = Sha1::hash(b"abc");
+        assert!(Sha1::verify(b"abc", &digest));
+        assert!(!Sha1::verify(b"abd", &digest));
+
+        let mut wrong = digest;
+        wrong[19] ^= 1;
+        assert!(!Sha1::verify(b"abc", &wrong));
+    }
 }
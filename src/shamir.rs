@@ -25,7 +25,7 @@ doc = ::embed_doc_image::embed_image!("lagrange-polynomial", "./images/lagrange-
 //!    affecting the existing shares.
 //! 4. It is dynamic, which means that if one of the shares is compromised, all of the given
 //!    shares can be recalculated to generate a new polynomial without requiring a change of the
-//!    secret.
+//!    secret (see `refresh`).
 //! 5. It is flexible, which means more shares can be given based on some condition, e.g. if one
 //!    party should have twice as many shares as another party, that can be handled by the system.
 //!
@@ -116,13 +116,73 @@ doc = ::embed_doc_image::embed_image!("lagrange-polynomial", "./images/lagrange-
 //! The other $k-1$ members would not be able to find out who was dishonest, since the decryption
 //! algorithm gives a successful response but the wrong secret.
 //!
-use oorandom::Rand32;
+//! ## Verifiable secret sharing
+//!
+//! Feldman's verifiable secret sharing fixes this, at the cost of the information-theoretic
+//! security. The secret is shared with a polynomial $f(x) = a_0 + a_1 x + \dots + a_{k-1} x^{k-1}$
+//! over $\mathbb{Z}_q$ instead of GF(256), where $p = 2q + 1$ is a safe prime, and the dealer
+//! publishes the commitments $C_j = g^{a_j} \bmod p$ for a $g$ of order $q$. Anyone can then check
+//! a share $(x, y)$ without learning anything else about the polynomial, since
+//! $g^y = g^{f(x)} = \prod_j C_j^{x^j}$. A share that fails the check was corrupted, or the
+//! dealer was dishonest. See `generate_verifiable` and `verify_share`.
+//!
+//! The commitment $C_0 = g^S$ gives the secret away to anyone who can compute discrete logarithms
+//! mod $p$, which for the 64-bit numbers used here is everyone. Like the Diffie-Hellman module,
+//! this is for illustration only.
+//!
+use crate::diffie_hellman::{is_prime, modexp_ct};
+use crate::gf_matrix::GfMatrix;
+use getrandom::getrandom;
+use oorandom::{Rand32, Rand64};
+use std::error::Error;
+use std::fmt;
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
 
+use ::gf256::gf2p16;
 use gf256::gf256;
 
 #[cfg(feature = "doc-images")]
 use embed_doc_image::embed_doc_image;
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ShamirError {
+    /// Fewer shares than the threshold were given.
+    NotEnoughShares(usize),
+    /// Two shares were given the same x coordinate.
+    DuplicateIndex(u8),
+    /// A share was given the x coordinate 0, where the polynomial is the secret.
+    ZeroIndex,
+    /// The shares have different lengths.
+    MismatchedLength,
+    /// A serialized share was truncated or had trailing bytes.
+    MalformedShare,
+    /// A serialized share had an unknown format version.
+    UnsupportedVersion(u8),
+    /// The shares were made with different thresholds.
+    MismatchedThreshold,
+    /// The share with this x coordinate doesn't lie on the polynomial through the first $k$
+    /// shares, so either it or one of them is corrupted.
+    InconsistentShare(u8),
+}
+
+impl fmt::Display for ShamirError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShamirError::NotEnoughShares(n) => write!(f, "Not enough shares: {}", n),
+            ShamirError::DuplicateIndex(x) => write!(f, "Duplicate share index {}", x),
+            ShamirError::ZeroIndex => write!(f, "Share index 0 is the secret"),
+            ShamirError::MismatchedLength => write!(f, "Mismatched share length"),
+            ShamirError::MalformedShare => write!(f, "Malformed share"),
+            ShamirError::UnsupportedVersion(v) => write!(f, "Unsupported share version {}", v),
+            ShamirError::MismatchedThreshold => write!(f, "Shares have different thresholds"),
+            ShamirError::InconsistentShare(x) => write!(f, "Share {} is inconsistent", x),
+        }
+    }
+}
+
+impl Error for ShamirError {}
+
 /// This function generates a random polynomial for Shamir's secret sharing.
 /// It takes a secret and degree of polynomial to create (the amount of shares)
 /// It sets the y-intercept to the secret passed in and then generates as many points as there are
@@ -135,15 +195,39 @@ use embed_doc_image::embed_doc_image;
 /// Imagine our $a$ is 7, our $b$ is 5 and our secret is 8. The polynomial would look like this:
 /// $7x^2 + 5x + 8$.
 /// In code, since we populate the values in reverse, that would be: `vec![8, 5, 7]`.
+/// Every coefficient is drawn uniformly from all 256 values, including 0, otherwise fewer than
+/// $k$ shares would rule out some secrets.
 fn poly_random(rng: &mut Rand32, secret: gf256, degree: usize) -> Vec<gf256> {
-    let mut f = vec![secret];
+    let mut f = Vec::with_capacity(degree + 1);
+    f.push(secret);
     for _ in 0..degree {
-        let num = rng.rand_range(1..255) as u8;
+        let num = rng.rand_u32() as u8;
         f.push(gf256::new(num));
     }
     f
 }
 
+/// This function zeroizes a polynomial's coefficients when the `zeroize` feature is enabled. The
+/// coefficients are as secret as the secret itself: anyone who reads them can compute it, or forge
+/// shares. `inner` gives the integer inside each coefficient, since the field types don't implement
+/// `Zeroize` themselves. Polynomials are allocated with their final capacity, so they are never
+/// reallocated and leave no copies behind.
+#[cfg(feature = "zeroize")]
+fn wipe<T, U: Zeroize + Default + PartialEq>(f: &mut [T], inner: impl Fn(&mut T) -> &mut U) {
+    for c in f.iter_mut() {
+        inner(c).zeroize();
+    }
+
+    #[cfg(test)]
+    tests::WIPED.with(|w| {
+        let zeroed = f.iter_mut().all(|c| *inner(c) == U::default());
+        w.borrow_mut().push((f.len(), zeroed))
+    });
+}
+
+#[cfg(not(feature = "zeroize"))]
+fn wipe<T, U>(_f: &mut [T], _inner: impl Fn(&mut T) -> &mut U) {}
+
 /// This function takes a polynomial and evaluates it.
 /// The polynomial is passed in in reverse order:
 /// Normally, polynomials are written as $ax^2 + bx + c$, but this function takes the y-intercept
@@ -177,30 +261,147 @@ fn poly_interpolate(xs: &[gf256], ys: &[gf256]) -> gf256 {
     y
 }
 
-/// This function generates a polynomial with the given secret, passed as bytes.
+/// This function evaluates the unique polynomial passing through the given points at $x$. This
+/// is the same lagrange interpolation as `poly_interpolate`, which is the special case of $x = 0$.
+fn poly_interpolate_at(xs: &[gf256], ys: &[gf256], x: gf256) -> gf256 {
+    assert!(xs.len() == ys.len());
+
+    let mut y = gf256::new(0);
+    for (i, (x0, y0)) in xs.iter().zip(ys).enumerate() {
+        let mut li = gf256::new(1);
+        for (j, (x1, _y1)) in xs.iter().zip(ys).enumerate() {
+            if i != j {
+                li *= (x - x1) / (x0 - x1);
+            }
+        }
+
+        y += li * y0;
+    }
+
+    y
+}
+
+/// Advances `c` to the next combination of indices in `0..n` in lexicographic order, returning
+/// false once all combinations have been visited.
+fn next_combination(c: &mut [usize], n: usize) -> bool {
+    let k = c.len();
+    for i in (0..k).rev() {
+        if c[i] < n - k + i {
+            c[i] += 1;
+            for j in i + 1..k {
+                c[j] = c[j - 1] + 1;
+            }
+            return true;
+        }
+    }
+    false
+}
+
+/// This function seeds a random number generator from the operating system. The polynomials must
+/// be unpredictable: with a fixed seed, anyone could compute them, and a single share would reveal
+/// the secret.
+fn os_seed() -> u64 {
+    let mut seed = [0; 8];
+    getrandom(&mut seed).unwrap();
+    u64::from_ne_bytes(seed)
+}
+
+/// This function generates a polynomial with the given secret, passed as bytes. The shares get
+/// the x coordinates `1..=n`, never 0, where the polynomial is the secret itself.
 pub fn generate(secret: &[u8], n: usize, k: usize) -> Vec<Vec<u8>> {
+    generate_with_rng(secret, n, k, &mut Rand32::new(os_seed()))
+}
+
+/// This function generates shares like `generate`, drawing the random polynomials from `rng`
+/// instead of seeding a generator from the operating system. A fixed seed gives the same shares
+/// every time, which is useful for tests but gives away the secret to anyone who knows the seed.
+pub fn generate_with_rng(secret: &[u8], n: usize, k: usize, rng: &mut Rand32) -> Vec<Vec<u8>> {
     // we only support up to 255 shares
     assert!(
         n <= usize::try_from(255).unwrap_or(usize::MAX),
         "exceeded {} shares",
         255
     );
-    let mut shares = vec![vec![]; n];
-    let mut rng = Rand32::new(0);
+    let indices = (1..=n)
+        .map(|i| u8::try_from(i).unwrap())
+        .collect::<Vec<_>>();
+    generate_at(secret, &indices, k, rng)
+}
 
-    // we need to store the x coord somewhere, so just prepend the share with it
-    for i in 0..n {
-        shares[i].push(u8::try_from(i + 1).unwrap());
+/// This function generates shares like `generate` from a fixed seed, so the same arguments always
+/// give the same shares. This is for reproducible tests only: anyone who knows or guesses the seed
+/// can recompute the polynomials, and recover the secret from a single share. Never use it for
+/// real secrets.
+///
+/// ```
+/// use crypto::shamir::{generate_deterministic, reconstruct};
+///
+/// let a = generate_deterministic(b"test secret", 5, 3, 42);
+/// let b = generate_deterministic(b"test secret", 5, 3, 42);
+/// assert_eq!(a, b);
+/// assert_eq!(reconstruct(&a[..3]), b"test secret");
+/// ```
+pub fn generate_deterministic(secret: &[u8], n: usize, k: usize, seed: u64) -> Vec<Vec<u8>> {
+    generate_with_rng(secret, n, k, &mut Rand32::new(seed))
+}
+
+/// This function generates shares like `generate`, but with the given x coordinates instead of
+/// `1..=n`, so shares can be tied to participant IDs. Since shares are independent points on the
+/// polynomial, holders can be added later without changing the existing shares, as long as they
+/// get new IDs. The IDs must be nonzero, as the share at 0 is the secret itself, and distinct.
+pub fn generate_with_indices(
+    secret: &[u8],
+    indices: &[u8],
+    k: usize,
+) -> Result<Vec<Vec<u8>>, ShamirError> {
+    check_indices(indices)?;
+    Ok(generate_at(secret, indices, k, &mut Rand32::new(os_seed())))
+}
+
+/// Checks that share x coordinates are nonzero and distinct. The polynomial at 0 is the secret,
+/// so a share there would give it away, and two shares with the same x coordinate make
+/// interpolation divide by zero.
+fn check_indices(indices: &[u8]) -> Result<(), ShamirError> {
+    for (i, x) in indices.iter().enumerate() {
+        if *x == 0 {
+            return Err(ShamirError::ZeroIndex);
+        }
+        if indices[..i].contains(x) {
+            return Err(ShamirError::DuplicateIndex(*x));
+        }
     }
+    Ok(())
+}
+
+/// Checks that all shares have the same length, since each byte after the x coordinate is a point
+/// on a different polynomial.
+fn check_lengths<S: AsRef<[u8]>>(shares: &[S]) -> Result<(), ShamirError> {
+    if shares
+        .windows(2)
+        .all(|ss| ss[0].as_ref().len() == ss[1].as_ref().len())
+    {
+        Ok(())
+    } else {
+        Err(ShamirError::MismatchedLength)
+    }
+}
+
+fn generate_at(secret: &[u8], indices: &[u8], k: usize, rng: &mut Rand32) -> Vec<Vec<u8>> {
+    debug_assert_eq!(check_indices(indices), Ok(()));
+
+    // we need to store the x coord somewhere, so just prepend the share with it
+    let mut shares = indices.iter().map(|x| vec![*x]).collect::<Vec<_>>();
 
     for x in secret {
         // generate a random polynomial for each byte
-        let f = poly_random(&mut rng, gf256::new(*x), k - 1);
+        let mut f = poly_random(rng, gf256::new(*x), k - 1);
 
-        // assign each share with a point at f(i)
-        for i in 0..n {
-            shares[i].push(poly_eval(&f, gf256::new(i as u8 + 1)).0);
+        // assign each share with a point at f(x)
+        for share in shares.iter_mut() {
+            let y = poly_eval(&f, gf256::new(share[0]));
+            share.push(y.0);
         }
+        wipe(&mut f, |c| &mut c.0);
     }
 
     shares
@@ -210,7 +411,47 @@ pub fn generate(secret: &[u8], n: usize, k: usize) -> Vec<Vec<u8>> {
 /// Given that this function doesn't know the number of shares required ($k$), it will try to fit a
 /// polynomial in any case, thus providing an incorrect secret if there are fewer than $k$ shares
 /// provided or at least one of the $k$ provided shares is incorrect.
+///
+/// Panics if a share has the x coordinate 0, if two shares have the same x coordinate, or if the
+/// shares have different lengths. A share at x = 0 is the secret in the clear, so it was never
+/// made by `generate`. To reject such shares with an error instead, use `try_reconstruct`.
 pub fn reconstruct<S: AsRef<[u8]>>(shares: &[S]) -> Vec<u8> {
+    try_reconstruct(shares).unwrap_or_else(|e| panic!("{}", e))
+}
+
+// interpolates every byte of the secret, for shares that have already been checked
+fn interpolate<S: AsRef<[u8]>>(shares: &[S]) -> Vec<u8> {
+    let mut secret = vec![];
+    let len = shares.first().map(|s| s.as_ref().len()).unwrap_or(0);
+    if len == 0 {
+        return secret;
+    }
+
+    // x is prepended to each share
+    let xs = shares
+        .iter()
+        .map(|s| gf256::new(s.as_ref()[0]))
+        .collect::<Vec<_>>();
+    for i in 1..len {
+        let ys = shares
+            .iter()
+            .map(|s| gf256::new(s.as_ref()[i]))
+            .collect::<Vec<_>>();
+        secret.push(poly_interpolate(&xs, &ys).0);
+    }
+
+    secret
+}
+
+/// This function recovers the whole polynomial behind each byte of the secret, not just its
+/// y-intercept. For every byte it returns the coefficients, y-intercept first like `poly_eval`
+/// takes them, so the first coefficient of each polynomial is the byte of the secret.
+///
+/// The coefficients are found by solving the Vandermonde system $V c = y$, where row $i$ of $V$
+/// is $[1, x_i, x_i^2, \ldots]$. $V$ only depends on the x coordinates, so it is inverted once.
+/// With $m$ shares there are $m$ coefficients, so if there are more shares than the threshold $k$,
+/// the coefficients past $x^{k-1}$ are 0. Panics if two shares have the same x coordinate.
+pub fn reconstruct_polynomial<S: AsRef<[u8]>>(shares: &[S]) -> Vec<Vec<gf256>> {
     assert!(
         shares
             .windows(2)
@@ -218,10 +459,9 @@ pub fn reconstruct<S: AsRef<[u8]>>(shares: &[S]) -> Vec<u8> {
         "mismatched share length"
     );
 
-    let mut secret = vec![];
     let len = shares.first().map(|s| s.as_ref().len()).unwrap_or(0);
     if len == 0 {
-        return secret;
+        return vec![];
     }
 
     // x is prepended to each share
@@ -229,20 +469,687 @@ pub fn reconstruct<S: AsRef<[u8]>>(shares: &[S]) -> Vec<u8> {
         .iter()
         .map(|s| gf256::new(s.as_ref()[0]))
         .collect::<Vec<_>>();
+    let inverse = GfMatrix::vandermonde(&xs, xs.len())
+        .invert()
+        .expect("duplicate share index");
+
+    (1..len)
+        .map(|i| {
+            let ys = shares
+                .iter()
+                .map(|s| gf256::new(s.as_ref()[i]))
+                .collect::<Vec<_>>();
+            inverse.mul_vec(&ys)
+        })
+        .collect()
+}
+
+/// This function reconstructs a secret like `reconstruct`, but returns an error for shares with
+/// the x coordinate 0, with duplicate x coordinates or with different lengths instead of leaking
+/// the secret or panicking. Use this for shares from untrusted sources.
+pub fn try_reconstruct<S: AsRef<[u8]>>(shares: &[S]) -> Result<Vec<u8>, ShamirError> {
+    check_lengths(shares)?;
+
+    if shares
+        .first()
+        .map(|s| s.as_ref().is_empty())
+        .unwrap_or(true)
+    {
+        return Ok(vec![]);
+    }
+    check_indices(&shares.iter().map(|s| s.as_ref()[0]).collect::<Vec<_>>())?;
+
+    Ok(interpolate(shares))
+}
+
+/// This function reconstructs a secret from at least $k$ shares, interpolating the first $k$ and
+/// checking that the remaining shares lie on the same polynomial. Unlike `reconstruct`, too few
+/// shares or a corrupted share is an error instead of a wrong secret, although a corrupted share
+/// can only be detected if there is at least one share more than $k$. To recover the secret
+/// despite corrupted shares, use `reconstruct_robust`.
+pub fn reconstruct_checked<S: AsRef<[u8]>>(shares: &[S], k: usize) -> Result<Vec<u8>, ShamirError> {
+    check_lengths(shares)?;
+
+    if k == 0 || shares.len() < k {
+        return Err(ShamirError::NotEnoughShares(shares.len()));
+    }
+
+    let len = shares[0].as_ref().len();
+    if len == 0 {
+        return Ok(vec![]);
+    }
+    check_indices(&shares.iter().map(|s| s.as_ref()[0]).collect::<Vec<_>>())?;
+
+    // x is prepended to each share
+    let xs = shares
+        .iter()
+        .map(|s| gf256::new(s.as_ref()[0]))
+        .collect::<Vec<_>>();
+    let (kxs, rest) = xs.split_at(k);
+
+    let mut secret = vec![];
     for i in 1..len {
-        let ys = shares
+        let ys = shares[..k]
             .iter()
             .map(|s| gf256::new(s.as_ref()[i]))
             .collect::<Vec<_>>();
-        secret.push(poly_interpolate(&xs, &ys).0);
+        secret.push(poly_interpolate(kxs, &ys).0);
+
+        for (x, s) in rest.iter().zip(&shares[k..]) {
+            if poly_interpolate_at(kxs, &ys, *x).0 != s.as_ref()[i] {
+                return Err(ShamirError::InconsistentShare(x.0));
+            }
+        }
+    }
+
+    Ok(secret)
+}
+
+/// This function reconstructs a secret from more shares than strictly required, tolerating some
+/// corrupted shares.
+///
+/// Every combination of $k$ shares is interpolated, and the remaining shares are checked against
+/// the resulting polynomial. With $n$ shares, up to $e$ corrupted shares can be tolerated as long
+/// as $n \geq k + 2e$, so a candidate is only accepted if it agrees with at least $(n + k) / 2$ of
+/// the shares. Otherwise no secret can be recovered unambiguously, and `None` is returned.
+///
+/// This tries up to $\binom{n}{k}$ combinations, so it is only practical for a modest number of
+/// shares.
+pub fn reconstruct_robust<S: AsRef<[u8]>>(shares: &[S], k: usize) -> Option<Vec<u8>> {
+    assert!(
+        shares
+            .windows(2)
+            .all(|ss| ss[0].as_ref().len() == ss[1].as_ref().len()),
+        "mismatched share length"
+    );
+
+    let n = shares.len();
+    if k == 0 || n < k {
+        return None;
+    }
+
+    let len = shares[0].as_ref().len();
+    if len == 0 {
+        return Some(vec![]);
+    }
+
+    // x is prepended to each share
+    let xs = shares
+        .iter()
+        .map(|s| gf256::new(s.as_ref()[0]))
+        .collect::<Vec<_>>();
+
+    let mut combination = (0..k).collect::<Vec<_>>();
+    loop {
+        let cxs = combination.iter().map(|&i| xs[i]).collect::<Vec<_>>();
+        let mut agree = vec![true; n];
+        let mut secret = vec![];
+
+        for i in 1..len {
+            let cys = combination
+                .iter()
+                .map(|&j| gf256::new(shares[j].as_ref()[i]))
+                .collect::<Vec<_>>();
+            secret.push(poly_interpolate(&cxs, &cys).0);
+
+            // check which shares lie on the same polynomial
+            for (j, s) in shares.iter().enumerate() {
+                if agree[j] && poly_interpolate_at(&cxs, &cys, xs[j]).0 != s.as_ref()[i] {
+                    agree[j] = false;
+                }
+            }
+        }
+
+        let agreeing = agree.iter().filter(|a| **a).count();
+        if 2 * agreeing >= n + k {
+            return Some(secret);
+        }
+
+        if !next_combination(&mut combination, n) {
+            return None;
+        }
+    }
+}
+
+/// This function computes one old holder's contribution to resharing a secret to a new set of
+/// holders, without ever reconstructing it.
+///
+/// `share` is this holder's share and `xs` are the x coordinates of all $k$ old shares taking
+/// part. Lagrange interpolation writes the secret as $S = \sum_i \lambda_i y_i$, so each old
+/// holder shares their term $\lambda_i y_i$ with a fresh random polynomial of degree
+/// `k - 1`, and returns one sub-share for each of the `n` new holders. Each new holder then sums
+/// the sub-shares they received with `combine_reshared`. The sums are points on the sum of the
+/// old holders' polynomials, which has $S$ as its y-intercept. The new shares are independent of
+/// the old ones, so the old shares can't be mixed with them.
+///
+/// `seed` seeds the random polynomials and must differ between holders.
+///
+/// The trust model is honest-but-curious: every old holder has to follow the protocol and send
+/// each sub-share over a private channel, and nothing checks that a contribution is correct
+/// (see Feldman VSS for that). The old holders must delete their shares afterwards, since any $k$
+/// of them can still recover the secret.
+pub fn reshare_contribution(
+    share: &[u8],
+    xs: &[u8],
+    n: usize,
+    k: usize,
+    seed: u64,
+) -> Vec<Vec<u8>> {
+    assert!(n <= 255, "exceeded {} shares", 255);
+    assert!(!share.is_empty(), "empty share");
+
+    let x0 = gf256::new(share[0]);
+    assert!(xs.contains(&share[0]), "share is not one of the old shares");
+
+    // the lagrange coefficient of this share at x = 0
+    let mut li = gf256::new(1);
+    for x1 in xs.iter().map(|x| gf256::new(*x)) {
+        if x1 != x0 {
+            li *= x1 / (x1 - x0);
+        }
+    }
+
+    let mut rng = Rand32::new(seed);
+    let mut subshares = (1..=n)
+        .map(|i| vec![u8::try_from(i).unwrap()])
+        .collect::<Vec<_>>();
+
+    for y in &share[1..] {
+        let mut f = poly_random(&mut rng, li * gf256::new(*y), k - 1);
+        for subshare in subshares.iter_mut() {
+            let x = gf256::new(subshare[0]);
+            subshare.push(poly_eval(&f, x).0);
+        }
+        wipe(&mut f, |c| &mut c.0);
+    }
+
+    subshares
+}
+
+/// This function combines the sub-shares a new holder received from every old holder during
+/// resharing into their new share.
+pub fn combine_reshared<S: AsRef<[u8]>>(subshares: &[S]) -> Vec<u8> {
+    assert!(
+        subshares
+            .windows(2)
+            .all(|ss| ss[0].as_ref().len() == ss[1].as_ref().len()),
+        "mismatched share length"
+    );
+    assert!(
+        subshares
+            .windows(2)
+            .all(|ss| ss[0].as_ref().first() == ss[1].as_ref().first()),
+        "sub-shares are for different holders"
+    );
+
+    let Some(first) = subshares.first() else {
+        return vec![];
+    };
+    let mut share = first.as_ref().to_vec();
+    for s in &subshares[1..] {
+        for (y, y1) in share.iter_mut().skip(1).zip(&s.as_ref()[1..]) {
+            *y ^= y1;
+        }
+    }
+    share
+}
+
+/// This function re-randomizes shares so they lie on a new polynomial with the same secret,
+/// without reconstructing it. A random polynomial of degree `k - 1` with a y-intercept of 0 is
+/// added to every share, which leaves the y-intercept alone but changes every other point. The
+/// refreshed shares can't be combined with the old ones, so old shares that leaked become useless
+/// once they are deleted.
+///
+/// Every share of the secret has to be refreshed together, since shares that aren't refreshed
+/// stay on the old polynomial.
+pub fn refresh<S: AsRef<[u8]>>(shares: &[S], k: usize) -> Vec<Vec<u8>> {
+    assert!(
+        shares
+            .windows(2)
+            .all(|ss| ss[0].as_ref().len() == ss[1].as_ref().len()),
+        "mismatched share length"
+    );
+
+    let mut rng = Rand32::new(os_seed());
+    let mut refreshed = shares
+        .iter()
+        .map(|s| s.as_ref().to_vec())
+        .collect::<Vec<_>>();
+    let len = refreshed.first().map(|s| s.len()).unwrap_or(0);
+
+    for i in 1..len {
+        let mut f = poly_random(&mut rng, gf256::new(0), k - 1);
+        for share in refreshed.iter_mut() {
+            share[i] ^= poly_eval(&f, gf256::new(share[0])).0;
+        }
+        wipe(&mut f, |c| &mut c.0);
     }
 
+    refreshed
+}
+
+/// This function reshares a secret from $k$ old shares to `n` new shares with a threshold of
+/// `k`, running every holder's side of `reshare_contribution` and `combine_reshared` locally.
+pub fn reshare<S: AsRef<[u8]>>(shares: &[S], n: usize, k: usize) -> Vec<Vec<u8>> {
+    let xs = shares.iter().map(|s| s.as_ref()[0]).collect::<Vec<_>>();
+
+    let contributions = shares
+        .iter()
+        .map(|s| reshare_contribution(s.as_ref(), &xs, n, k, os_seed()))
+        .collect::<Vec<_>>();
+
+    (0..n)
+        .map(|j| {
+            let received = contributions.iter().map(|c| &c[j]).collect::<Vec<_>>();
+            combine_reshared(&received)
+        })
+        .collect()
+}
+
+/// A share together with the threshold needed to reconstruct its secret, so shares can be stored
+/// and sent around without keeping track of how they were made.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Share {
+    index: u8,
+    k: u8,
+    payload: Vec<u8>,
+}
+
+impl Share {
+    /// The version byte at the start of every serialized share.
+    pub const VERSION: u8 = 1;
+
+    /// The x coordinate of this share.
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    /// The number of shares needed to reconstruct the secret.
+    pub fn threshold(&self) -> u8 {
+        self.k
+    }
+
+    /// The y coordinates of this share, one per byte of the secret.
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    /// Serializes the share as the version byte, the index, the threshold, the length of the
+    /// payload as a big-endian `u32`, and the payload.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let len = u32::try_from(self.payload.len()).expect("share too long");
+
+        let mut bytes = vec![Self::VERSION, self.index, self.k];
+        bytes.extend_from_slice(&len.to_be_bytes());
+        bytes.extend_from_slice(&self.payload);
+        bytes
+    }
+
+    /// Parses a share serialized by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ShamirError> {
+        if bytes.len() < 7 {
+            return Err(ShamirError::MalformedShare);
+        }
+        if bytes[0] != Self::VERSION {
+            return Err(ShamirError::UnsupportedVersion(bytes[0]));
+        }
+
+        let len = u32::from_be_bytes(bytes[3..7].try_into().unwrap());
+        let payload = &bytes[7..];
+        if usize::try_from(len).ok() != Some(payload.len()) {
+            return Err(ShamirError::MalformedShare);
+        }
+        if bytes[1] == 0 {
+            return Err(ShamirError::ZeroIndex);
+        }
+
+        Ok(Self {
+            index: bytes[1],
+            k: bytes[2],
+            payload: payload.to_vec(),
+        })
+    }
+}
+
+/// This function splits a secret like `generate`, returning `Share`s that record the threshold.
+pub fn generate_shares(secret: &[u8], n: usize, k: usize) -> Vec<Share> {
+    let k8 = u8::try_from(k).expect("threshold too large");
+
+    generate(secret, n, k)
+        .into_iter()
+        .map(|share| Share {
+            index: share[0],
+            k: k8,
+            payload: share[1..].to_vec(),
+        })
+        .collect()
+}
+
+/// This function reconstructs a secret from `Share`s with `reconstruct_checked`, using the
+/// threshold recorded in the shares. Shares made with different thresholds can't be from the same
+/// split, so they are rejected.
+pub fn reconstruct_shares(shares: &[Share]) -> Result<Vec<u8>, ShamirError> {
+    let Some(first) = shares.first() else {
+        return Err(ShamirError::NotEnoughShares(0));
+    };
+    if shares.iter().any(|s| s.k != first.k) {
+        return Err(ShamirError::MismatchedThreshold);
+    }
+
+    let raw = shares
+        .iter()
+        .map(|s| {
+            let mut share = vec![s.index];
+            share.extend_from_slice(&s.payload);
+            share
+        })
+        .collect::<Vec<_>>();
+    reconstruct_checked(&raw, usize::from(first.k))
+}
+
+/// This function packs the shares of several independently split secrets so that each
+/// participant gets a single blob. `splits` holds the shares of each secret, as returned by
+/// `generate`, and every split must give its shares to the participants in the same order.
+///
+/// Participant $i$'s blob is their x coordinate, followed by their share of each secret in turn:
+/// the length of the share's payload as a big-endian `u32` and the payload. Panics if the splits
+/// have a different number of shares, or if a participant has different x coordinates in
+/// different splits.
+pub fn pack_shares(splits: &[Vec<Vec<u8>>]) -> Vec<Vec<u8>> {
+    let n = splits.first().map(|s| s.len()).unwrap_or(0);
+    assert!(
+        splits.iter().all(|s| s.len() == n),
+        "mismatched number of shares"
+    );
+
+    (0..n)
+        .map(|i| {
+            let x = splits[0][i][0];
+            let mut packed = vec![x];
+            for split in splits {
+                let share = &split[i];
+                assert_eq!(share[0], x, "mismatched share index");
+
+                let len = u32::try_from(share.len() - 1).expect("share too long");
+                packed.extend_from_slice(&len.to_be_bytes());
+                packed.extend_from_slice(&share[1..]);
+            }
+            packed
+        })
+        .collect()
+}
+
+/// This function unpacks a participant's blob from `pack_shares` into their share of each
+/// secret, with the x coordinate prepended like `generate` does.
+pub fn unpack_shares(packed: &[u8]) -> Result<Vec<Vec<u8>>, ShamirError> {
+    let (&x, mut rest) = packed.split_first().ok_or(ShamirError::MalformedShare)?;
+
+    let mut shares = vec![];
+    while !rest.is_empty() {
+        if rest.len() < 4 {
+            return Err(ShamirError::MalformedShare);
+        }
+        let (len, tail) = rest.split_at(4);
+        let len = usize::try_from(u32::from_be_bytes(len.try_into().unwrap()))
+            .map_err(|_| ShamirError::MalformedShare)?;
+        if tail.len() < len {
+            return Err(ShamirError::MalformedShare);
+        }
+
+        let (payload, tail) = tail.split_at(len);
+        let mut share = vec![x];
+        share.extend_from_slice(payload);
+        shares.push(share);
+        rest = tail;
+    }
+
+    Ok(shares)
+}
+
+/// This function splits a secret like `generate`, but over GF(2^16) instead of GF(256), so there
+/// can be up to 65535 shares instead of 255. The secret is packed two bytes per field element,
+/// big-endian, with a zero byte appended if its length is odd.
+///
+/// Each share starts with its x coordinate as two big-endian bytes, followed by a byte that is 1
+/// if the secret was padded and 0 otherwise, and then the y coordinates as two big-endian bytes
+/// each. Reconstruct them with `reconstruct_wide`.
+pub fn generate_wide(secret: &[u8], n: usize, k: usize) -> Vec<Vec<u8>> {
+    assert!(n <= 65535, "exceeded {} shares", 65535);
+    assert!(k >= 1, "threshold must be at least 1");
+
+    let mut rng = Rand32::new(os_seed());
+    let mut shares = (1..=n)
+        .map(|x| {
+            let mut share = u16::try_from(x).unwrap().to_be_bytes().to_vec();
+            share.push(u8::from(secret.len() % 2 == 1));
+            share
+        })
+        .collect::<Vec<_>>();
+
+    for chunk in secret.chunks(2) {
+        let mut f = Vec::with_capacity(k);
+        f.push(gf2p16::new(u16::from_be_bytes([
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+        ])));
+        for _ in 1..k {
+            f.push(gf2p16::new(rng.rand_u32() as u16));
+        }
+
+        for (x, share) in (1..=n).zip(shares.iter_mut()) {
+            let x = gf2p16::new(x as u16);
+            let y = f.iter().rev().fold(gf2p16::new(0), |y, c| y * x + c);
+            share.extend_from_slice(&y.0.to_be_bytes());
+        }
+        wipe(&mut f, |c| &mut c.0);
+    }
+
+    shares
+}
+
+/// This function reconstructs a secret from shares made by `generate_wide`. Like `reconstruct`,
+/// it interpolates every share it is given, so too few shares or a corrupted share give a wrong
+/// secret.
+pub fn reconstruct_wide<S: AsRef<[u8]>>(shares: &[S]) -> Vec<u8> {
+    assert!(
+        shares
+            .windows(2)
+            .all(|ss| ss[0].as_ref().len() == ss[1].as_ref().len()),
+        "mismatched share length"
+    );
+
+    let mut secret = vec![];
+    let Some(first) = shares.first() else {
+        return secret;
+    };
+    let first = first.as_ref();
+    assert!(
+        first.len() >= 3 && first.len() % 2 == 1,
+        "malformed share length"
+    );
+
+    let xs = shares
+        .iter()
+        .map(|s| gf2p16::new(u16::from_be_bytes([s.as_ref()[0], s.as_ref()[1]])))
+        .collect::<Vec<_>>();
+
+    // the lagrange coefficients at x = 0 only depend on the x coordinates, so compute them once
+    let ls = xs
+        .iter()
+        .enumerate()
+        .map(|(i, x0)| {
+            xs.iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .fold(gf2p16::new(1), |li, (_, x1)| li * (x1 / (x1 - x0)))
+        })
+        .collect::<Vec<_>>();
+
+    for i in (3..first.len()).step_by(2) {
+        let y = shares.iter().zip(&ls).fold(gf2p16::new(0), |y, (s, li)| {
+            y + li * gf2p16::new(u16::from_be_bytes([s.as_ref()[i], s.as_ref()[i + 1]]))
+        });
+        secret.extend_from_slice(&y.0.to_be_bytes());
+    }
+
+    if first[2] == 1 {
+        secret.pop();
+    }
     secret
 }
 
+/// The generator used for Feldman commitments. 4 is a square, so for a safe prime $p = 2q + 1$ it
+/// lies in the subgroup of order $q$, and since $q$ is prime and $4 \neq 1$ it generates all of it.
+const FELDMAN_GENERATOR: u64 = 4;
+
+/// A share of a secret in $\mathbb{Z}_q$, dealt by `generate_verifiable`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct VerifiableShare {
+    pub x: u64,
+    pub y: u64,
+}
+
+/// The dealer's public commitments $C_j = g^{a_j} \bmod p$ to the coefficients of the sharing
+/// polynomial, with the safe prime $p$ they were computed with.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Commitments {
+    p: u64,
+    values: Vec<u64>,
+}
+
+impl Commitments {
+    /// The safe prime modulus.
+    pub fn p(&self) -> u64 {
+        self.p
+    }
+
+    /// The commitments, starting with the one to the secret.
+    pub fn values(&self) -> &[u64] {
+        &self.values
+    }
+
+    /// The number of shares needed to reconstruct the secret.
+    pub fn threshold(&self) -> usize {
+        self.values.len()
+    }
+
+    fn q(&self) -> u64 {
+        self.p / 2
+    }
+}
+
+fn mul_mod(a: u64, b: u64, m: u64) -> u64 {
+    (a as u128 * b as u128 % m as u128) as u64
+}
+
+/// This function splits `secret` into `n` shares with a threshold of `k`, like `generate`, and
+/// also returns the commitments that let each holder check their share with `verify_share`.
+///
+/// `p` must be a safe prime $p = 2q + 1$, such as one from
+/// `diffie_hellman::generate_safe_prime`. The secret and the shares are numbers mod $q$, so the
+/// secret must be less than $q$, and there can be fewer than $q$ shares.
+pub fn generate_verifiable(
+    secret: u64,
+    n: usize,
+    k: usize,
+    p: u64,
+) -> (Vec<VerifiableShare>, Commitments) {
+    assert!(
+        p > FELDMAN_GENERATOR && is_prime(p) && is_prime(p / 2),
+        "{} is not a safe prime",
+        p
+    );
+    let q = p / 2;
+    assert!(secret < q, "secret must be less than {}", q);
+    assert!(k >= 1 && k <= n, "threshold must be between 1 and {}", n);
+    assert!((n as u64) < q, "exceeded {} shares", q - 1);
+
+    let mut rng = Rand64::new(u128::from(os_seed()) << 64 | u128::from(os_seed()));
+    let mut f = Vec::with_capacity(k);
+    f.push(secret);
+    for _ in 1..k {
+        f.push(rng.rand_range(0..q));
+    }
+
+    let values = f
+        .iter()
+        .map(|a| modexp_ct(FELDMAN_GENERATOR, *a, p))
+        .collect();
+
+    let shares = (1..=n as u64)
+        .map(|x| VerifiableShare {
+            x,
+            y: f.iter().rev().fold(0, |y, a| (mul_mod(y, x, q) + a) % q),
+        })
+        .collect();
+    wipe(&mut f, |c| c);
+
+    (shares, Commitments { p, values })
+}
+
+/// This function checks that a share lies on the polynomial the dealer committed to, i.e. that
+/// $g^y = \prod_j C_j^{x^j} \bmod p$. Shares that fail were corrupted or dealt dishonestly, and
+/// shouldn't be used for reconstruction.
+pub fn verify_share(share: &VerifiableShare, commitments: &Commitments) -> bool {
+    let (p, q) = (commitments.p, commitments.q());
+    if share.x == 0 || share.x >= q || share.y >= q {
+        return false;
+    }
+
+    let mut expected = 1;
+    let mut x_j = 1;
+    for c in &commitments.values {
+        expected = mul_mod(expected, modexp_ct(*c, x_j, p), p);
+        x_j = mul_mod(x_j, share.x, q);
+    }
+
+    modexp_ct(FELDMAN_GENERATOR, share.y, p) == expected
+}
+
+/// This function reconstructs a secret from verifiable shares, checking every share against the
+/// commitments first. It returns `None` if any share fails verification, if two shares have the
+/// same x coordinate, or if there are fewer shares than the threshold.
+pub fn reconstruct_verifiable(
+    shares: &[VerifiableShare],
+    commitments: &Commitments,
+) -> Option<u64> {
+    let q = commitments.q();
+    let k = commitments.threshold();
+    if shares.len() < k || !shares.iter().all(|s| verify_share(s, commitments)) {
+        return None;
+    }
+
+    let shares = &shares[..k];
+    let mut secret = 0;
+    for (i, s0) in shares.iter().enumerate() {
+        let mut li = 1;
+        for (j, s1) in shares.iter().enumerate() {
+            if i != j {
+                if s0.x == s1.x {
+                    return None;
+                }
+                // x1 / (x1 - x0), dividing by multiplying with the inverse from Fermat's theorem
+                let diff = (s1.x + q - s0.x) % q;
+                li = mul_mod(li, mul_mod(s1.x, modexp_ct(diff, q - 2, q), q), q);
+            }
+        }
+        secret = (secret + mul_mod(li, s0.y, q)) % q;
+    }
+
+    Some(secret)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(feature = "zeroize")]
+    use std::cell::RefCell;
+
+    // the length of every polynomial wiped on this thread, and whether it was all zeros afterwards
+    #[cfg(feature = "zeroize")]
+    thread_local! {
+        pub(super) static WIPED: RefCell<Vec<(usize, bool)>> = const { RefCell::new(vec![]) };
+    }
 
     #[test]
     fn ex() {
@@ -257,4 +1164,363 @@ mod tests {
         assert_eq!(reconstruct(&shares[..4]), b"secret secret secret!");
         assert_eq!(reconstruct(&shares[..5]), b"secret secret secret!");
     }
+
+    #[test]
+    fn fresh_randomness() {
+        let secret = b"secret secret secret!";
+        let a = generate(secret, 5, 3);
+        let b = generate(secret, 5, 3);
+
+        // the same secret gets different polynomials every time
+        assert_ne!(a, b);
+        assert_eq!(reconstruct(&a[..3]), secret);
+        assert_eq!(reconstruct(&b[2..]), secret);
+
+        assert_ne!(reshare(&a[..3], 5, 3), reshare(&a[..3], 5, 3));
+    }
+
+    #[test]
+    fn uniform_coefficients() {
+        // coefficients can take every value, including 0 and 255
+        let mut rng = Rand32::new(42);
+        let mut seen = [false; 256];
+        for _ in 0..64 {
+            for c in &poly_random(&mut rng, gf256::new(0), 64)[1..] {
+                seen[usize::from(c.0)] = true;
+            }
+        }
+        assert!(seen[0] && seen[255]);
+        assert!(seen.iter().all(|s| *s));
+    }
+
+    #[test]
+    fn seeded() {
+        let secret = b"secret secret secret!";
+        let a = generate_with_rng(secret, 5, 3, &mut Rand32::new(42));
+        let b = generate_with_rng(secret, 5, 3, &mut Rand32::new(42));
+        assert_eq!(a, b);
+        assert_eq!(reconstruct(&a[1..4]), secret);
+
+        let c = generate_with_rng(secret, 5, 3, &mut Rand32::new(43));
+        assert_ne!(a, c);
+        assert_eq!(reconstruct(&c[..3]), secret);
+    }
+
+    #[test]
+    fn feldman() {
+        // the largest safe prime below 2^62
+        let p = 0x3fff_ffff_ffff_d6bb;
+        let secret = 0x1234_5678_9abc_def0;
+        let (mut shares, commitments) = generate_verifiable(secret, 5, 3, p);
+        assert_eq!(commitments.threshold(), 3);
+
+        // honest shares pass
+        assert!(shares.iter().all(|s| verify_share(s, &commitments)));
+        assert_eq!(
+            reconstruct_verifiable(&shares[2..], &commitments),
+            Some(secret)
+        );
+        assert_eq!(reconstruct_verifiable(&shares[..2], &commitments), None);
+
+        // a tampered share is caught, and reconstruction refuses to use it
+        shares[1].y ^= 1;
+        assert!(!verify_share(&shares[1], &commitments));
+        assert!(verify_share(&shares[0], &commitments));
+        assert_eq!(reconstruct_verifiable(&shares[..3], &commitments), None);
+
+        // a share moved to another x coordinate doesn't verify either
+        let moved = VerifiableShare {
+            x: shares[0].x + 1,
+            y: shares[0].y,
+        };
+        assert!(!verify_share(&moved, &commitments));
+
+        // a dealer handing out a share off the committed polynomial is caught too
+        let (other, _) = generate_verifiable(secret, 5, 3, p);
+        assert!(!verify_share(&other[0], &commitments));
+    }
+
+    #[test]
+    fn checked() {
+        let secret = b"secret secret secret!";
+        let mut shares = generate(secret, 5, 3);
+
+        assert_eq!(reconstruct_checked(&shares, 3).unwrap(), secret);
+        assert_eq!(reconstruct_checked(&shares[1..4], 3).unwrap(), secret);
+        assert_eq!(
+            reconstruct_checked(&shares[..2], 3),
+            Err(ShamirError::NotEnoughShares(2))
+        );
+
+        // a corrupted share is reported instead of returning the wrong secret
+        shares[3][7] ^= 0x42;
+        assert_eq!(
+            reconstruct_checked(&shares, 3),
+            Err(ShamirError::InconsistentShare(4))
+        );
+    }
+
+    #[test]
+    fn custom_indices() {
+        let secret = b"secret secret secret!";
+        let shares = generate_with_indices(secret, &[3, 17, 42, 200, 255], 3).unwrap();
+        assert_eq!(
+            shares.iter().map(|s| s[0]).collect::<Vec<_>>(),
+            [3, 17, 42, 200, 255]
+        );
+
+        let subset = [&shares[0], &shares[2], &shares[4]];
+        assert_eq!(reconstruct(&subset), secret);
+        assert_eq!(reconstruct_checked(&shares[1..], 3).unwrap(), secret);
+
+        assert_eq!(
+            generate_with_indices(secret, &[1, 0, 2], 2),
+            Err(ShamirError::ZeroIndex)
+        );
+        assert_eq!(
+            generate_with_indices(secret, &[1, 5, 2, 5], 2),
+            Err(ShamirError::DuplicateIndex(5))
+        );
+    }
+
+    #[test]
+    fn wide() {
+        let secret = b"secret secret secret!";
+        let shares = generate_wide(secret, 300, 200);
+        assert_eq!(shares.len(), 300);
+        assert_eq!(&shares[299][..3], [1, 44, 1]);
+
+        assert_eq!(reconstruct_wide(&shares[..200]), secret);
+        assert_eq!(reconstruct_wide(&shares[100..]), secret);
+        assert_ne!(reconstruct_wide(&shares[..199]), secret);
+
+        // even-length secrets aren't padded
+        let shares = generate_wide(b"even", 5, 3);
+        assert_eq!(shares[0].len(), 7);
+        assert_eq!(reconstruct_wide(&shares[2..]), b"even");
+    }
+
+    #[test]
+    fn serialization() {
+        let secret = b"secret secret secret!";
+        let shares = generate_shares(secret, 5, 3);
+
+        let bytes = shares[1].to_bytes();
+        assert_eq!(bytes.len(), 7 + secret.len());
+        assert_eq!(bytes[..7], [Share::VERSION, 2, 3, 0, 0, 0, 21]);
+        assert_eq!(Share::from_bytes(&bytes).unwrap(), shares[1]);
+
+        let parsed = shares
+            .iter()
+            .map(|s| Share::from_bytes(&s.to_bytes()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(reconstruct_shares(&parsed[2..]).unwrap(), secret);
+        assert_eq!(
+            reconstruct_shares(&parsed[..2]),
+            Err(ShamirError::NotEnoughShares(2))
+        );
+
+        // shares from a split with a different threshold are rejected
+        let other = generate_shares(secret, 5, 2);
+        let mixed = [parsed[0].clone(), parsed[1].clone(), other[2].clone()];
+        assert_eq!(
+            reconstruct_shares(&mixed),
+            Err(ShamirError::MismatchedThreshold)
+        );
+
+        assert_eq!(
+            Share::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(ShamirError::MalformedShare)
+        );
+        assert_eq!(
+            Share::from_bytes(&bytes[..5]),
+            Err(ShamirError::MalformedShare)
+        );
+        let mut bad = bytes.clone();
+        bad[0] = 2;
+        assert_eq!(
+            Share::from_bytes(&bad),
+            Err(ShamirError::UnsupportedVersion(2))
+        );
+    }
+
+    #[test]
+    fn refreshing() {
+        let secret = b"secret secret secret!";
+        let shares = generate(secret, 5, 3);
+        let refreshed = refresh(&shares, 3);
+
+        for (old, new) in shares.iter().zip(&refreshed) {
+            assert_eq!(old[0], new[0]);
+            assert_ne!(old, new);
+        }
+        assert_eq!(reconstruct(&refreshed[..3]), secret);
+        assert_eq!(reconstruct(&refreshed[2..]), secret);
+
+        // old and refreshed shares don't mix
+        let mixed = [&shares[0], &shares[1], &refreshed[2]];
+        assert_ne!(reconstruct(&mixed), secret);
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn wipe_coefficients() {
+        let secret = b"secret";
+        let wiped = || WIPED.with(|w| w.take());
+        wiped();
+
+        // every polynomial a dealer uses is wiped, one per byte of the secret
+        let shares = generate(secret, 5, 3);
+        assert_eq!(wiped(), vec![(3, true); secret.len()]);
+
+        refresh(&shares, 3);
+        assert_eq!(wiped(), vec![(3, true); secret.len()]);
+
+        reshare(&shares[..3], 4, 2);
+        assert_eq!(wiped(), vec![(2, true); 3 * secret.len()]);
+
+        generate_wide(secret, 5, 3);
+        assert_eq!(wiped(), vec![(3, true); secret.len() / 2]);
+
+        generate_verifiable(42, 5, 3, 0x3fff_ffff_ffff_d6bb);
+        assert_eq!(wiped(), vec![(3, true)]);
+    }
+
+    #[test]
+    fn polynomial() {
+        let secret = b"secret secret secret!";
+        let shares = generate(secret, 6, 4);
+
+        let polys = reconstruct_polynomial(&shares[1..5]);
+        assert_eq!(polys.len(), secret.len());
+        for (i, (poly, byte)) in polys.iter().zip(secret).enumerate() {
+            // k shares give the k coefficients of a polynomial of degree k - 1
+            assert_eq!(poly.len(), 4);
+            assert_eq!(poly[0], gf256::new(*byte));
+
+            // which goes through every share, including the ones that weren't used
+            for share in &shares {
+                assert_eq!(poly_eval(poly, gf256::new(share[0])).0, share[i + 1]);
+            }
+        }
+
+        // extra shares only add zero coefficients
+        for poly in reconstruct_polynomial(&shares) {
+            assert_eq!(poly.len(), 6);
+            assert_eq!(&poly[4..], [gf256::new(0); 2]);
+        }
+    }
+
+    #[test]
+    fn zero_index() {
+        let secret = b"secret secret secret!";
+        let mut shares = generate(secret, 5, 3);
+        assert!(shares.iter().all(|s| s[0] != 0));
+        assert_eq!(try_reconstruct(&shares[..3]).unwrap(), secret);
+
+        // a share at x = 0 holds the secret itself
+        shares[1][0] = 0;
+        assert_eq!(try_reconstruct(&shares), Err(ShamirError::ZeroIndex));
+        assert_eq!(reconstruct_checked(&shares, 3), Err(ShamirError::ZeroIndex));
+        assert!(std::panic::catch_unwind(|| reconstruct(&shares)).is_err());
+
+        shares[1][0] = 3;
+        assert_eq!(
+            try_reconstruct(&shares),
+            Err(ShamirError::DuplicateIndex(3))
+        );
+    }
+
+    #[test]
+    fn mismatched_length() {
+        let secret = b"secret secret secret!";
+        let mut shares = generate(secret, 5, 3);
+
+        shares[2].pop();
+        assert_eq!(try_reconstruct(&shares), Err(ShamirError::MismatchedLength));
+        assert_eq!(
+            reconstruct_checked(&shares, 3),
+            Err(ShamirError::MismatchedLength)
+        );
+
+        // reconstruct_shares shouldn't panic on shares from the wire either
+        let mut shares = generate_shares(secret, 5, 3);
+        let mut bytes = shares[0].to_bytes();
+        bytes.push(0);
+        bytes[6] += 1;
+        shares[0] = Share::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            reconstruct_shares(&shares),
+            Err(ShamirError::MismatchedLength)
+        );
+    }
+
+    #[test]
+    fn packing() {
+        let blob = (0..4096)
+            .map(|i| (i * 7 + i / 256) as u8)
+            .collect::<Vec<_>>();
+        let secrets: [&[u8]; 3] = [&blob, b"secret secret secret!", b""];
+        let splits = secrets
+            .iter()
+            .map(|s| generate(s, 5, 3))
+            .collect::<Vec<_>>();
+
+        let packed = pack_shares(&splits);
+        assert_eq!(packed.len(), 5);
+        assert_eq!(packed[0].len(), 1 + 3 * 4 + 4096 + 21);
+
+        let unpacked = packed[2..]
+            .iter()
+            .map(|p| unpack_shares(p).unwrap())
+            .collect::<Vec<_>>();
+        for (j, secret) in secrets.iter().enumerate() {
+            let shares = unpacked.iter().map(|u| &u[j]).collect::<Vec<_>>();
+            assert_eq!(reconstruct(&shares), *secret);
+        }
+
+        assert_eq!(
+            unpack_shares(&packed[0][..100]),
+            Err(ShamirError::MalformedShare)
+        );
+        assert_eq!(unpack_shares(&[]), Err(ShamirError::MalformedShare));
+    }
+
+    #[test]
+    fn robust() {
+        let secret = b"secret secret secret!";
+        let mut shares = generate(secret, 6, 4);
+
+        // a single corrupted share breaks plain reconstruction
+        shares[2][5] ^= 0x42;
+        assert_ne!(reconstruct(&shares), secret);
+
+        // but k + 2 shares are enough to find and ignore it
+        assert_eq!(reconstruct_robust(&shares, 4).as_deref(), Some(&secret[..]));
+
+        // with two corrupted shares, the secret can't be recovered unambiguously
+        shares[4][1] ^= 0x42;
+        assert_eq!(reconstruct_robust(&shares, 4), None);
+
+        // too few shares
+        assert_eq!(reconstruct_robust(&shares[..3], 4), None);
+    }
+
+    #[test]
+    fn resharing() {
+        let secret = b"secret secret secret!";
+        let shares = generate(secret, 5, 3);
+
+        // any 3 old shares can reshare to a new set of holders, with a new threshold
+        let new_shares = reshare(&shares[1..4], 7, 4);
+        assert_eq!(new_shares.len(), 7);
+        assert_eq!(reconstruct(&new_shares[..4]), secret);
+        assert_eq!(reconstruct(&new_shares[3..]), secret);
+        assert_ne!(reconstruct(&new_shares[..3]), secret);
+        assert_ne!(new_shares[..5], shares[..]);
+
+        // resharing the new shares again still gives the same secret
+        let newer_shares = reshare(&new_shares[2..6], 4, 2);
+        assert_eq!(reconstruct(&newer_shares[1..3]), secret);
+    }
 }
@@ -0,0 +1,254 @@
+//! Small helpers shared between the other modules.
+
+use crate::md5::MD5;
+use crate::sha1::Sha1;
+use gf256::p128;
+
+/// Computes `sha1(sha1(data))`, as used by some legacy password stores.
+pub fn double_sha1(data: &[u8]) -> [u8; 20] {
+    Sha1::hash(&Sha1::hash(data))
+}
+
+/// Computes `md5(md5(data))`, as used by some legacy password stores.
+pub fn double_md5(data: &[u8]) -> [u8; 16] {
+    MD5::hash(&MD5::hash(data))
+}
+
+/// Hashes the salt prepended to the data with the given hash function, e.g.
+/// `salted_hash(salt, password, MD5::hash)` computes `md5(salt || password)`.
+pub fn salted_hash<const N: usize>(
+    salt: &[u8],
+    data: &[u8],
+    hasher: impl Fn(&[u8]) -> [u8; N],
+) -> [u8; N] {
+    let mut input = salt.to_vec();
+    input.extend_from_slice(data);
+    hasher(&input)
+}
+
+/// Formats bytes as lowercase hex, two digits per byte, in order.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Compares two byte strings in constant time, for checking MACs and authentication tags. Every
+/// byte is compared and the differences are ORed together, so the time taken doesn't reveal how
+/// long a matching prefix a forged tag has. Only the lengths, which are public, can end the
+/// comparison early: slices of different lengths are never equal.
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Looks up `table[index]` in constant time, by reading every entry of the table and keeping the
+/// one at `index` with a mask. A plain lookup loads a different cache line depending on the index,
+/// which can leak secret indices like the AES S-box inputs through cache timing. Indices past the
+/// end of the table return 0.
+pub fn ct_lookup(table: &[u8], index: u8) -> u8 {
+    let mut result = 0;
+    for (i, x) in table.iter().enumerate() {
+        // all ones when i == index, all zeroes otherwise: only zero borrows into the top bit
+        let diff = i ^ usize::from(index);
+        let mask = 0u8.wrapping_sub((diff.wrapping_sub(1) >> (usize::BITS - 1)) as u8);
+        result |= x & mask;
+    }
+    result
+}
+
+/// Multiplies two elements of GF(2^8) in constant time. `polynomial` is the low byte of the
+/// reduction polynomial, `0x1b` for AES's $x^8 + x^4 + x^3 + x + 1$ and `0x1d` for the
+/// $x^8 + x^4 + x^3 + x^2 + 1$ used by the `gf256` crate.
+///
+/// Instead of branching on the bits of `b` or on the carry out of `a`, each bit is turned into an
+/// all-zeros or all-ones mask, so the same instructions run for every input.
+pub const fn ct_gf256_mul(a: u8, b: u8, polynomial: u8) -> u8 {
+    let (mut a, mut b) = (a, b);
+    let mut p = 0;
+
+    let mut i = 0;
+    while i < 8 {
+        p ^= a & 0u8.wrapping_sub(b & 1);
+        let carry = 0u8.wrapping_sub(a >> 7);
+        a = (a << 1) ^ (polynomial & carry);
+        b >>= 1;
+        i += 1;
+    }
+    p
+}
+
+/// Finds the multiplicative inverse of `a` in GF(2^8) in constant time, by computing $a^{254}$
+/// (since $a^{255} = 1$). The square-and-multiply only branches on the bits of the public exponent,
+/// never on `a`. Zero maps to zero, which is what the AES S-box expects.
+pub const fn ct_gf256_inverse(a: u8, polynomial: u8) -> u8 {
+    let mut result = 1;
+
+    let mut i = 8;
+    while i > 0 {
+        i -= 1;
+        result = ct_gf256_mul(result, result, polynomial);
+        if (254 >> i) & 1 == 1 {
+            result = ct_gf256_mul(result, a, polynomial);
+        }
+    }
+    result
+}
+
+/// Returns true if `b` divides `a` as polynomials over GF(2), that is if `a % b` is 0. This is the
+/// check a CRC or Reed-Solomon decoder makes, that a codeword is a multiple of the generator.
+pub fn is_divisible_by(a: p128, b: p128) -> bool {
+    assert!(b.0 != 0, "division by zero polynomial");
+    a % b == p128(0)
+}
+
+/// Returns true if `a` divides `b` as polynomials over GF(2), the same as `is_divisible_by(b, a)`.
+pub fn divides(a: p128, b: p128) -> bool {
+    is_divisible_by(b, a)
+}
+
+/// Divides `a` by `b` as polynomials over GF(2) with schoolbook long division, returning the
+/// partial quotient and remainder after each step. Each step cancels the leading term of the
+/// remainder by subtracting (XORing) a shifted copy of `b`, so the last remainder is `a % b` and
+/// the last quotient is `a / b`. If `a` already has a lower degree than `b` the trace is empty.
+///
+/// This is meant for stepping through the reductions done by CRCs and the other polynomial
+/// division in this crate.
+pub fn long_division_trace(a: p128, b: p128) -> Vec<(p128, p128)> {
+    assert!(b.0 != 0, "division by zero polynomial");
+
+    let b_degree = 127 - b.0.leading_zeros();
+    let mut quotient = 0u128;
+    let mut remainder = a.0;
+    let mut steps = vec![];
+
+    while remainder != 0 && 127 - remainder.leading_zeros() >= b_degree {
+        let shift = 127 - remainder.leading_zeros() - b_degree;
+        quotient ^= 1 << shift;
+        remainder ^= b.0 << shift;
+        steps.push((p128(quotient), p128(remainder)));
+    }
+
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn double_hashes() {
+        assert_eq!(
+            double_sha1(b"abc"),
+            [
+                0x0d, 0x3c, 0xed, 0x9b, // first
+                0xec, 0x10, 0xa7, 0x77, // second
+                0xae, 0xc2, 0x3c, 0xcc, // third
+                0x35, 0x3a, 0x8c, 0x08, // fourth
+                0xa6, 0x33, 0x04, 0x5e, // fifth
+            ]
+        );
+        assert_eq!(
+            double_md5(b"abc"),
+            [
+                0xaf, 0x5d, 0xa9, 0xf4, // first
+                0x5a, 0xf7, 0xa3, 0x00, // second
+                0xe3, 0xad, 0xed, 0x97, // third
+                0x2f, 0x8f, 0xf6, 0x87, // fourth
+            ]
+        );
+    }
+
+    #[test]
+    fn ct_inverse_matches_gf256() {
+        use gf256::gf256;
+
+        assert_eq!(ct_gf256_inverse(0, 0x1d), 0);
+        for i in 1..=255 {
+            assert_eq!(ct_gf256_inverse(i, 0x1d), gf256::new(i).recip().0);
+            assert_eq!(ct_gf256_mul(i, ct_gf256_inverse(i, 0x1d), 0x1d), 1);
+        }
+    }
+
+    #[test]
+    fn constant_time_eq() {
+        assert!(ct_eq(b"", b""));
+        assert!(ct_eq(b"tag", b"tag"));
+        assert!(!ct_eq(b"tag", b"tah"));
+        assert!(!ct_eq(b"tag", b"uag"));
+        assert!(!ct_eq(b"tag", b"ta"));
+        assert!(!ct_eq(b"", b"t"));
+    }
+
+    #[test]
+    fn salted() {
+        assert_eq!(
+            salted_hash(b"salt", b"password", MD5::hash),
+            MD5::hash(b"saltpassword")
+        );
+        assert_eq!(
+            salted_hash(b"salt", b"password", Sha1::hash),
+            [
+                0x59, 0xb3, 0xe8, 0xd6, // first
+                0x37, 0xcf, 0x97, 0xed, // second
+                0xbe, 0x23, 0x84, 0xcf, // third
+                0x59, 0xcb, 0x74, 0x53, // fourth
+                0xdf, 0xe3, 0x07, 0x89, // fifth
+            ]
+        );
+    }
+
+    #[test]
+    fn negation() {
+        use gf256::{p64, p8};
+
+        // addition is XOR, so every polynomial over GF(2) is its own negative
+        assert_eq!(-p8(5), p8(5));
+        assert_eq!(-p64(5), p64(5));
+        assert_eq!(-p128(5), p128(5));
+        assert_eq!(p128(5) + -p128(5), p128(0));
+    }
+
+    #[test]
+    fn divisibility() {
+        // x^2 + 1 = (x + 1)^2, but x^2 + x + 1 is irreducible
+        assert!(is_divisible_by(p128(0b101), p128(0b11)));
+        assert!(!is_divisible_by(p128(0b111), p128(0b11)));
+        assert!(divides(p128(0b11), p128(0b101)));
+        assert!(!divides(p128(0b11), p128(0b111)));
+
+        // a product is divisible by both factors, but not by a factor plus one
+        let a = p128(0x1_0000_0000_0000_001b);
+        let b = p128(0x1021);
+        let product = a.naive_mul(b);
+        assert!(is_divisible_by(product, a));
+        assert!(is_divisible_by(product, b));
+        assert!(!is_divisible_by(p128(product.0 ^ 1), b));
+
+        // everything is divisible by 1, and 0 by everything
+        assert!(is_divisible_by(a, p128(1)));
+        assert!(is_divisible_by(p128(0), a));
+        assert!(!divides(a, b));
+    }
+
+    #[test]
+    fn division_trace() {
+        // (x^6 + x^5 + x^2 + 1) / (x^3 + x + 1) = x^3 + x^2 + x, remainder x^2 + x + 1
+        let a = p128(0b1100101);
+        let b = p128(0b1011);
+        let steps = long_division_trace(a, b);
+        assert_eq!(steps.len(), 3);
+        assert_eq!(*steps.last().unwrap(), (p128(0b1110), p128(0b111)));
+
+        let (quotient, remainder) = *steps.last().unwrap();
+        assert_eq!(remainder, a.naive_rem(b));
+        assert_eq!(p128(quotient.naive_mul(b).0 ^ remainder.0), a);
+
+        // the degree of the remainder drops at every step
+        for w in steps.windows(2) {
+            assert!(w[1].1 .0.leading_zeros() > w[0].1 .0.leading_zeros());
+        }
+
+        assert!(long_division_trace(b, a).is_empty());
+    }
+}